@@ -0,0 +1,189 @@
+//! Encoding data for storage
+//!
+//! `AutoEncoder::encode` always produces JSON, which is easy
+//! to inspect but large, especially for encrypted (binary)
+//! data. A `Codec` chooses between JSON and two binary formats,
+//! CBOR and bincode.
+//!
+//! Binary codecs start their output with a zero byte and a tag
+//! naming the codec. A zero byte never starts a JSON document,
+//! which means that data can always be decoded without knowing
+//! which codec wrote it. Storage backends can switch codecs
+//! and convert their files one at a time.
+//!
+//! ```
+//! # use lockchain_core::codec::{self, Codec};
+//! let data = Codec::Cbor.encode(&vec![1, 2, 3]).unwrap();
+//! assert_eq!(Codec::detect(&data), Some(Codec::Cbor));
+//! assert_eq!(codec::decode::<Vec<u8>>(&data).unwrap(), vec![1, 2, 3]);
+//! ```
+
+use crate::errors::{DataError, Error as LockError, Source};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Marks data written by a binary codec
+const FRAME: u8 = 0;
+
+/// A format to encode data with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Plain JSON, as written by `AutoEncoder::encode`
+    #[default]
+    Json,
+    /// Concise Binary Object Representation (RFC 7049)
+    Cbor,
+    /// A compact encoding, which relies on the exact type to decode
+    Bincode,
+}
+
+impl Codec {
+    /// The tag following the frame marker
+    fn tag(self) -> Option<u8> {
+        match self {
+            Codec::Json => None,
+            Codec::Cbor => Some(b'c'),
+            Codec::Bincode => Some(b'b'),
+        }
+    }
+
+    /// Find out which codec some data was encoded with
+    ///
+    /// Returns `None` for framed data with an unknown tag.
+    pub fn detect(data: &[u8]) -> Option<Codec> {
+        match data {
+            [FRAME, b'c', ..] => Some(Codec::Cbor),
+            [FRAME, b'b', ..] => Some(Codec::Bincode),
+            [FRAME, ..] => None,
+            _ => Some(Codec::Json),
+        }
+    }
+
+    /// Encode a value with this codec
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, LockError> {
+        let mut data = match self.tag() {
+            Some(tag) => vec![FRAME, tag],
+            None => Vec::new(),
+        };
+
+        let result = match self {
+            Codec::Json => serde_json::to_writer(&mut data, value).map_err(|e| e.into()),
+            Codec::Cbor => serde_cbor::to_writer(&mut data, value).map_err(|e| e.into()),
+            Codec::Bincode => bincode::serialize_into(&mut data, value).map_err(|e| e.into()),
+        };
+        result
+            .map(|_| data)
+            .map_err(|e| failure(DataError::FailedEncode, self, e))
+    }
+}
+
+/// Decode a value, written by any codec
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, LockError> {
+    let codec = Codec::detect(data)
+        .ok_or_else(|| LockError::from(DataError::FailedDecode).context("unknown codec"))?;
+    let body = match codec.tag() {
+        Some(_) => &data[2..],
+        None => data,
+    };
+
+    let result = match codec {
+        Codec::Json => serde_json::from_slice(body).map_err(|e| e.into()),
+        Codec::Cbor => serde_cbor::from_slice(body).map_err(|e| e.into()),
+        Codec::Bincode => bincode::deserialize(body).map_err(|e| e.into()),
+    };
+    result.map_err(|e| failure(DataError::FailedDecode, codec, e))
+}
+
+fn failure(error: DataError, codec: Codec, cause: Source) -> LockError {
+    LockError::Context {
+        error: Box::new(error.into()),
+        context: Some(format!("codec '{}'", codec)),
+        source: Some(cause),
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Codec::Json => "json",
+                Codec::Cbor => "cbor",
+                Codec::Bincode => "bincode",
+            }
+        )
+    }
+}
+
+impl FromStr for Codec {
+    type Err = LockError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Codec::Json),
+            "cbor" => Ok(Codec::Cbor),
+            "bincode" => Ok(Codec::Bincode),
+            _ => Err(LockError::from(DataError::FailedDecode).context(format!("codec '{}'", s))),
+        }
+    }
+}
+
+/// Encodes bytes as base64 for human readable formats (like JSON)
+/// and as plain bytes everywhere else
+///
+/// Also accepts lists of numbers, which is how `Vec<u8>` was
+/// encoded before.
+pub(crate) mod bytes {
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(data: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            s.serialize_str(&base64::encode(data))
+        } else {
+            s.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        if d.is_human_readable() {
+            d.deserialize_any(BytesVisitor)
+        } else {
+            d.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a byte array")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            base64::decode(v).map_err(de::Error::custom)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()? {
+                data.push(b);
+            }
+            Ok(data)
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Deserialise any type from a `Payload`
+//!
+//! A payload is it's own deserializer. `DateTime` values are
+//! handed to visitors as RFC 3339 strings and `Secret` values
+//! as plain strings.
+
+use crate::errors::Error;
+use crate::record::Payload;
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, Error as _, IntoDeserializer, Unexpected, VariantAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+impl Payload {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Payload::Text(s) => Unexpected::Str(s),
+            Payload::Boolean(b) => Unexpected::Bool(*b),
+            Payload::Number(n) => Unexpected::Signed(*n),
+            Payload::BTreeMap(_) => Unexpected::Map,
+            Payload::List(_) => Unexpected::Seq,
+            Payload::Bytes(b) => Unexpected::Bytes(b),
+            Payload::Float(f) => Unexpected::Float(*f),
+            Payload::DateTime(_) => Unexpected::Other("date"),
+            Payload::Secret(_) => Unexpected::Other("secret"),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Payload {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Text(s) | Payload::Secret(s) => visitor.visit_string(s),
+            Payload::Boolean(b) => visitor.visit_bool(b),
+            Payload::Number(n) => visitor.visit_i64(n),
+            Payload::Float(f) => visitor.visit_f64(f),
+            Payload::Bytes(b) => visitor.visit_byte_buf(b),
+            Payload::DateTime(d) => visitor.visit_string(d.to_rfc3339()),
+            Payload::List(list) => {
+                let mut seq = SeqDeserializer::new(list.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Payload::BTreeMap(tree) => {
+                let mut map = MapDeserializer::new(tree.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Bytes can also be read as a sequence, i.e. into a `Vec<u8>`
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Bytes(bytes) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(bytes.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Payload::Text(name) => visitor.visit_enum(Variant { name, value: None }),
+            Payload::BTreeMap(tree) if tree.len() == 1 => {
+                let (name, value) = tree.into_iter().next().unwrap();
+                visitor.visit_enum(Variant {
+                    name,
+                    value: Some(value),
+                })
+            }
+            other => Err(Error::invalid_type(other.unexpected(), &"an enum variant")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf unit unit_struct tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Payload {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// An enum variant, with it's content unless it's a unit variant
+struct Variant {
+    name: String,
+    value: Option<Payload>,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = Content;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Content), Error> {
+        let name: StringDeserializer<Error> = self.name.into_deserializer();
+        Ok((seed.deserialize(name)?, Content(self.value)))
+    }
+}
+
+/// The content of an enum variant
+struct Content(Option<Payload>);
+
+impl<'de> VariantAccess<'de> for Content {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            None => Ok(()),
+            Some(p) => Err(Error::invalid_type(p.unexpected(), &"a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.0 {
+            Some(p) => seed.deserialize(p),
+            None => Err(Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Some(p @ Payload::List(_)) => de::Deserializer::deserialize_any(p, visitor),
+            Some(p) => Err(Error::invalid_type(p.unexpected(), &"a tuple variant")),
+            None => Err(Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Some(p @ Payload::BTreeMap(_)) => de::Deserializer::deserialize_any(p, visitor),
+            Some(p) => Err(Error::invalid_type(p.unexpected(), &"a struct variant")),
+            None => Err(Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a struct variant",
+            )),
+        }
+    }
+}
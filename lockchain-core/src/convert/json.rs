@@ -0,0 +1,125 @@
+//! Lossless conversion between `Payload` and `serde_json::Value`
+//!
+//! Payloads that JSON can express natively (text, booleans,
+//! numbers, maps and lists) map onto the equivalent JSON value.
+//! `Bytes`, `DateTime` and `Secret` become an object with a single,
+//! tagged key, so that they survive the round trip:
+//!
+//! ```json
+//! { "$bytes": "a2V5" }
+//! { "$datetime": "2019-03-01T12:00:00+01:00" }
+//! { "$secret": "hunter2" }
+//! ```
+//!
+//! Some JSON values have no payload equivalent: `null`, numbers
+//! that don't fit into an `i64` and non-finite floats. Converting
+//! them fails with `DataError::FailedEncode`/`FailedDecode`.
+
+use crate::errors::{DataError, Error};
+use crate::record::Payload;
+use chrono::{DateTime, Local};
+use serde_json::{Map, Number, Value};
+use std::convert::TryFrom;
+
+const BYTES: &str = "$bytes";
+const DATETIME: &str = "$datetime";
+const SECRET: &str = "$secret";
+
+fn tagged(tag: &str, value: String) -> Value {
+    let mut map = Map::new();
+    map.insert(tag.to_owned(), Value::String(value));
+    Value::Object(map)
+}
+
+impl TryFrom<Payload> for Value {
+    type Error = Error;
+
+    fn try_from(payload: Payload) -> Result<Value, Error> {
+        Ok(match payload {
+            Payload::Text(s) => Value::String(s),
+            Payload::Boolean(b) => Value::Bool(b),
+            Payload::Number(n) => Value::Number(n.into()),
+            Payload::Float(f) => Value::Number(Number::from_f64(f).ok_or_else(|| {
+                Error::from(DataError::FailedEncode).context(format!("float '{}'", f))
+            })?),
+            Payload::BTreeMap(tree) => Value::Object(
+                tree.into_iter()
+                    .map(|(k, v)| Ok((k, Value::try_from(v)?)))
+                    .collect::<Result<_, Error>>()?,
+            ),
+            Payload::List(list) => Value::Array(
+                list.into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Payload::Bytes(b) => tagged(BYTES, base64::encode(&b)),
+            Payload::DateTime(d) => tagged(DATETIME, d.to_rfc3339()),
+            Payload::Secret(s) => tagged(SECRET, s),
+        })
+    }
+}
+
+/// Decode a tagged object, if it is one
+fn untag(map: &Map<String, Value>) -> Option<Result<Payload, Error>> {
+    if map.len() != 1 {
+        return None;
+    }
+
+    let (tag, value) = map.iter().next()?;
+    let value = value.as_str()?;
+    let failed = |e: &dyn ToString| {
+        Error::from(DataError::FailedDecode).context(format!(
+            "{} '{}': {}",
+            tag,
+            value,
+            e.to_string()
+        ))
+    };
+
+    Some(match tag.as_str() {
+        BYTES => base64::decode(value)
+            .map(Payload::Bytes)
+            .map_err(|e| failed(&e)),
+        DATETIME => DateTime::parse_from_rfc3339(value)
+            .map(|d| Payload::DateTime(d.with_timezone(&Local)))
+            .map_err(|e| failed(&e)),
+        SECRET => Ok(Payload::Secret(value.to_owned())),
+        _ => return None,
+    })
+}
+
+impl TryFrom<Value> for Payload {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Payload, Error> {
+        Ok(match value {
+            Value::Null => {
+                return Err(Error::from(DataError::FailedDecode).context("null value"));
+            }
+            Value::Bool(b) => Payload::Boolean(b),
+            Value::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+                (Some(i), _, _) => Payload::Number(i),
+                (None, None, Some(f)) => Payload::Float(f),
+                _ => {
+                    return Err(
+                        Error::from(DataError::FailedDecode).context(format!("number '{}'", n))
+                    );
+                }
+            },
+            Value::String(s) => Payload::Text(s),
+            Value::Array(list) => Payload::List(
+                list.into_iter()
+                    .map(Payload::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(map) => match untag(&map) {
+                Some(payload) => payload?,
+                None => Payload::BTreeMap(
+                    map.into_iter()
+                        .map(|(k, v)| Ok((k, Payload::try_from(v)?)))
+                        .collect::<Result<_, Error>>()?,
+                ),
+            },
+        })
+    }
+}
@@ -0,0 +1,62 @@
+//! Conversion between `Payload` and other types
+//!
+//! `to_payload` turns any `Serialize` type into a payload tree and
+//! `from_payload` turns a payload back into any `DeserializeOwned`
+//! type. This way applications can keep their data in typed structs
+//! and still store it in a record body.
+//!
+//! Types are mapped onto payloads like this:
+//!
+//! - Integers become `Number`, if they fit into an `i64`
+//! - Floats become `Float`, strings and chars become `Text`
+//! - Byte arrays (i.e. via `serde_bytes`) become `Bytes`
+//! - Structs and maps become a `BTreeMap`. Map keys need to be strings
+//! - Sequences and tuples become a `List`
+//! - `None` and `()` are left out of structs and maps. They
+//!   can't be stored anywhere else
+//! - Unit enum variants become `Text`, all other variants a
+//!   `BTreeMap` with the variant name as the only key
+//!
+//! Payloads can also be converted to and from `serde_json::Value`
+//! via `TryFrom`. See the `json` module for details.
+
+mod de;
+mod json;
+mod ser;
+
+pub use self::ser::Serializer;
+
+use crate::errors::{DataError, Error};
+use crate::record::Payload;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Display;
+
+/// Serialise any type into a payload
+///
+/// ```
+/// # use lockchain_core::{convert, Payload};
+/// let payload = convert::to_payload(&vec![1, 2]).unwrap();
+/// assert_eq!(payload, Payload::List(vec![Payload::Number(1), Payload::Number(2)]));
+/// ```
+pub fn to_payload<T: Serialize + ?Sized>(value: &T) -> Result<Payload, Error> {
+    value
+        .serialize(Serializer)?
+        .ok_or_else(|| Error::from(DataError::FailedEncode).context("value is empty"))
+}
+
+/// Deserialise any type from a payload
+pub fn from_payload<T: DeserializeOwned>(payload: Payload) -> Result<T, Error> {
+    T::deserialize(payload)
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::from(DataError::FailedEncode).context(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::from(DataError::FailedDecode).context(msg.to_string())
+    }
+}
@@ -0,0 +1,333 @@
+//! Serialise any type into a `Payload`
+
+use crate::errors::Error;
+use crate::record::Payload;
+use serde::ser::{self, Error as _, Serialize};
+use std::collections::BTreeMap;
+
+/// A serializer that produces payloads
+///
+/// Values without a representation (`None` and `()`) serialise
+/// to `Ok(None)`, so that structs and maps can leave them out.
+pub struct Serializer;
+
+/// Serialise a value that has to be present, i.e. inside a list
+fn required<T: Serialize + ?Sized>(value: &T) -> Result<Payload, Error> {
+    value
+        .serialize(Serializer)?
+        .ok_or_else(|| Error::custom("empty values can only be stored in structs and maps"))
+}
+
+/// Wrap a payload in a map with the variant name as the only key
+fn variant(name: &'static str, value: Payload) -> Option<Payload> {
+    let mut map = BTreeMap::new();
+    map.insert(name.to_owned(), value);
+    Some(Payload::BTreeMap(map))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeVariant<SerializeList>;
+    type SerializeMap = SerializeTree;
+    type SerializeStruct = SerializeTree;
+    type SerializeStructVariant = SerializeVariant<SerializeTree>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Boolean(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Number(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        if v > i64::MAX as u64 {
+            Err(Error::custom(format!("{} doesn't fit into a number", v)))
+        } else {
+            self.serialize_i64(v as i64)
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Text(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Text(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::Bytes(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Ok(variant(name, required(value)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+        Ok(SerializeList {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(SerializeVariant {
+            name,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<SerializeTree, Error> {
+        Ok(SerializeTree {
+            tree: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<SerializeTree, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(SerializeVariant {
+            name,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Collects sequences and tuples into a `Payload::List`
+pub struct SerializeList {
+    items: Vec<Payload>,
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(required(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::List(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Collects maps and structs into a `Payload::BTreeMap`
+pub struct SerializeTree {
+    tree: BTreeMap<String, Payload>,
+    key: Option<String>,
+}
+
+impl SerializeTree {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.tree.insert(key, value);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for SerializeTree {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            Some(Payload::Text(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::custom("map keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::custom("map value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(Payload::BTreeMap(self.tree)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeTree {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps a tuple or struct variant with it's name
+pub struct SerializeVariant<S> {
+    name: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(variant(self.name, Payload::List(self.inner.items)))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeTree> {
+    type Ok = Option<Payload>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.inner.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(variant(self.name, Payload::BTreeMap(self.inner.tree)))
+    }
+}
@@ -1,3 +1,4 @@
+use crate::codec::bytes;
 use crate::traits::AutoEncoder;
 use serde::{Deserialize, Serialize};
 
@@ -6,10 +7,14 @@ use serde::{Deserialize, Serialize};
 /// Includes all cryptographic state primitives that are
 /// required to send the data over a network and decrypt on
 /// the other side of a pipe.
+/// Byte fields are stored as base64 in text formats.
 #[derive(Serialize, Deserialize)]
 pub struct PackedData {
+    #[serde(with = "bytes")]
     pub nonce: Vec<u8>,
+    #[serde(with = "bytes")]
     pub iv: Vec<u8>,
+    #[serde(with = "bytes")]
     pub data: Vec<u8>,
 }
 
@@ -23,3 +23,18 @@ pub fn blake2(data: &str, salt: &str) -> [u8; BLAKE_16_LENGTH] {
 
     return buffer;
 }
+
+/// Compute a blake2 checksum over a blob of data
+///
+/// Not meant for passwords, but to verify the integrity of
+/// stored files.
+pub fn checksum(data: &[u8]) -> [u8; BLAKE_16_LENGTH] {
+    let mut hasher = Blake2s::new(BLAKE_16_LENGTH).expect("Invalid blake2 output length");
+    hasher.process(data);
+
+    let mut buffer = [0u8; BLAKE_16_LENGTH];
+    hasher
+        .variable_result(&mut buffer)
+        .expect("Invalid blake2 output length");
+    buffer
+}
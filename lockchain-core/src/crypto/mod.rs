@@ -18,6 +18,7 @@ mod data;
 pub mod encoding;
 pub mod hashing;
 pub mod random;
+pub mod wipe;
 
 pub use self::data::PackedData;
 pub use self::keys::{Key, KeyType};
@@ -0,0 +1,22 @@
+//! Overwrite secrets in memory before they are dropped
+//!
+//! Writes are volatile, so that the compiler can't optimise
+//! them away, even if the memory is freed right after.
+
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrite a buffer with zeros
+pub fn bytes(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrite a string with zeros, keeping it's length
+///
+/// A string of `\0` characters is still valid UTF-8.
+pub fn string(s: &mut String) {
+    bytes(unsafe { s.as_mut_vec() });
+}
@@ -13,17 +13,32 @@ pub enum Error {
     FailedPAM,
     /// The provided user either doesn't exist or is not authorised
     UserNotAuthorised,
+    /// The token doesn't belong to an active session
+    InvalidToken,
+    /// The user doesn't exist
+    UnknownUser,
+    /// A user with that name already exists
+    UserExists,
+    /// The last administrator of a vault can't be deleted
+    LastAdmin,
 }
 
 impl StdError for Error {}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::Error::*;
         write!(
             f,
             "{}",
             match self {
-                _ => "Unknown failure",
+                FailedFork => "Failed to fork the authentication process",
+                FailedPAM => "Authentication via PAM failed",
+                UserNotAuthorised => "User doesn't exist or isn't authorised",
+                InvalidToken => "Token doesn't belong to an active session",
+                UnknownUser => "No such user",
+                UserExists => "A user with that name already exists",
+                LastAdmin => "The last administrator can't be deleted",
             }
         )
     }
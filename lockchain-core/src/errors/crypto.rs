@@ -18,11 +18,14 @@ impl StdError for Error {}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::Error::*;
         write!(
             f,
             "{}",
             match self {
-                _ => "Unknown failure",
+                InvalidCryptoLayer => "Crypto layer is missing or lacks required features",
+                FailedCrypto => "Failed to initialise the crypto module",
+                FailedKey => "Invalid key or user identity",
             }
         )
     }
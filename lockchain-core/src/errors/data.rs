@@ -4,23 +4,35 @@ use serde::{Deserialize, Serialize};
 use std::error::Error as StdError;
 use std::fmt::{Display, Formatter, Result};
 
+/// Errors that occur while reading, writing or converting data
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Error {
+    /// Data couldn't be read from the backing storage
     FailedRead,
+    /// Data couldn't be written, or a body didn't accept a field
     FailedWrite,
+    /// Data couldn't be serialised
     FailedEncode,
+    /// Data couldn't be deserialised, or is damaged
     FailedDecode,
+    /// A field (or path) that was requested doesn't exist
+    UnknownField,
 }
 
 impl StdError for Error {}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::Error::*;
         write!(
             f,
             "{}",
             match self {
-                _ => "Unknown failure",
+                FailedRead => "Failed to read data",
+                FailedWrite => "Failed to write data",
+                FailedEncode => "Failed to encode data",
+                FailedDecode => "Failed to decode data",
+                UnknownField => "No such field",
             }
         )
     }
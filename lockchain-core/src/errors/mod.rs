@@ -6,6 +6,13 @@
 //!
 //! It also re-exports those error types so that a user can use
 //! the error handling module simply by including `lockchain_core::errors::*`
+//!
+//! Any error can be given context (such as the record, user or path
+//! that was involved) and an underlying cause, which is available
+//! via `source()`. Because the cause usually can't be serialised,
+//! it is dropped when sending an error over the wire, while
+//! the context is kept. To check what went wrong, regardless of
+//! context, match on `Error::kind`.
 
 mod auth;
 mod crypto;
@@ -21,6 +28,9 @@ use serde::{Deserialize, Serialize};
 use std::error::Error as StdError;
 use std::fmt::{Display, Formatter, Result};
 
+/// The underlying cause of an error
+pub type Source = Box<dyn StdError + Send + Sync>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Error {
     /// A common "unknown" type for errors
@@ -36,26 +46,134 @@ pub enum Error {
     Crypto(CryptoError),
     /// Data integrity or retrieval errors
     Data(DataError),
+    /// An error with additional information attached
+    Context {
+        /// The error that occured
+        error: Box<Error>,
+        /// What was involved, for example `record 'github'`
+        context: Option<String>,
+        /// The underlying cause, which is never serialised
+        #[serde(skip)]
+        source: Option<Source>,
+    },
     /// Make sure we don't break user code with new options
     #[doc(hidden)]
     __NonExhaustive,
 }
 
-impl StdError for Error {}
+impl Error {
+    /// Describe what was involved when the error occured
+    ///
+    /// ```
+    /// # use lockchain_core::errors::{Error, VaultError};
+    /// let e = Error::from(VaultError::UnknownRecord).context("record 'github'");
+    /// assert_eq!(e.to_string(), "VaultError: No such record (record 'github')");
+    /// ```
+    pub fn context<S: Into<String>>(self, context: S) -> Self {
+        match self {
+            Error::Context { error, source, .. } => Error::Context {
+                error,
+                context: Some(context.into()),
+                source,
+            },
+            error => Error::Context {
+                error: Box::new(error),
+                context: Some(context.into()),
+                source: None,
+            },
+        }
+    }
+
+    /// Attach the underlying cause of this error
+    pub fn caused_by<E>(self, cause: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        match self {
+            Error::Context { error, context, .. } => Error::Context {
+                error,
+                context,
+                source: Some(Box::new(cause)),
+            },
+            error => Error::Context {
+                error: Box::new(error),
+                context: None,
+                source: Some(Box::new(cause)),
+            },
+        }
+    }
+
+    /// The error itself, without any context
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Context { error, .. } => error.kind(),
+            error => error,
+        }
+    }
+}
+
+/// Attach the name of the record, domain, user or path an error is about
+///
+/// ```
+/// # use lockchain_core::errors::{about, VaultError};
+/// let e = about(VaultError::UnknownRecord, "record", "github");
+/// assert_eq!(e.to_string(), "VaultError: No such record (record 'github')");
+/// ```
+pub fn about<E: Into<Error>, N: Display>(error: E, what: &str, name: N) -> Error {
+    error.into().context(format!("{} '{}'", what, name))
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Context {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
         use self::Error::*;
-        write!(
-            f,
-            "{}",
-            match self {
-                Vault(ee) => format!("VaultError: {}", ee),
-                Auth(ee) => format!("AuthError: {}", ee),
-                Crypto(ee) => format!("CryptoError: {}", ee),
-                Data(ee) => format!("DataError: {}", ee),
-                _ => "Unknown error".into(),
-            }
-        )
+        match self {
+            Vault(ee) => write!(f, "VaultError: {}", ee),
+            Auth(ee) => write!(f, "AuthError: {}", ee),
+            Crypto(ee) => write!(f, "CryptoError: {}", ee),
+            Data(ee) => write!(f, "DataError: {}", ee),
+            Context {
+                error,
+                context: Some(context),
+                ..
+            } => write!(f, "{} ({})", error, context),
+            Context { error, .. } => write!(f, "{}", error),
+            _ => write!(f, "Unknown error"),
+        }
+    }
+}
+
+impl From<VaultError> for Error {
+    fn from(e: VaultError) -> Self {
+        Error::Vault(e)
+    }
+}
+
+impl From<AuthError> for Error {
+    fn from(e: AuthError) -> Self {
+        Error::Auth(e)
+    }
+}
+
+impl From<CryptoError> for Error {
+    fn from(e: CryptoError) -> Self {
+        Error::Crypto(e)
+    }
+}
+
+impl From<DataError> for Error {
+    fn from(e: DataError) -> Self {
+        Error::Data(e)
     }
 }
@@ -26,18 +26,59 @@ pub enum Error {
         /// Optionally the type that is incompatible (if it can be determined)
         tt: Option<String>,
     },
+    /// The vault was written in a format version that the backend can't open
+    IncompatibleVersion {
+        /// The format version recorded in the vault
+        found: String,
+        /// The format version supported by the backend
+        supported: String,
+    },
+    /// The vault is already opened by another handle or process
+    ///
+    /// Only one handle may have a vault open at a time, to make sure
+    /// that writes from different processes can't interleave.
+    Locked,
+    /// The vault was written in an older format version and
+    /// needs to be migrated before it can be opened
+    MigrationRequired {
+        /// The format version recorded in the vault
+        found: String,
+        /// The format version supported by the backend
+        supported: String,
+    },
+    /// Failed to migrate a vault to a newer format version
+    FailedMigration,
+    /// The vault configuration is missing or couldn't be parsed
+    CorruptedConfig,
     /// Vault failed it's checksum self-test
     ///
     /// This is problematic because it also means the vault was unable to correct
     /// any errors. Either the backing storage has some serious issues or maybe
     /// an external sync process that lockchain can't detect is still working.
     FailedSelfTest,
+    /// An operation on the version history of a vault failed
+    FailedVersioning,
     /// Failed to initialise lockchain vault handler
     FailedInitalise,
     /// Failed to create a vault for an unknown reason
     FailedCreation,
     /// Failed to load a vault for an unknown reason
     FailedLoading,
+    /// The requested record doesn't exist in the vault
+    UnknownRecord,
+    /// A record with that name already exists
+    RecordExists,
+    /// The requested metadata domain doesn't exist in the vault
+    UnknownDomain,
+    /// A metadata domain with that name already exists
+    DomainExists,
+    /// Failed to write changes back to the backing storage
+    FailedSync,
+    /// The backing storage was changed by someone else
+    ///
+    /// Changes are only written if the stored data is still the
+    /// version that was read. Reload the vault and try again.
+    Conflict,
     /// Failed to close the vault properly.
     ///
     /// This could be because the backing storage is no longer available
@@ -52,12 +93,40 @@ impl StdError for Error {}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                _ => "Unknown failure",
-            }
-        )
+        use self::Error::*;
+        match self {
+            IncompleteGenerator => write!(f, "Vault generator is missing a name, location or user"),
+            AlreadyExists => write!(f, "A vault already exists at this location"),
+            InvalidPath => write!(f, "Invalid vault path"),
+            InvalidName => write!(f, "Name can't be used by this backend"),
+            InvalidCompoents { tt: Some(tt) } => write!(f, "Incompatible component '{}'", tt),
+            InvalidCompoents { tt: None } => write!(f, "Incompatible combination of components"),
+            IncompatibleVersion { found, supported } => write!(
+                f,
+                "Vault format version {} is incompatible with supported version {}",
+                found, supported
+            ),
+            Locked => write!(f, "Vault is opened by another handle or process"),
+            MigrationRequired { found, supported } => write!(
+                f,
+                "Vault format version {} needs to be migrated to version {}",
+                found, supported
+            ),
+            FailedMigration => write!(f, "Failed to migrate vault"),
+            CorruptedConfig => write!(f, "Vault configuration is missing or corrupted"),
+            FailedSelfTest => write!(f, "Vault failed it's self-test and couldn't be repaired"),
+            FailedVersioning => write!(f, "Failed to access the vault history"),
+            UnknownRecord => write!(f, "No such record"),
+            RecordExists => write!(f, "A record with that name already exists"),
+            UnknownDomain => write!(f, "No such metadata domain"),
+            DomainExists => write!(f, "A metadata domain with that name already exists"),
+            FailedSync => write!(f, "Failed to sync changes to the backing storage"),
+            Conflict => write!(f, "Stored data was changed by someone else"),
+            FailedInitalise => write!(f, "Failed to initialise vault handler"),
+            FailedCreation => write!(f, "Failed to create vault"),
+            FailedLoading => write!(f, "Failed to load vault"),
+            FailedClosing => write!(f, "Failed to close vault"),
+            __NonExhaustive => write!(f, "Unknown failure"),
+        }
     }
 }
@@ -1,4 +1,5 @@
-use crate::errors::VaultError;
+use crate::codec::Codec;
+use crate::errors::Error;
 use crate::traits::{Body, Vault};
 use serde::{Deserialize, Serialize};
 
@@ -51,6 +52,8 @@ pub struct Generator {
     pub location: Option<String>,
     #[doc(hidden)]
     pub user_type: Option<VaultType>,
+    #[doc(hidden)]
+    pub codec: Option<Codec>,
 }
 
 impl Generator {
@@ -60,6 +63,7 @@ impl Generator {
             name: None,
             location: None,
             user_type: None,
+            codec: None,
         }
     }
 
@@ -86,8 +90,18 @@ impl Generator {
         }
     }
 
+    /// Choose how a vault encodes the data it stores
+    ///
+    /// Backends that don't store data ignore this. Defaults to JSON.
+    pub fn codec(self, codec: Codec) -> Self {
+        Self {
+            codec: Some(codec),
+            ..self
+        }
+    }
+
     /// Finally call this function to construct the vault
-    pub fn finalise<V, B>(self) -> Result<V, VaultError>
+    pub fn finalise<V, B>(self) -> Result<V, Error>
     where
         V: Vault<B>,
         B: Body,
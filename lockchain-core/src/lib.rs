@@ -56,24 +56,39 @@
 //!
 //! If we missed something, please let us know!
 
+pub mod codec;
+pub mod convert;
 pub mod crypto;
 pub mod errors;
 mod meta;
+pub mod query;
 mod record;
+pub mod state;
+pub mod store;
 pub mod traits;
 pub mod users;
+mod version;
+
+#[cfg(feature = "testing")]
+pub mod testing;
 
 mod init;
 
+pub use self::codec::Codec;
 pub use self::crypto::PackedData;
 pub use self::init::{Generator, VaultType};
 pub use self::meta::{MetaDomain, VaultMetadata};
-pub use self::record::{EncryptedBody, Header, Payload, Record};
+pub use self::record::{split_path, EncryptedBody, Header, Payload, Record};
+pub use self::version::{Operation, Version};
+pub use uuid::Uuid;
 
 /// Export commonly used types via the prelude
 pub mod prelude {
+    pub use super::codec::Codec;
     pub use super::crypto::PackedData;
     pub use super::init::{Generator, VaultType};
     pub use super::meta::{MetaDomain, VaultMetadata};
     pub use super::record::{EncryptedBody, Header, Payload, Record};
+    pub use super::version::{Operation, Version};
+    pub use uuid::Uuid;
 }
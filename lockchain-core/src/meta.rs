@@ -4,10 +4,10 @@
 //! as the normal Vault Record, without having to rely on encryption
 //! or trait base security.
 
-use crate::record::Payload;
+use crate::record::{split_path, Payload};
 use crate::traits::{AutoEncoder, Body};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// A simple representation of metadata for a vault or vault section
 pub struct VaultMetadata {
@@ -33,7 +33,7 @@ pub struct VaultMetadata {
 ///
 /// It implements a series of traits which means it's possible to easily
 /// interact with to store data.
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct MetaDomain {
     /// The name of this meta domain
     name: String,
@@ -94,6 +94,33 @@ impl Body for MetaDomain {
             .map_or(Some(()), |_| Some(()))
     }
 
+    fn remove_field(&mut self, key: &str) -> Option<Payload> {
+        self.body.remove(key)
+    }
+
+    /// Set a nested value in place, without copying the field
+    fn set_path(&mut self, path: &str, value: Payload) -> Option<()> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.set_field(key, value);
+        }
+
+        self.body
+            .entry(key.into())
+            .or_insert_with(|| Payload::BTreeMap(BTreeMap::new()))
+            .set_path(&rest, value)
+    }
+
+    /// Remove a nested value in place, without copying the field
+    fn remove_path(&mut self, path: &str) -> Option<Payload> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.remove_field(key);
+        }
+
+        self.body.get_mut(key)?.remove_path(&rest)
+    }
+
     /// Not implemented, always returns None
     fn flatten(&mut self) -> Option<()> {
         None
@@ -0,0 +1,294 @@
+//! Search records by their headers
+//!
+//! A `Query` is built up from filters, an optional sort order and
+//! pagination. It only ever looks at `Header`s, which are available
+//! without decrypting anything, so that a server without the
+//! crypto layer can still answer searches.
+//!
+//! ```
+//! # use lockchain_core::query::{Query, SortKey};
+//! # use lockchain_core::Payload;
+//! let query = Query::new()
+//!     .category("web")
+//!     .any_tag(&["work", "dev"])
+//!     .field_range("port", Payload::Number(1)..Payload::Number(1024))
+//!     .sort_by(SortKey::Updated)
+//!     .descending()
+//!     .page(0, 20);
+//! ```
+//!
+//! All filters need to match for a header to be returned. Queries
+//! can be serialised, so that they can be sent to a remote vault.
+
+use crate::record::{Header, Payload};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+/// One end of a range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Limit<T> {
+    Unbounded,
+    Included(T),
+    Excluded(T),
+}
+
+impl<T: Clone> Limit<T> {
+    fn from_bound(bound: Bound<&T>) -> Self {
+        match bound {
+            Bound::Unbounded => Limit::Unbounded,
+            Bound::Included(t) => Limit::Included(t.clone()),
+            Bound::Excluded(t) => Limit::Excluded(t.clone()),
+        }
+    }
+}
+
+/// A range of values, with an optional lower and upper limit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range<T> {
+    pub lower: Limit<T>,
+    pub upper: Limit<T>,
+}
+
+impl<T: Clone> Range<T> {
+    fn new<R: RangeBounds<T>>(range: R) -> Self {
+        Self {
+            lower: Limit::from_bound(range.start_bound()),
+            upper: Limit::from_bound(range.end_bound()),
+        }
+    }
+
+    /// Check if a value is within the range, given a way to compare them
+    fn contains<V, F>(&self, value: &V, cmp: F) -> bool
+    where
+        F: Fn(&V, &T) -> Option<Ordering>,
+    {
+        let lower = match &self.lower {
+            Limit::Unbounded => true,
+            Limit::Included(l) => cmp(value, l).is_some_and(|o| o != Ordering::Less),
+            Limit::Excluded(l) => cmp(value, l) == Some(Ordering::Greater),
+        };
+        let upper = match &self.upper {
+            Limit::Unbounded => true,
+            Limit::Included(u) => cmp(value, u).is_some_and(|o| o != Ordering::Greater),
+            Limit::Excluded(u) => cmp(value, u) == Some(Ordering::Less),
+        };
+        lower && upper
+    }
+}
+
+/// A filter on a single header property
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filter {
+    /// The record is in this category
+    Category(String),
+    /// The record has at least one of these tags
+    AnyTag(Vec<String>),
+    /// The record has all of these tags
+    AllTags(Vec<String>),
+    /// A custom header field has exactly this value
+    FieldEquals(String, Payload),
+    /// A custom header field is within a range
+    FieldRange(String, Range<Payload>),
+    /// The record was created within a range
+    Created(Range<DateTime<Local>>),
+    /// The record was last updated within a range
+    Updated(Range<DateTime<Local>>),
+}
+
+impl Filter {
+    /// Check if a header passes this filter
+    pub fn matches(&self, header: &Header) -> bool {
+        match self {
+            Filter::Category(c) => &header.category == c,
+            Filter::AnyTag(tags) => tags.iter().any(|t| header.tags.contains(t)),
+            Filter::AllTags(tags) => tags.iter().all(|t| header.tags.contains(t)),
+            Filter::FieldEquals(key, value) => header.fields.get(key) == Some(value),
+            Filter::FieldRange(key, range) => header
+                .fields
+                .get(key)
+                .is_some_and(|v| range.contains(v, compare)),
+            Filter::Created(range) => range.contains(&header.date_created, |a, b| a.partial_cmp(b)),
+            Filter::Updated(range) => range.contains(&header.date_updated, |a, b| a.partial_cmp(b)),
+        }
+    }
+}
+
+/// Compare two payloads of the same (or a numeric) type
+///
+/// Numbers and floats compare by value, texts lexically, dates
+/// chronologically. Any other combination can't be compared.
+pub fn compare(a: &Payload, b: &Payload) -> Option<Ordering> {
+    match (a, b) {
+        (Payload::Number(a), Payload::Number(b)) => Some(a.cmp(b)),
+        (Payload::Float(a), Payload::Float(b)) => a.partial_cmp(b),
+        (Payload::Number(a), Payload::Float(b)) => (*a as f64).partial_cmp(b),
+        (Payload::Float(a), Payload::Number(b)) => a.partial_cmp(&(*b as f64)),
+        (Payload::Text(a), Payload::Text(b)) => Some(a.cmp(b)),
+        (Payload::Boolean(a), Payload::Boolean(b)) => Some(a.cmp(b)),
+        (Payload::DateTime(a), Payload::DateTime(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// What to sort query results by
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    Name,
+    Category,
+    Created,
+    Updated,
+    /// A custom header field. Records without it come last
+    Field(String),
+}
+
+impl SortKey {
+    /// Compare two headers, with names breaking ties
+    fn compare(&self, a: &Header, b: &Header, descending: bool) -> Ordering {
+        let ord = match self {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Category => a.category.cmp(&b.category),
+            SortKey::Created => a.date_created.cmp(&b.date_created),
+            SortKey::Updated => a.date_updated.cmp(&b.date_updated),
+            SortKey::Field(key) => match (a.fields.get(key), b.fields.get(key)) {
+                (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+                /* Missing fields come last in either direction */
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+        .then_with(|| a.name.cmp(&b.name));
+
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// A search over record headers
+///
+/// Without any filters, all records match. Results are sorted by
+/// name unless a `SortKey` is given, with the name breaking ties.
+/// Pagination is applied after sorting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+    pub sort: Option<SortKey>,
+    pub descending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Start a new query, which matches all records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an arbitrary filter
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Only match records in a certain category
+    pub fn category(self, category: &str) -> Self {
+        self.filter(Filter::Category(category.into()))
+    }
+
+    /// Only match records with at least one of these tags
+    pub fn any_tag(self, tags: &[&str]) -> Self {
+        self.filter(Filter::AnyTag(tags.iter().map(|&t| t.into()).collect()))
+    }
+
+    /// Only match records with all of these tags
+    pub fn all_tags(self, tags: &[&str]) -> Self {
+        self.filter(Filter::AllTags(tags.iter().map(|&t| t.into()).collect()))
+    }
+
+    /// Only match records where a custom field has a certain value
+    pub fn field_eq(self, key: &str, value: Payload) -> Self {
+        self.filter(Filter::FieldEquals(key.into(), value))
+    }
+
+    /// Only match records where a custom field is within a range
+    ///
+    /// Values that can't be compared to the range (see `compare`)
+    /// never match.
+    pub fn field_range<R: RangeBounds<Payload>>(self, key: &str, range: R) -> Self {
+        self.filter(Filter::FieldRange(key.into(), Range::new(range)))
+    }
+
+    /// Only match records created within a range
+    pub fn created<R: RangeBounds<DateTime<Local>>>(self, range: R) -> Self {
+        self.filter(Filter::Created(Range::new(range)))
+    }
+
+    /// Only match records last updated within a range
+    pub fn updated<R: RangeBounds<DateTime<Local>>>(self, range: R) -> Self {
+        self.filter(Filter::Updated(Range::new(range)))
+    }
+
+    /// Sort results by something other than their name
+    pub fn sort_by(self, key: SortKey) -> Self {
+        Self {
+            sort: Some(key),
+            ..self
+        }
+    }
+
+    /// Reverse the sort order
+    pub fn descending(self) -> Self {
+        Self {
+            descending: true,
+            ..self
+        }
+    }
+
+    /// Skip the first `offset` results
+    pub fn offset(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Return at most `limit` results
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Return a single page of results, counting from `0`
+    pub fn page(self, page: usize, size: usize) -> Self {
+        self.offset(page * size).limit(size)
+    }
+
+    /// Check if a header passes all filters
+    pub fn matches(&self, header: &Header) -> bool {
+        self.filters.iter().all(|f| f.matches(header))
+    }
+
+    /// Run this query over a set of headers
+    ///
+    /// This is meant for `Vault` implementations, which
+    /// can hand in their header index as it is.
+    pub fn run<'a, I>(&self, headers: I) -> Vec<&'a Header>
+    where
+        I: IntoIterator<Item = &'a Header>,
+    {
+        let mut found: Vec<_> = headers.into_iter().filter(|h| self.matches(h)).collect();
+
+        let key = self.sort.as_ref().unwrap_or(&SortKey::Name);
+        found.sort_by(|a, b| key.compare(a, b, self.descending));
+
+        found
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
@@ -9,17 +9,27 @@
 //!
 //! **No secret information should ever be stored in the header**
 
+use crate::codec;
+use crate::convert;
+use crate::crypto::wipe;
+use crate::errors::{DataError, Error as LockError};
 use crate::traits::{AutoEncoder, Body};
 use chrono::{DateTime, Local};
-use serde::{Deserialize, Serialize};
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::BTreeMap;
+use uuid::Uuid;
 
 /// An enum that wraps around all possible data types to store
 /// as the value of a vault record.
 ///
 /// This doesn't include metadata attached to a field, just the
 /// data representation itself (i.e. text, number or sub data-tree)
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+///
+/// Floats are compared by their bit pattern, so that `Payload`
+/// can still be `Eq`. Non-finite floats can't be stored in
+/// JSON encoded records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Payload {
     /// A simple (variable) text
     Text(String),
@@ -31,6 +41,142 @@ pub enum Payload {
     BTreeMap(BTreeMap<String, Payload>),
     /// A list of various payloads
     List(Vec<Payload>),
+    /// Raw binary data, stored as base64 in text formats
+    Bytes(#[serde(with = "codec::bytes")] Vec<u8>),
+    /// A 64bit floating point number
+    Float(f64),
+    /// A point in time, such as an expiry date
+    DateTime(DateTime<Local>),
+    /// Text that clients should mask when displaying it
+    Secret(String),
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Payload) -> bool {
+        use self::Payload::*;
+        match (self, other) {
+            (Text(a), Text(b)) => a == b,
+            (Boolean(a), Boolean(b)) => a == b,
+            (Number(a), Number(b)) => a == b,
+            (BTreeMap(a), BTreeMap(b)) => a == b,
+            (List(a), List(b)) => a == b,
+            (Bytes(a), Bytes(b)) => a == b,
+            (Float(a), Float(b)) => a.to_bits() == b.to_bits(),
+            (DateTime(a), DateTime(b)) => a == b,
+            (Secret(a), Secret(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Payload {}
+
+impl Payload {
+    /// Overwrite all data in this payload with zeros
+    ///
+    /// Texts, secrets and bytes keep their length, but
+    /// their contents are wiped in place.
+    pub fn zeroize(&mut self) {
+        match self {
+            Payload::Text(s) | Payload::Secret(s) => wipe::string(s),
+            Payload::Bytes(b) => wipe::bytes(b),
+            Payload::Number(n) => *n = 0,
+            Payload::Float(f) => *f = 0.0,
+            Payload::Boolean(b) => *b = false,
+            Payload::DateTime(_) => {}
+            Payload::BTreeMap(tree) => tree.values_mut().for_each(Payload::zeroize),
+            Payload::List(list) => list.iter_mut().for_each(Payload::zeroize),
+        }
+    }
+
+    /// Get a nested value, following a path of map keys and list indices
+    ///
+    /// An empty path returns the payload itself.
+    pub fn get_path(&self, path: &[&str]) -> Option<&Payload> {
+        path.iter().try_fold(self, |p, seg| match p {
+            Payload::BTreeMap(map) => map.get(*seg),
+            Payload::List(list) => list.get(seg.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// Get a mutable reference to a nested value
+    pub fn get_path_mut(&mut self, path: &[&str]) -> Option<&mut Payload> {
+        let mut p = self;
+        for seg in path {
+            p = match p {
+                Payload::BTreeMap(map) => map.get_mut(*seg)?,
+                Payload::List(list) => list.get_mut(seg.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(p)
+    }
+
+    /// Set a nested value, creating missing maps along the way
+    ///
+    /// A list index can either address an existing entry, or be one
+    /// past the end to append to the list. Fails if the path runs
+    /// into a value that isn't a map or list.
+    pub fn set_path(&mut self, path: &[&str], value: Payload) -> Option<()> {
+        let (last, parents) = path.split_last()?;
+        let mut p = self;
+        for seg in parents {
+            p = match p {
+                Payload::BTreeMap(map) => map
+                    .entry(seg.to_string())
+                    .or_insert_with(|| Payload::BTreeMap(BTreeMap::new())),
+                Payload::List(list) => {
+                    let i = seg.parse::<usize>().ok()?;
+                    if i == list.len() {
+                        list.push(Payload::BTreeMap(BTreeMap::new()));
+                    }
+                    list.get_mut(i)?
+                }
+                _ => return None,
+            };
+        }
+
+        match p {
+            Payload::BTreeMap(map) => {
+                map.insert(last.to_string(), value);
+            }
+            Payload::List(list) => match last.parse::<usize>().ok()? {
+                i if i < list.len() => list[i] = value,
+                i if i == list.len() => list.push(value),
+                _ => return None,
+            },
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Remove a nested value, returning it
+    pub fn remove_path(&mut self, path: &[&str]) -> Option<Payload> {
+        let (last, parents) = path.split_last()?;
+        match self.get_path_mut(parents)? {
+            Payload::BTreeMap(map) => map.remove(*last),
+            Payload::List(list) => match last.parse::<usize>().ok()? {
+                i if i < list.len() => Some(list.remove(i)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Split a field path into the top-level key and the nested path
+///
+/// Segments are separated by `.`, and none of them can be empty.
+pub fn split_path(path: &str) -> Option<(&str, Vec<&str>)> {
+    let mut segments = path.split('.');
+    let key = segments.next()?;
+    let rest: Vec<_> = segments.collect();
+    if key.is_empty() || rest.iter().any(|s| s.is_empty()) {
+        None
+    } else {
+        Some((key, rest))
+    }
 }
 
 /// The public header of a record
@@ -43,8 +189,17 @@ pub enum Payload {
 /// You should never rely on the presence of custom fields as
 /// older version of the software might not support them or
 /// know about them!
+///
+/// Backends store and address records by their `id`, which never
+/// changes. The `name` is a (unique) display attribute and can be
+/// changed via `Vault::rename_record`.
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Header {
+    /// The immutable id of this record
+    ///
+    /// Records written before ids existed get a random one when decoded
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
     /// The name of this record
     pub name: String,
     /// The primary category the record is in
@@ -78,6 +233,7 @@ impl<T: Body> Record<T> {
     pub fn new(name: &str, category: &str, tags: Vec<&str>) -> Self {
         Record {
             header: Header {
+                id: Uuid::new_v4(),
                 name: name.to_owned(),
                 category: category.to_owned(),
                 tags: tags.into_iter().map(|s| s.to_owned()).collect(),
@@ -90,22 +246,78 @@ impl<T: Body> Record<T> {
     }
 
     /// Attempt to set a key to a certain value
+    ///
+    /// The key can be a path into nested data, such as
+    /// `server.credentials.0.password` (see `Body::set_path`).
+    /// Also bumps the `date_updated` timestamp of the header
     pub fn add_data(&mut self, key: &str, value: Payload) -> Option<()> {
-        (self.body.as_mut()?).set_field(key, value);
+        (self.body.as_mut()?).set_path(key, value)?;
+        self.header.date_updated = Local::now();
         Some(())
     }
 
-    /// Attempt to read out the value of a certain key
+    /// Attempt to read out the value of a certain key or path
     pub fn get_data(&self, key: &str) -> Option<&Payload> {
-        (self.body.as_ref()?).get_field(key)
+        (self.body.as_ref()?).get_path(key)
+    }
+
+    /// Serialise any type into a certain key or path
+    ///
+    /// See the `convert` module for how types are stored
+    pub fn set_typed<S: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &S,
+    ) -> Result<(), LockError> {
+        let payload = convert::to_payload(value)?;
+        self.add_data(key, payload).ok_or_else(|| {
+            LockError::from(DataError::FailedWrite).context(format!("field '{}'", key))
+        })
+    }
+
+    /// Deserialise the value of a certain key or path into any type
+    pub fn get_typed<D: DeserializeOwned>(&self, key: &str) -> Result<D, LockError> {
+        let payload = self.get_data(key).ok_or_else(|| {
+            LockError::from(DataError::UnknownField).context(format!("field '{}'", key))
+        })?;
+        convert::from_payload(payload.clone())
+    }
+
+    /// Attempt to remove a certain key or path, returning it's value
+    ///
+    /// Also bumps the `date_updated` timestamp of the header
+    pub fn remove_data(&mut self, key: &str) -> Option<Payload> {
+        let value = (self.body.as_mut()?).remove_path(key)?;
+        self.header.date_updated = Local::now();
+        Some(value)
     }
 }
 
 impl<T: Body> AutoEncoder for Record<T> {}
 
-#[derive(Serialize, Deserialize)]
+/// A body that was encrypted by an `EncryptionHandler`
+///
+/// The packed data is stored as base64 in text formats, and
+/// as plain bytes everywhere else.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct EncryptedBody {
-    pub data: String,
+    #[serde(serialize_with = "codec::bytes::serialize")]
+    #[serde(deserialize_with = "packed")]
+    pub data: Vec<u8>,
+}
+
+/// Older versions stored packed data as a JSON string, which is kept as it is
+fn packed<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    if d.is_human_readable() {
+        let s = String::deserialize(d)?;
+        if s.starts_with('{') {
+            Ok(s.into_bytes())
+        } else {
+            base64::decode(&s).map_err(de::Error::custom)
+        }
+    } else {
+        codec::bytes::deserialize(d)
+    }
 }
 
 impl Body for EncryptedBody {
@@ -115,6 +327,9 @@ impl Body for EncryptedBody {
     fn set_field(&mut self, _: &str, _: Payload) -> Option<()> {
         None
     }
+    fn remove_field(&mut self, _: &str) -> Option<Payload> {
+        None
+    }
     fn flatten(&mut self) -> Option<()> {
         None
     }
@@ -0,0 +1,39 @@
+//! The stored form of a header index
+//!
+//! Backends that keep one blob per record also keep the headers of
+//! all records in a single index, so that opening a vault doesn't
+//! require reading every record. Each entry remembers the checksum
+//! or revision of the record it was taken from, to notice records
+//! that changed behind the back of the index.
+
+use crate::record::Header;
+use crate::traits::AutoEncoder;
+use serde::{Deserialize, Serialize};
+
+/// The key that a header index is stored under
+pub const INDEX: &str = "headers";
+
+/// The headers of all records of a vault
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HeaderIndex {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl AutoEncoder for HeaderIndex {}
+
+/// A single header, with the checksum or revision of it's record
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub sum: Option<String>,
+    pub header: Header,
+}
+
+/// The part of a stored record that is needed to index it
+///
+/// Decoding a record as a `StoredHeader` skips it's body.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredHeader {
+    pub header: Header,
+}
+
+impl AutoEncoder for StoredHeader {}
@@ -0,0 +1,665 @@
+//! The in-memory state shared by all vault backends
+//!
+//! Backends differ in where they keep records, metadata domains
+//! and users, but not in how they keep them in memory. A
+//! `VaultState` holds the userstore, user sessions, the headers of
+//! all records, pulled records and metadata domains, along with
+//! everything that changed since the last sync.
+//!
+//! It implements all parts of the `Vault` API that don't touch
+//! storage, which leaves loading and writing to the backend.
+//! Changes to a record need it's body, so backends have to pull
+//! a record before changing it.
+
+mod index;
+
+pub use self::index::{HeaderIndex, IndexEntry, StoredHeader, INDEX};
+
+use crate::errors::{about, AuthError, DataError, Error as LockError, VaultError};
+use crate::init::VaultType;
+use crate::meta::MetaDomain;
+use crate::query::Query;
+use crate::record::{Header, Payload, Record};
+use crate::traits::Body;
+use crate::users::{Access, Role, Sessions, Token, UserStore};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// The name of the userstore, next to the metadata domains
+pub const USERS: &str = "userstore";
+
+/// Everything that changed since the last sync
+#[derive(Debug, Default)]
+pub struct Dirty {
+    pub users: bool,
+    /// The header index has to be written, even if no record changed
+    pub index: bool,
+    pub records: HashSet<Uuid>,
+    pub removed: HashSet<Uuid>,
+    pub domains: HashSet<String>,
+}
+
+impl Dirty {
+    /// A record was created or changed
+    pub fn record(&mut self, id: Uuid) {
+        self.removed.remove(&id);
+        self.records.insert(id);
+    }
+
+    /// A record was deleted
+    pub fn remove(&mut self, id: Uuid) {
+        self.records.remove(&id);
+        self.removed.insert(id);
+    }
+
+    /// A metadata domain was created or changed
+    pub fn domain(&mut self, name: &str) {
+        self.domains.insert(name.into());
+    }
+
+    /// Whether records were created, changed or deleted
+    pub fn any_records(&self) -> bool {
+        !self.records.is_empty() || !self.removed.is_empty()
+    }
+}
+
+/// Users, records and metadata domains of a vault, as kept in memory
+pub struct VaultState<T: Body> {
+    /// All users of the vault
+    pub users: UserStore,
+    /// Sessions of authenticated users, never stored
+    sessions: Sessions,
+    /// The headers of all records, pulled or not
+    headers: HashMap<Uuid, Header>,
+    /// The ids of all records, by name
+    names: HashMap<String, Uuid>,
+    /// Records that were pulled
+    records: HashMap<Uuid, Record<T>>,
+    /// All metadata domains, which are always loaded
+    pub metadata: HashMap<String, MetaDomain>,
+    /// Everything that changed since the last sync
+    pub dirty: Dirty,
+}
+
+impl<T: Body> VaultState<T> {
+    /// An empty vault with some users
+    pub fn new(users: UserStore) -> Self {
+        Self {
+            users,
+            sessions: Sessions::new(),
+            headers: HashMap::new(),
+            names: HashMap::new(),
+            records: HashMap::new(),
+            metadata: HashMap::new(),
+            dirty: Dirty::default(),
+        }
+    }
+
+    /// An empty vault with the first user of a vault type
+    ///
+    /// The first user administrates the vault. The userstore
+    /// is marked as changed.
+    pub fn create(vault_type: &VaultType) -> Self {
+        let (username, secret) = match vault_type {
+            VaultType::SoloUser { username, secret } => (username.as_str(), secret),
+            VaultType::Administrated { secret } => ("Admin", secret),
+        };
+        let mut users = UserStore::new();
+        users.add_user(username.into(), secret.clone());
+        users.grant(username, Access::Vault(Role::Admin));
+
+        let mut me = Self::new(users);
+        me.dirty.users = true;
+        me
+    }
+
+    /// Authenticate a user with their root secret
+    pub fn authenticate(&mut self, username: &str, secret: &str) -> Result<Token, LockError> {
+        if self.users.verify(username, secret.as_bytes()) {
+            Ok(self.sessions.open(username))
+        } else {
+            Err(about(AuthError::UserNotAuthorised, "user", username))
+        }
+    }
+
+    pub fn deauthenticate(&mut self, username: &str, token: Token) -> Result<(), LockError> {
+        if self.sessions.close(username, &token) {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidToken.into())
+        }
+    }
+
+    /// Make sure a token belongs to an administrator of the vault
+    fn admin(&self, token: &Token) -> Result<(), LockError> {
+        match self.sessions.verify(token) {
+            Some(user) if self.users.is_admin(user) => Ok(()),
+            Some(user) => Err(about(AuthError::UserNotAuthorised, "user", user)),
+            None => Err(AuthError::InvalidToken.into()),
+        }
+    }
+
+    /// Create a new user, which is stored on the next sync
+    ///
+    /// The secret becomes the root key of the user, every other
+    /// access gets a new key of it's own.
+    pub fn create_user(
+        &mut self,
+        token: Token,
+        username: &str,
+        secret: Vec<u8>,
+        access: Vec<Access>,
+    ) -> Result<(), LockError> {
+        self.admin(&token)?;
+        if self.users.contains(username) {
+            return Err(about(AuthError::UserExists, "user", username));
+        }
+
+        self.users.add_user(username.into(), secret);
+        access
+            .into_iter()
+            .filter(|a| a != &Access::Root)
+            .for_each(|a| self.users.grant(username, a));
+        self.dirty.users = true;
+        Ok(())
+    }
+
+    /// Fails with `AuthError::LastAdmin` for the last administrator
+    pub fn delete_user(&mut self, token: Token, username: &str) -> Result<(), LockError> {
+        self.admin(&token)?;
+        if !self.users.contains(username) {
+            return Err(about(AuthError::UnknownUser, "user", username));
+        }
+        if self.users.is_admin(username) && self.users.admins() == 1 {
+            return Err(about(AuthError::LastAdmin, "user", username));
+        }
+
+        self.users.del_user(username);
+        self.sessions.close_all(username);
+        self.dirty.users = true;
+        Ok(())
+    }
+
+    /// Find the id of a record by it's name or id
+    pub fn resolve(&self, record: &str) -> Option<Uuid> {
+        self.named(record).or_else(|| {
+            Uuid::parse_str(record)
+                .ok()
+                .filter(|id| self.headers.contains_key(id))
+        })
+    }
+
+    /// Find the id of a record by it's name only
+    pub fn named(&self, name: &str) -> Option<Uuid> {
+        self.names.get(name).cloned()
+    }
+
+    /// Find the id of a record, failing with `VaultError::UnknownRecord`
+    pub fn find(&self, record: &str) -> Result<Uuid, LockError> {
+        self.resolve(record)
+            .ok_or_else(|| about(VaultError::UnknownRecord, "record", record))
+    }
+
+    /// The number of records, pulled or not
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn header(&self, id: &Uuid) -> Option<&Header> {
+        self.headers.get(id)
+    }
+
+    /// The headers of all records, pulled or not
+    pub fn headers(&self) -> impl Iterator<Item = &Header> {
+        self.headers.values()
+    }
+
+    pub fn query(&self, query: &Query) -> Vec<&Header> {
+        query.run(self.headers.values())
+    }
+
+    /// Whether the body of a record is loaded
+    pub fn is_pulled(&self, id: &Uuid) -> bool {
+        self.records.contains_key(id)
+    }
+
+    /// Pulled records, by id
+    pub fn record(&self, id: &Uuid) -> Option<&Record<T>> {
+        self.records.get(id)
+    }
+
+    /// All pulled records
+    pub fn records(&self) -> impl Iterator<Item = &Record<T>> {
+        self.records.values()
+    }
+
+    /// Set the header of a record that was read from storage
+    pub fn insert_header(&mut self, header: Header) {
+        if let Some(old) = self.headers.get(&header.id) {
+            if self.names.get(&old.name) == Some(&old.id) {
+                self.names.remove(&old.name);
+            }
+        }
+        self.names.insert(header.name.clone(), header.id);
+        self.headers.insert(header.id, header);
+    }
+
+    /// Take over a record that was read from storage
+    pub fn insert(&mut self, record: Record<T>) {
+        let id = record.header.id;
+        self.evict(&id);
+        self.insert_header(record.header.clone());
+        self.records.insert(id, record);
+    }
+
+    /// Forget a record that was removed from storage
+    pub fn forget(&mut self, id: &Uuid) -> Option<Header> {
+        self.evict(id);
+        let header = self.headers.remove(id)?;
+        if self.names.get(&header.name) == Some(id) {
+            self.names.remove(&header.name);
+        }
+        Some(header)
+    }
+
+    /// Forget all records, i.e. before reading them from storage again
+    pub fn clear(&mut self) {
+        self.evict_all();
+        self.headers.clear();
+        self.names.clear();
+    }
+
+    /// Drop a loaded body, zeroizing it first
+    pub fn evict(&mut self, id: &Uuid) {
+        if let Some(mut body) = self.records.remove(id).and_then(|r| r.body) {
+            body.zeroize();
+        }
+    }
+
+    /// Drop all loaded bodies, zeroizing them first
+    pub fn evict_all(&mut self) {
+        for (_, rec) in self.records.drain() {
+            if let Some(mut body) = rec.body {
+                body.zeroize();
+            }
+        }
+    }
+
+    /// Forget all unsynced changes to records and domains
+    ///
+    /// Changes to users are kept.
+    pub fn reset(&mut self) {
+        self.dirty = Dirty {
+            users: self.dirty.users,
+            ..Dirty::default()
+        };
+    }
+
+    /// A pulled record, by name or id
+    pub fn get_record(&self, name: &str) -> Option<&Record<T>> {
+        self.records.get(&self.resolve(name)?)
+    }
+
+    /// Fails with `VaultError::InvalidName` for empty names and
+    /// `VaultError::RecordExists` if the name is taken
+    ///
+    /// Returns the id of the new record.
+    pub fn add_record(
+        &mut self,
+        key: &str,
+        category: &str,
+        tags: Vec<&str>,
+    ) -> Result<Uuid, LockError> {
+        if key.is_empty() {
+            return Err(about(VaultError::InvalidName, "record", key));
+        }
+
+        if self.names.contains_key(key) {
+            return Err(about(VaultError::RecordExists, "record", key));
+        }
+
+        let mut record = Record::new(key, category, tags);
+        record.body = Some(T::default());
+
+        let id = record.header.id;
+        self.insert_header(record.header.clone());
+        self.records.insert(id, record);
+        self.dirty.record(id);
+        Ok(id)
+    }
+
+    /// Returns the removed record, without body if it was never pulled
+    pub fn delete_record(&mut self, record: &str) -> Result<Record<T>, LockError> {
+        let id = self.find(record)?;
+        let header = self.headers.remove(&id).unwrap();
+        if self.names.get(&header.name) == Some(&id) {
+            self.names.remove(&header.name);
+        }
+        self.dirty.remove(id);
+        Ok(self
+            .records
+            .remove(&id)
+            .unwrap_or(Record { header, body: None }))
+    }
+
+    /// A pulled record, to change it
+    fn pulled(&mut self, record: &str) -> Result<&mut Record<T>, LockError> {
+        let id = self.find(record)?;
+        self.records
+            .get_mut(&id)
+            .ok_or_else(|| about(DataError::FailedRead, "record", record))
+    }
+
+    /// Fails with `VaultError::RecordExists` if the name is taken
+    pub fn rename_record(&mut self, record: &str, name: &str) -> Result<(), LockError> {
+        let id = self.find(record)?;
+        if name.is_empty() {
+            return Err(about(VaultError::InvalidName, "record", name));
+        }
+        if self.named(name).is_some_and(|other| other != id) {
+            return Err(about(VaultError::RecordExists, "record", name));
+        }
+
+        let rec = self.pulled(record)?;
+        rec.header.name = name.to_owned();
+        let header = rec.header.clone();
+        self.insert_header(header);
+        self.dirty.record(id);
+        Ok(())
+    }
+
+    pub fn add_data(&mut self, record: &str, key: &str, data: Payload) -> Result<(), LockError> {
+        let rec = self.pulled(record)?;
+        rec.add_data(key, data)
+            .ok_or_else(|| about(DataError::FailedWrite, "record", record))?;
+        let header = rec.header.clone();
+        self.dirty.record(header.id);
+        self.headers.insert(header.id, header);
+        Ok(())
+    }
+
+    pub fn get_data(&self, record: &str, key: &str) -> Option<&Payload> {
+        self.get_record(record)?.get_data(key)
+    }
+
+    pub fn remove_data(&mut self, record: &str, key: &str) -> Result<Payload, LockError> {
+        let rec = self.pulled(record)?;
+        let value = rec
+            .remove_data(key)
+            .ok_or_else(|| about(DataError::UnknownField, "field", key))?;
+        let header = rec.header.clone();
+        self.dirty.record(header.id);
+        self.headers.insert(header.id, header);
+        Ok(value)
+    }
+
+    /// Fails with `VaultError::InvalidName` for empty names and `userstore`
+    pub fn meta_add_domain(&mut self, domain: &str) -> Result<(), LockError> {
+        if domain.is_empty() || domain == USERS {
+            Err(about(VaultError::InvalidName, "domain", domain))
+        } else if self.metadata.contains_key(domain) {
+            Err(about(VaultError::DomainExists, "domain", domain))
+        } else {
+            self.metadata.insert(domain.into(), MetaDomain::new(domain));
+            self.dirty.domain(domain);
+            Ok(())
+        }
+    }
+
+    pub fn meta_pull_domain(&self, domain: &str) -> Option<&MetaDomain> {
+        self.metadata.get(domain)
+    }
+
+    /// Fails with `VaultError::InvalidName` for empty names and `userstore`
+    pub fn meta_push_domain(&mut self, domain: MetaDomain) -> Result<(), LockError> {
+        if domain.name().is_empty() || domain.name() == USERS {
+            return Err(about(VaultError::InvalidName, "domain", domain.name()));
+        }
+
+        self.dirty.domain(domain.name());
+        self.metadata.insert(domain.name().into(), domain);
+        Ok(())
+    }
+
+    pub fn meta_set(&mut self, domain: &str, name: &str, data: Payload) -> Result<(), LockError> {
+        self.metadata
+            .get_mut(domain)
+            .ok_or_else(|| about(VaultError::UnknownDomain, "domain", domain))?
+            .set_path(name, data)
+            .ok_or_else(|| about(DataError::FailedWrite, "domain", domain))?;
+        self.dirty.domain(domain);
+        Ok(())
+    }
+
+    pub fn meta_get(&self, domain: &str, name: &str) -> Option<Payload> {
+        Some(self.metadata.get(domain)?.get_path(name)?.clone())
+    }
+
+    pub fn meta_remove(&mut self, domain: &str, name: &str) -> Result<Payload, LockError> {
+        let value = self
+            .metadata
+            .get_mut(domain)
+            .ok_or_else(|| about(VaultError::UnknownDomain, "domain", domain))?
+            .remove_path(name)
+            .ok_or_else(|| about(DataError::UnknownField, "field", name))?;
+        self.dirty.domain(domain);
+        Ok(value)
+    }
+
+    pub fn meta_exists(&self, domain: &str) -> bool {
+        self.metadata.contains_key(domain)
+    }
+}
+
+impl<T: Body> Drop for VaultState<T> {
+    /// Zeroizes all loaded bodies
+    fn drop(&mut self) {
+        self.evict_all();
+    }
+}
+
+/// Implement the `Vault` functions that only need a `VaultState`
+///
+/// Used inside of an `impl Vault<T>` block, with the field that
+/// holds the state and the functions to forward to it. Functions
+/// listed after `pulled` call the vault's own `pulled(&mut self,
+/// record: &str)` first, so that the record body is loaded.
+/// The body type of the vault has to be called `T`.
+///
+/// ```ignore
+/// impl<T: Body> Vault<T> for MyVault<T> {
+///     forward_to_state! {
+///         state: authenticate, deauthenticate, get_record, query;
+///         pulled: rename_record, add_data, remove_data
+///     }
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! forward_to_state {
+    ($state:ident: $($fun:ident),* $(,)*) => {
+        $( $crate::forward_to_state!(@fn $state, $fun); )*
+    };
+    ($state:ident: $($fun:ident),* ; pulled: $($pulled:ident),* $(,)*) => {
+        $( $crate::forward_to_state!(@fn $state, $fun); )*
+        $( $crate::forward_to_state!(@pulled $state, $pulled); )*
+    };
+    (@pulled $state:ident, rename_record) => {
+        fn rename_record(&mut self, record: &str, name: &str) -> Result<(), $crate::errors::Error> {
+            self.pulled(record)?;
+            self.$state.rename_record(record, name)
+        }
+    };
+    (@pulled $state:ident, add_data) => {
+        fn add_data(
+            &mut self,
+            record: &str,
+            key: &str,
+            data: $crate::Payload,
+        ) -> Result<(), $crate::errors::Error> {
+            self.pulled(record)?;
+            self.$state.add_data(record, key, data)
+        }
+    };
+    (@pulled $state:ident, remove_data) => {
+        fn remove_data(
+            &mut self,
+            record: &str,
+            key: &str,
+        ) -> Result<$crate::Payload, $crate::errors::Error> {
+            self.pulled(record)?;
+            self.$state.remove_data(record, key)
+        }
+    };
+    (@fn $state:ident, authenticate) => {
+        fn authenticate(
+            &mut self,
+            username: &str,
+            secret: &str,
+        ) -> Result<$crate::users::Token, $crate::errors::Error> {
+            self.$state.authenticate(username, secret)
+        }
+    };
+    (@fn $state:ident, deauthenticate) => {
+        fn deauthenticate(
+            &mut self,
+            username: &str,
+            token: $crate::users::Token,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.deauthenticate(username, token)
+        }
+    };
+    (@fn $state:ident, create_user) => {
+        fn create_user(
+            &mut self,
+            token: $crate::users::Token,
+            username: &str,
+            secret: Vec<u8>,
+            access: Vec<$crate::users::Access>,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.create_user(token, username, secret, access)
+        }
+    };
+    (@fn $state:ident, delete_user) => {
+        fn delete_user(
+            &mut self,
+            token: $crate::users::Token,
+            username: &str,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.delete_user(token, username)
+        }
+    };
+    (@fn $state:ident, get_record) => {
+        fn get_record(&self, name: &str) -> Option<&$crate::Record<T>> {
+            self.$state.get_record(name)
+        }
+    };
+    (@fn $state:ident, contains) => {
+        fn contains(&self, name: &str) -> bool {
+            self.$state.resolve(name).is_some()
+        }
+    };
+    (@fn $state:ident, query) => {
+        fn query(&self, query: &$crate::query::Query) -> Vec<&$crate::Header> {
+            self.$state.query(query)
+        }
+    };
+    (@fn $state:ident, add_record) => {
+        fn add_record(
+            &mut self,
+            key: &str,
+            category: &str,
+            tags: Vec<&str>,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.add_record(key, category, tags).map(|_| ())
+        }
+    };
+    (@fn $state:ident, delete_record) => {
+        fn delete_record(
+            &mut self,
+            record: &str,
+        ) -> Result<$crate::Record<T>, $crate::errors::Error> {
+            self.$state.delete_record(record)
+        }
+    };
+    (@fn $state:ident, rename_record) => {
+        fn rename_record(&mut self, record: &str, name: &str) -> Result<(), $crate::errors::Error> {
+            self.$state.rename_record(record, name)
+        }
+    };
+    (@fn $state:ident, add_data) => {
+        fn add_data(
+            &mut self,
+            record: &str,
+            key: &str,
+            data: $crate::Payload,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.add_data(record, key, data)
+        }
+    };
+    (@fn $state:ident, get_data) => {
+        fn get_data(&self, record: &str, key: &str) -> Option<&$crate::Payload> {
+            self.$state.get_data(record, key)
+        }
+    };
+    (@fn $state:ident, remove_data) => {
+        fn remove_data(
+            &mut self,
+            record: &str,
+            key: &str,
+        ) -> Result<$crate::Payload, $crate::errors::Error> {
+            self.$state.remove_data(record, key)
+        }
+    };
+    (@fn $state:ident, meta_add_domain) => {
+        fn meta_add_domain(&mut self, domain: &str) -> Result<(), $crate::errors::Error> {
+            self.$state.meta_add_domain(domain)
+        }
+    };
+    (@fn $state:ident, meta_pull_domain) => {
+        fn meta_pull_domain(&self, domain: &str) -> Option<&$crate::MetaDomain> {
+            self.$state.meta_pull_domain(domain)
+        }
+    };
+    (@fn $state:ident, meta_push_domain) => {
+        fn meta_push_domain(
+            &mut self,
+            domain: $crate::MetaDomain,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.meta_push_domain(domain)
+        }
+    };
+    (@fn $state:ident, meta_set) => {
+        fn meta_set(
+            &mut self,
+            domain: &str,
+            name: &str,
+            data: $crate::Payload,
+        ) -> Result<(), $crate::errors::Error> {
+            self.$state.meta_set(domain, name, data)
+        }
+    };
+    (@fn $state:ident, meta_get) => {
+        fn meta_get(&mut self, domain: &str, name: &str) -> Option<$crate::Payload> {
+            self.$state.meta_get(domain, name)
+        }
+    };
+    (@fn $state:ident, meta_remove) => {
+        fn meta_remove(
+            &mut self,
+            domain: &str,
+            name: &str,
+        ) -> Result<$crate::Payload, $crate::errors::Error> {
+            self.$state.meta_remove(domain, name)
+        }
+    };
+    (@fn $state:ident, meta_exists) => {
+        fn meta_exists(&self, domain: &str) -> bool {
+            self.$state.meta_exists(domain)
+        }
+    };
+}
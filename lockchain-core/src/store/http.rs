@@ -0,0 +1,37 @@
+//! Shared pieces of stores that talk HTTP
+//!
+//! Requires the `http` feature.
+
+use crate::errors::{DataError, Error as LockError};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use ureq::Response;
+
+/// Everything but the unreserved characters of RFC 3986
+pub const RESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The outcome of a request that didn't fail in transport
+pub enum Reply {
+    Ok(Box<Response>),
+    Status(u16),
+}
+
+impl Reply {
+    /// Sort the result of a request, failing only for transport errors
+    pub fn new(
+        res: Result<Response, ureq::Error>,
+        method: &str,
+        url: &str,
+    ) -> Result<Self, LockError> {
+        match res {
+            Ok(res) => Ok(Reply::Ok(Box::new(res))),
+            Err(ureq::Error::Status(code, _)) => Ok(Reply::Status(code)),
+            Err(e) => Err(LockError::from(DataError::FailedRead)
+                .context(format!("{} {}", method, url))
+                .caused_by(e)),
+        }
+    }
+}
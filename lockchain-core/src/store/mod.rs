@@ -0,0 +1,167 @@
+//! Low-level storage for vaults
+//!
+//! A `BlobStore` only knows how to get, put, list and delete
+//! opaque blobs of data. It has no idea what a record, user or
+//! metadata domain is. `StoreVault` implements the `Vault` API
+//! on top of any such store, so that a new backend only needs
+//! to implement the storage part.
+//!
+//! Blobs are sorted into a few `Space`s and named by a key:
+//!
+//! - `Records` holds one blob per record, named after it's id
+//! - `Metadata` holds one blob per metadata domain, plus the
+//!   `userstore`
+//! - `Index` holds the header index, named `headers`
+//!
+//! Every stored blob has a `Revision`, which changes whenever it is
+//! written. Writes can be made conditional on the revision that was
+//! read (like `If-Match` in HTTP), which is how concurrent writers
+//! are detected. Stores report a failed `Condition` with
+//! `VaultError::Conflict`.
+
+mod vault;
+
+#[cfg(feature = "http")]
+pub mod http;
+
+pub use self::vault::StoreVault;
+
+use crate::errors::{Error as LockError, VaultError};
+use crate::init::Generator;
+
+/// A group of blobs in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Space {
+    /// Records, named after their id
+    Records,
+    /// Metadata domains and the userstore, named after the domain
+    Metadata,
+    /// The header index
+    Index,
+}
+
+/// An opaque version of a stored blob, such as a checksum or an ETag
+pub type Revision = String;
+
+/// A blob, as it was read from a store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+    pub revision: Revision,
+}
+
+/// When a blob may be written or deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Always, no matter what is stored
+    Any,
+    /// Only if the blob doesn't exist yet
+    Absent,
+    /// Only if the blob still has this revision
+    Matches(Revision),
+}
+
+impl Condition {
+    /// Check the condition against the revision of a blob, if it exists
+    pub fn holds(&self, current: Option<&Revision>) -> bool {
+        match self {
+            Condition::Any => true,
+            Condition::Absent => current.is_none(),
+            Condition::Matches(rev) => current == Some(rev),
+        }
+    }
+
+    /// The error to return if the condition doesn't hold
+    pub fn failed(space: Space, key: &str) -> LockError {
+        LockError::from(VaultError::Conflict).context(format!("{:?} '{}'", space, key))
+    }
+}
+
+/// Check if a key can be stored by every store
+///
+/// Keys become file or object names, so they can't be empty or
+/// contain path separators. Hidden names are reserved for
+/// temporary files.
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.starts_with('.') && !key.contains(['/', '\\', '\0'])
+}
+
+/// A single write or delete, as part of a `BlobStore::commit`
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub space: Space,
+    pub key: String,
+    /// The new contents, or `None` to delete the blob
+    pub data: Option<Vec<u8>>,
+    pub condition: Condition,
+}
+
+/// A `BlobStore::commit` that stopped with an error
+///
+/// Changes that were applied before the error keep their new
+/// revisions, so that the caller knows what is stored now.
+#[derive(Debug)]
+pub struct Partial {
+    /// The revisions of the changes that were applied, in order
+    pub applied: Vec<Option<Revision>>,
+    /// Why the commit stopped
+    pub error: LockError,
+}
+
+impl From<Partial> for LockError {
+    fn from(p: Partial) -> Self {
+        p.error
+    }
+}
+
+/// A storage backend for opaque blobs
+///
+/// Missing blobs are not an error: `get` returns `None` and
+/// `delete` does nothing (if the condition allows it).
+pub trait BlobStore: Send {
+    /// Read a blob along with it's current revision
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError>;
+    /// Write a blob if the condition holds, and return it's new revision
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError>;
+    /// Delete a blob if the condition holds
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError>;
+    /// The keys and revisions of all blobs in a space
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError>;
+
+    /// Apply several changes, returning the new revision of each written blob
+    ///
+    /// By default, changes are applied one after the other and the
+    /// first failure stops the rest, reporting the changes that were
+    /// applied until then. Stores that support transactions should
+    /// apply either all changes or none.
+    fn commit(&mut self, changes: Vec<Change>) -> Result<Vec<Option<Revision>>, Partial> {
+        let mut applied = Vec::with_capacity(changes.len());
+        for c in changes {
+            let res = match c.data {
+                Some(data) => self.put(c.space, &c.key, data, &c.condition).map(Some),
+                None => self.delete(c.space, &c.key, &c.condition).map(|_| None),
+            };
+            match res {
+                Ok(rev) => applied.push(rev),
+                Err(error) => return Err(Partial { applied, error }),
+            }
+        }
+        Ok(applied)
+    }
+}
+
+/// A store that can be found by the name and location of a vault
+///
+/// This allows `StoreVault` to implement `Vault::new` and `Vault::load`.
+pub trait Locate: BlobStore + Sized {
+    /// Set up the storage for a new vault
+    fn create(gen: &Generator) -> Result<Self, LockError>;
+    /// Open the storage of an existing vault
+    fn open(name: &str, location: &str) -> Result<Self, LockError>;
+}
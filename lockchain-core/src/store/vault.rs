@@ -0,0 +1,366 @@
+//! A vault on top of any `BlobStore`
+
+use super::{BlobStore, Change, Condition, Locate, Revision, Space};
+use crate::codec::Codec;
+use crate::errors::{about, DataError, Error as LockError, VaultError};
+use crate::init::Generator;
+use crate::meta::{MetaDomain, VaultMetadata};
+use crate::record::Record;
+use crate::state::{HeaderIndex, IndexEntry, StoredHeader, VaultState, INDEX, USERS};
+use crate::traits::{AutoEncoder, Body, LoadRecord, Vault};
+use crate::users::UserStore;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A vault that keeps it's data in a `BlobStore`
+///
+/// Works much like a `FileVault`: opening a vault only reads the
+/// header index, record bodies are read when they are pulled and
+/// only changed blobs are written on `sync`.
+///
+/// Every blob is written on the condition that it wasn't changed
+/// since it was read. If another handle wrote to the store in the
+/// meantime, `sync` fails with `VaultError::Conflict` and keeps all
+/// changes. `fetch` brings the vault up to date again, throwing
+/// away unsynced changes to records and metadata domains.
+pub struct StoreVault<S: BlobStore, T: Body> {
+    store: S,
+    name: String,
+    location: String,
+    /// The codec that blobs are written with
+    codec: Codec,
+    state: VaultState<T>,
+    /// The revisions of all blobs, as they were last read or written
+    revisions: HashMap<(Space, String), Revision>,
+}
+
+impl<S: BlobStore, T: Body> StoreVault<S, T> {
+    /// Create a new vault in an empty store
+    ///
+    /// The vault is written to the store right away.
+    pub fn create(store: S, gen: Generator) -> Result<Self, LockError> {
+        let (name, location) = match gen {
+            Generator {
+                name: Some(ref n),
+                location: Some(ref l),
+                ..
+            } => (n.clone(), l.clone()),
+            _ => return Err(VaultError::IncompleteGenerator.into()),
+        };
+
+        let vault_type = gen.user_type.ok_or(VaultError::IncompleteGenerator)?;
+
+        let mut me = Self::empty(store, name, location, VaultState::create(&vault_type));
+        me.codec = gen.codec.unwrap_or_default();
+        me.state.dirty.index = true;
+        me.write_changes()?;
+        Ok(me)
+    }
+
+    /// Open a vault that was written to a store before
+    ///
+    /// New blobs are written with the codec the userstore was written with.
+    pub fn open(store: S, name: &str, location: &str) -> Result<Self, LockError> {
+        let blob = store
+            .get(Space::Metadata, USERS)?
+            .ok_or_else(|| about(VaultError::FailedLoading, "vault", name))?;
+        let users = UserStore::from_bytes(&blob.data)?;
+
+        let mut me = Self::empty(store, name.into(), location.into(), VaultState::new(users));
+        me.codec = Codec::detect(&blob.data).unwrap_or_default();
+        me.revisions
+            .insert((Space::Metadata, USERS.into()), blob.revision);
+        me.reload()?;
+        Ok(me)
+    }
+
+    fn empty(store: S, name: String, location: String, state: VaultState<T>) -> Self {
+        Self {
+            store,
+            name,
+            location,
+            codec: Codec::default(),
+            state,
+            revisions: HashMap::new(),
+        }
+    }
+
+    /// The store this vault keeps it's data in
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The codec that blobs are written with
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Write blobs with a different codec from now on
+    ///
+    /// Blobs that were written before are converted
+    /// the next time they change.
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+
+    /// Make sure a record is loaded
+    fn pulled(&mut self, record: &str) -> Result<(), LockError> {
+        let id = self.state.find(record)?;
+        if !self.state.is_pulled(&id) {
+            self.load_record(record)?;
+        }
+        Ok(())
+    }
+
+    /// Only overwrite a blob if it is still the version we know about
+    fn condition(&self, space: Space, key: &str) -> Condition {
+        match self.revisions.get(&(space, key.to_owned())) {
+            Some(rev) => Condition::Matches(rev.clone()),
+            None => Condition::Absent,
+        }
+    }
+
+    /// Read the header index, and re-read all records that changed since it was written
+    ///
+    /// Returns `true` if the index is out of date.
+    fn load_index(&mut self) -> Result<bool, LockError> {
+        let listed: HashMap<Uuid, Revision> = self
+            .store
+            .list(Space::Records)?
+            .into_iter()
+            .filter_map(|(key, rev)| Some((Uuid::parse_str(&key).ok()?, rev)))
+            .collect();
+
+        let mut indexed = HashMap::new();
+        let mut stale = true;
+        if let Some(blob) = self.store.get(Space::Index, INDEX)? {
+            if let Ok(index) = HeaderIndex::from_bytes(&blob.data) {
+                stale = index.entries.len() != listed.len();
+                indexed = index
+                    .entries
+                    .into_iter()
+                    .filter(|e| e.sum.is_some() && e.sum.as_ref() == listed.get(&e.header.id))
+                    .map(|e| (e.header.id, e.header))
+                    .collect();
+            }
+            self.revisions
+                .insert((Space::Index, INDEX.into()), blob.revision);
+        }
+
+        self.state.clear();
+        for (id, rev) in listed {
+            let key = id.to_string();
+            let (header, rev) = match indexed.remove(&id) {
+                Some(header) => (header, rev),
+                None => {
+                    stale = true;
+
+                    /* Blobs that can't be read aren't part of the vault */
+                    let blob = match self.store.get(Space::Records, &key)? {
+                        Some(blob) => blob,
+                        None => continue,
+                    };
+                    match StoredHeader::from_bytes(&blob.data) {
+                        Ok(stored) if stored.header.id == id => (stored.header, blob.revision),
+                        _ => continue,
+                    }
+                }
+            };
+            self.state.insert_header(header);
+            self.revisions.insert((Space::Records, key), rev);
+        }
+
+        Ok(stale)
+    }
+
+    /// Write the header index, listing the current revision of every record
+    fn write_index(&mut self) -> Result<(), LockError> {
+        let mut entries: Vec<_> = self
+            .state
+            .headers()
+            .map(|h| IndexEntry {
+                sum: self
+                    .revisions
+                    .get(&(Space::Records, h.id.to_string()))
+                    .cloned(),
+                header: h.clone(),
+            })
+            .collect();
+        entries.sort_by_key(|e| e.header.id);
+
+        let data = HeaderIndex { entries }.to_bytes(self.codec)?;
+        let condition = self.condition(Space::Index, INDEX);
+        let rev = self.store.put(Space::Index, INDEX, data, &condition)?;
+        self.revisions.insert((Space::Index, INDEX.into()), rev);
+        Ok(())
+    }
+
+    /// Reads all record headers and metadata domains from the store
+    ///
+    /// Loaded record bodies are dropped, unsynced changes to
+    /// records and domains are lost.
+    fn reload(&mut self) -> Result<(), LockError> {
+        self.revisions
+            .retain(|(space, key), _| *space == Space::Metadata && key == USERS);
+        let stale = self.load_index()?;
+
+        let mut metadata = HashMap::new();
+        for (key, _) in self.store.list(Space::Metadata)? {
+            if key == USERS {
+                continue;
+            }
+            let blob = match self.store.get(Space::Metadata, &key)? {
+                Some(blob) => blob,
+                None => continue,
+            };
+            match MetaDomain::from_bytes(&blob.data) {
+                Ok(domain) if domain.name() == key => {
+                    self.revisions
+                        .insert((Space::Metadata, key.clone()), blob.revision);
+                    metadata.insert(key, domain);
+                }
+                _ => continue,
+            }
+        }
+
+        self.state.metadata = metadata;
+        self.state.reset();
+        self.state.dirty.index = stale;
+        Ok(())
+    }
+
+    fn load_record(&mut self, name: &str) -> Result<(), LockError> {
+        let key = self.state.find(name)?.to_string();
+        let blob = self
+            .store
+            .get(Space::Records, &key)?
+            .ok_or_else(|| about(DataError::FailedRead, "record", name))?;
+        let rec = Record::<T>::from_bytes(&blob.data)
+            .map_err(|e| e.context(format!("record '{}'", name)))?;
+
+        self.state.insert(rec);
+        self.revisions.insert((Space::Records, key), blob.revision);
+        Ok(())
+    }
+
+    /// Writes everything that changed since the last sync
+    ///
+    /// The header index is written last, once all records are stored.
+    fn write_changes(&mut self) -> Result<(), LockError> {
+        let dirty = &self.state.dirty;
+        let mut changes = Vec::new();
+        for id in &dirty.records {
+            if let Some(rec) = self.state.record(id) {
+                changes.push((
+                    Space::Records,
+                    id.to_string(),
+                    Some(rec.to_bytes(self.codec)?),
+                ));
+            }
+        }
+        for id in &dirty.removed {
+            changes.push((Space::Records, id.to_string(), None));
+        }
+        for name in &dirty.domains {
+            if let Some(domain) = self.state.metadata.get(name) {
+                changes.push((
+                    Space::Metadata,
+                    name.clone(),
+                    Some(domain.to_bytes(self.codec)?),
+                ));
+            }
+        }
+        if dirty.users {
+            changes.push((
+                Space::Metadata,
+                USERS.into(),
+                Some(self.state.users.to_bytes(self.codec)?),
+            ));
+        }
+
+        /* Records that were never stored don't need to be deleted */
+        let changes: Vec<_> = changes
+            .into_iter()
+            .filter(|(space, key, data)| {
+                data.is_some() || self.revisions.contains_key(&(*space, key.clone()))
+            })
+            .map(|(space, key, data)| Change {
+                condition: self.condition(space, &key),
+                space,
+                key,
+                data,
+            })
+            .collect();
+        let keys: Vec<_> = changes.iter().map(|c| (c.space, c.key.clone())).collect();
+
+        /* Applied changes are remembered even if the commit failed, so a retry doesn't conflict */
+        let (applied, failed) = match self.store.commit(changes) {
+            Ok(applied) => (applied, None),
+            Err(partial) => (partial.applied, Some(partial.error)),
+        };
+        for (key, rev) in keys.into_iter().zip(applied) {
+            match rev {
+                Some(rev) => self.revisions.insert(key, rev),
+                None => self.revisions.remove(&key),
+            };
+        }
+        if let Some(e) = failed {
+            return Err(e);
+        }
+
+        if self.state.dirty.index || self.state.dirty.any_records() {
+            self.write_index()?;
+        }
+        self.state.dirty = Default::default();
+        Ok(())
+    }
+}
+
+impl<S: Locate, T: Body> LoadRecord<T> for StoreVault<S, T> {}
+
+impl<S: Locate, T: Body> Vault<T> for StoreVault<S, T> {
+    fn new(gen: Generator) -> Result<Box<Self>, LockError> {
+        let store = S::create(&gen)?;
+        Self::create(store, gen).map(Box::new)
+    }
+
+    fn load(name: &str, location: &str) -> Result<Box<Self>, LockError> {
+        let store = S::open(name, location)?;
+        Self::open(store, name, location).map(Box::new)
+    }
+
+    crate::forward_to_state! {
+        state: authenticate, deauthenticate, create_user, delete_user, get_record, contains,
+            query, add_record, delete_record, get_data, meta_add_domain, meta_pull_domain,
+            meta_push_domain, meta_set, meta_get, meta_remove, meta_exists;
+        pulled: rename_record, add_data, remove_data
+    }
+
+    fn metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            name: self.name.clone(),
+            location: self.location.clone(),
+            size: self.state.len(),
+        }
+    }
+
+    /// Reads all record headers and metadata domains from the store
+    ///
+    /// Loaded record bodies are dropped, unsynced changes to
+    /// records and domains are lost.
+    fn fetch(&mut self) -> Result<(), LockError> {
+        self.reload()
+    }
+
+    fn pull(&mut self, name: &str) -> Result<(), LockError> {
+        self.load_record(name)
+    }
+
+    /// Writes everything that changed since the last sync
+    ///
+    /// Fails with `VaultError::Conflict` if another handle changed
+    /// the same blobs since they were read.
+    fn sync(&mut self) -> Result<(), LockError> {
+        self.write_changes()
+    }
+}
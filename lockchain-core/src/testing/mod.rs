@@ -0,0 +1,180 @@
+//! A conformance test suite for `Vault` implementations
+//!
+//! Only available with the `testing` feature. The `Vault` trait leaves
+//! a lot of behaviour to the implementation. The functions in `suite`
+//! pin it down, so that all backends agree on what "correct" means.
+//!
+//! A backend runs the entire suite by invoking `vault_conformance!`
+//! in one of it's integration tests, with `TestBody` as record body:
+//!
+//! ```ignore
+//! use lockchain_core::{testing::TestBody, vault_conformance};
+//! use lockchain_files::FileVault;
+//!
+//! vault_conformance!(file_vault, FileVault<TestBody>);
+//! ```
+//!
+//! Backends without persistence pass `transient` as a third
+//! argument, which skips all tests that re-open a vault.
+
+pub mod suite;
+
+use crate::record::Payload;
+use crate::traits::{Body, Vault};
+use crate::{Generator, VaultType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The user that every test vault is created with
+pub const USER: &str = "alice";
+
+/// The root secret of `USER`
+pub const SECRET: &str = "secret";
+
+/// A minimal cleartext record body
+///
+/// Simply stores all fields in a map. It is not meant to be
+/// used outside of tests (see `lockchain-crypto` for that).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestBody {
+    fields: BTreeMap<String, Payload>,
+}
+
+impl Body for TestBody {
+    fn get_field(&self, key: &str) -> Option<&Payload> {
+        self.fields.get(key)
+    }
+
+    fn set_field(&mut self, key: &str, value: Payload) -> Option<()> {
+        self.fields.insert(key.into(), value);
+        Some(())
+    }
+
+    fn remove_field(&mut self, key: &str) -> Option<Payload> {
+        self.fields.remove(key)
+    }
+
+    fn flatten(&mut self) -> Option<()> {
+        Some(())
+    }
+
+    fn zeroize(&mut self) {
+        self.fields.values_mut().for_each(Payload::zeroize);
+    }
+}
+
+/// A unique, temporary location to create a test vault in
+///
+/// The directory (and everything a backend put into it)
+/// is removed again when the fixture is dropped.
+pub struct Fixture {
+    /// The name used for the test vault
+    pub name: String,
+    /// The location used for the test vault
+    pub location: String,
+    dir: PathBuf,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fixture {
+    /// Create a new, empty fixture directory
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "lockchain-testing-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+            nanos
+        ));
+        fs::create_dir_all(&dir).expect("Failed to create fixture directory");
+
+        Self {
+            name: "conformance".into(),
+            location: dir.to_str().expect("Non UTF-8 temp dir").into(),
+            dir,
+        }
+    }
+
+    /// A generator for a solo-user vault, owned by `USER`
+    pub fn generator(&self) -> Generator {
+        Generator::new()
+            .path(self.name.as_str(), self.location.as_str())
+            .user_type(VaultType::SoloUser {
+                username: USER.into(),
+                secret: SECRET.as_bytes().to_vec(),
+            })
+    }
+
+    /// Create a new vault in this fixture
+    pub fn create<V: Vault<TestBody>>(&self) -> V {
+        self.generator()
+            .finalise()
+            .expect("Failed to create test vault")
+    }
+
+    /// Load the vault previously created in this fixture
+    pub fn reopen<V: Vault<TestBody>>(&self) -> V {
+        *V::load(&self.name, &self.location).expect("Failed to re-open test vault")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Generate the conformance suite for a vault type
+///
+/// Creates a module called `$name`, with one `#[test]` per
+/// function in `testing::suite`. The vault type has to
+/// implement `Vault<TestBody>`.
+#[macro_export]
+macro_rules! vault_conformance {
+    ($name:ident, $vault:ty) => {
+        $crate::vault_conformance!($name, $vault, persistent);
+    };
+    ($name:ident, $vault:ty, transient) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::vault_conformance!(@tests $vault;
+                records, missing_records, nested_data, renames, queries, metadata,
+                authentication, users);
+        }
+    };
+    ($name:ident, $vault:ty, persistent) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::vault_conformance!(@tests $vault;
+                records, missing_records, nested_data, renames, queries, metadata,
+                authentication, users, persist_records, persist_deletions, persist_renames,
+                persist_queries, persist_metadata, persist_users);
+        }
+    };
+    (@tests $vault:ty; $($test:ident),*) => {
+        $(
+            #[test]
+            fn $test() {
+                $crate::testing::suite::$test::<$vault>();
+            }
+        )*
+    };
+}
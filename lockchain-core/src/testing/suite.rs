@@ -0,0 +1,572 @@
+//! The individual conformance tests
+//!
+//! Every function creates it's own `Fixture` and panics if the
+//! vault under test misbehaves. They are usually invoked via the
+//! `vault_conformance!` macro, but can also be called by hand.
+//!
+//! Tests prefixed with `persist_` drop a vault after syncing it
+//! and check what `Vault::load` brings back.
+
+use super::{Fixture, TestBody, SECRET, USER};
+use crate::errors::{AuthError, DataError, Error, VaultError};
+use crate::query::Query;
+use crate::record::Payload;
+use crate::traits::{Body, Vault};
+use crate::users::{Access, Role, Token};
+use crate::MetaDomain;
+
+fn text(s: &str) -> Payload {
+    Payload::Text(s.into())
+}
+
+/// Assert that an operation failed with a specific error, regardless of context
+macro_rules! assert_err {
+    ($op:expr, $err:pat) => {
+        match $op {
+            Err(e) => match e.kind() {
+                $err => {}
+                _ => panic!("`{}` failed with the wrong error: {:?}", stringify!($op), e),
+            },
+            Ok(_) => panic!("`{}` should have failed", stringify!($op)),
+        }
+    };
+}
+
+/// Records can be added, filled, read and deleted
+pub fn records<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+    assert!(!vault.contains("github"));
+    assert_eq!(vault.metadata().size, 0);
+
+    vault
+        .add_record("github", "web", vec!["dev", "work"])
+        .unwrap();
+    assert!(vault.contains("github"));
+    assert_eq!(vault.metadata().size, 1);
+
+    let header = &vault.get_record("github").unwrap().header;
+    assert_eq!(header.name, "github");
+    assert_eq!(header.category, "web");
+    assert_eq!(header.tags, vec!["dev", "work"]);
+
+    /* Fields can be set, overwritten and read back */
+    vault.add_data("github", "user", text("alice")).unwrap();
+    vault.add_data("github", "password", text("1234")).unwrap();
+    vault
+        .add_data("github", "password", text("hunter2"))
+        .unwrap();
+    assert_eq!(vault.get_data("github", "user"), Some(&text("alice")));
+    assert_eq!(vault.get_data("github", "password"), Some(&text("hunter2")));
+    assert_eq!(vault.get_data("github", "email"), None);
+
+    /* Deleting hands back the record */
+    let record = vault.delete_record("github").unwrap();
+    assert_eq!(record.header.name, "github");
+    assert!(!vault.contains("github"));
+    assert!(vault.get_record("github").is_none());
+    assert_eq!(vault.get_data("github", "user"), None);
+    assert_eq!(vault.metadata().size, 0);
+}
+
+/// Operations on records that don't exist fail without side-effects
+pub fn missing_records<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+
+    assert!(vault.get_record("missing").is_none());
+    assert_err!(
+        vault.add_data("missing", "key", text("value")),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+    assert_eq!(vault.get_data("missing", "key"), None);
+    assert_err!(
+        vault.delete_record("missing"),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+    assert!(!vault.contains("missing"));
+    assert_eq!(vault.metadata().size, 0);
+
+    vault.add_record("once", "misc", vec![]).unwrap();
+    vault.delete_record("once").unwrap();
+    assert_err!(
+        vault.delete_record("once"),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+}
+
+/// Fields and nested values can be addressed by path and removed
+pub fn nested_data<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+    vault.add_record("server", "ops", vec![]).unwrap();
+
+    /* Missing maps are created along the way */
+    vault
+        .add_data("server", "host.name", text("example.com"))
+        .unwrap();
+    vault
+        .add_data("server", "credentials", Payload::List(vec![]))
+        .unwrap();
+    vault
+        .add_data("server", "credentials.0.password", text("1234"))
+        .unwrap();
+    vault
+        .add_data("server", "credentials.0.password", text("hunter2"))
+        .unwrap();
+    assert_eq!(
+        vault.get_data("server", "host.name"),
+        Some(&text("example.com"))
+    );
+    assert_eq!(
+        vault.get_data("server", "credentials.0.password"),
+        Some(&text("hunter2"))
+    );
+    assert_eq!(vault.get_data("server", "credentials.1.password"), None);
+    assert_eq!(vault.get_data("server", "host..name"), None);
+
+    /* Paths can't run through plain values or past the end of a list */
+    assert_err!(
+        vault.add_data("server", "host.name.first", text("x")),
+        Error::Data(DataError::FailedWrite)
+    );
+    assert_err!(
+        vault.add_data("server", "credentials.5", text("x")),
+        Error::Data(DataError::FailedWrite)
+    );
+
+    assert_eq!(
+        vault
+            .remove_data("server", "credentials.0.password")
+            .unwrap(),
+        text("hunter2")
+    );
+    assert_eq!(vault.get_data("server", "credentials.0.password"), None);
+    assert_eq!(
+        vault
+            .remove_data("server", "host")
+            .unwrap()
+            .get_path(&["name"]),
+        Some(&text("example.com"))
+    );
+    assert_eq!(vault.get_data("server", "host"), None);
+    assert_err!(
+        vault.remove_data("server", "host"),
+        Error::Data(DataError::UnknownField)
+    );
+    assert_err!(
+        vault.remove_data("missing", "host"),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+
+    /* Metadata domains work the same way */
+    vault.meta_add_domain("registry").unwrap();
+    vault
+        .meta_set("registry", "limits.invites", Payload::Number(3))
+        .unwrap();
+    assert_eq!(
+        vault.meta_get("registry", "limits.invites"),
+        Some(Payload::Number(3))
+    );
+    assert_eq!(
+        vault.meta_remove("registry", "limits.invites").unwrap(),
+        Payload::Number(3)
+    );
+    assert_eq!(vault.meta_get("registry", "limits.invites"), None);
+    assert_err!(
+        vault.meta_remove("registry", "limits.invites"),
+        Error::Data(DataError::UnknownField)
+    );
+    assert_err!(
+        vault.meta_remove("missing", "limits"),
+        Error::Vault(VaultError::UnknownDomain)
+    );
+}
+
+/// Records keep their id when renamed and can be found by either
+pub fn renames<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    vault.add_data("mail", "user", text("alice")).unwrap();
+    vault.add_record("web", "personal", vec![]).unwrap();
+
+    let id = vault.get_record("mail").unwrap().header.id;
+    assert_ne!(id, vault.get_record("web").unwrap().header.id);
+    assert!(vault.contains(&id.to_string()));
+    assert_eq!(
+        vault.get_data(&id.to_string(), "user"),
+        Some(&text("alice"))
+    );
+
+    vault.rename_record("mail", "email").unwrap();
+    assert!(!vault.contains("mail"));
+    assert_eq!(vault.get_record("email").unwrap().header.id, id);
+    assert_eq!(vault.get_data("email", "user"), Some(&text("alice")));
+    assert_eq!(vault.metadata().size, 2);
+
+    /* Renaming by id works too */
+    vault.rename_record(&id.to_string(), "inbox").unwrap();
+    assert_eq!(vault.get_record("inbox").unwrap().header.name, "inbox");
+
+    /* Names stay unique */
+    assert_err!(
+        vault.rename_record("inbox", "web"),
+        Error::Vault(VaultError::RecordExists)
+    );
+    assert_err!(
+        vault.rename_record("inbox", ""),
+        Error::Vault(VaultError::InvalidName)
+    );
+    assert_err!(
+        vault.rename_record("missing", "other"),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+    assert!(vault.contains("inbox"));
+    assert_err!(
+        vault.add_record("", "misc", vec![]),
+        Error::Vault(VaultError::InvalidName)
+    );
+    assert_err!(
+        vault.add_record("inbox", "misc", vec![]),
+        Error::Vault(VaultError::RecordExists)
+    );
+    assert_eq!(vault.get_data("inbox", "user"), Some(&text("alice")));
+
+    /* Old names are free again */
+    vault.add_record("mail", "misc", vec![]).unwrap();
+    assert_ne!(vault.get_record("mail").unwrap().header.id, id);
+    assert_eq!(vault.metadata().size, 3);
+
+    /* Deleting by id works as well */
+    vault.delete_record(&id.to_string()).unwrap();
+    assert!(!vault.contains("inbox"));
+}
+
+/// Headers can be searched by category and tags, sorted and paged
+pub fn queries<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+    assert!(vault.query(&Query::new()).is_empty());
+
+    vault.add_record("ssh", "server", vec!["work"]).unwrap();
+    vault
+        .add_record("github", "web", vec!["dev", "work"])
+        .unwrap();
+    vault.add_record("mail", "web", vec!["personal"]).unwrap();
+
+    let names = |q: Query| -> Vec<String> {
+        vault
+            .query(&q)
+            .into_iter()
+            .map(|h| h.name.clone())
+            .collect()
+    };
+    assert_eq!(names(Query::new()), vec!["github", "mail", "ssh"]);
+    assert_eq!(names(Query::new().category("web")), vec!["github", "mail"]);
+    assert_eq!(
+        names(Query::new().any_tag(&["work", "personal"]).descending()),
+        vec!["ssh", "mail", "github"]
+    );
+    assert_eq!(
+        names(Query::new().all_tags(&["dev", "work"])),
+        vec!["github"]
+    );
+    assert_eq!(names(Query::new().page(1, 2)), vec!["ssh"]);
+}
+
+/// Metadata domains can be added, filled and replaced
+pub fn metadata<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+
+    assert!(!vault.meta_exists("registry"));
+    assert!(vault.meta_pull_domain("registry").is_none());
+    assert_err!(
+        vault.meta_set("registry", "invites", Payload::Number(1)),
+        Error::Vault(VaultError::UnknownDomain)
+    );
+    assert_eq!(vault.meta_get("registry", "invites"), None);
+
+    /* Adding a domain twice fails */
+    vault.meta_add_domain("registry").unwrap();
+    assert_err!(
+        vault.meta_add_domain("registry"),
+        Error::Vault(VaultError::DomainExists)
+    );
+    assert!(vault.meta_exists("registry"));
+
+    vault
+        .meta_set("registry", "invites", Payload::Number(3))
+        .unwrap();
+    assert_eq!(
+        vault.meta_get("registry", "invites"),
+        Some(Payload::Number(3))
+    );
+    assert_eq!(vault.meta_get("registry", "missing"), None);
+
+    /* Pushing a domain replaces it entirely */
+    let mut domain = MetaDomain::new("registry");
+    domain.set_field("open", Payload::Boolean(true));
+    vault.meta_push_domain(domain.clone()).unwrap();
+    assert_eq!(vault.meta_pull_domain("registry"), Some(&domain));
+    assert_eq!(vault.meta_get("registry", "invites"), None);
+}
+
+/// Only correct secrets yield tokens, which stop working on logout
+pub fn authentication<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+
+    for (user, secret) in &[(USER, "wrong"), ("mallory", SECRET), ("", "")] {
+        assert_err!(
+            vault.authenticate(user, secret),
+            Error::Auth(AuthError::UserNotAuthorised)
+        );
+    }
+    assert_err!(
+        vault.create_user(Token::new(), "bob", vec![1], vec![]),
+        Error::Auth(AuthError::InvalidToken)
+    );
+
+    /* Every login is it's own session */
+    let first = vault.authenticate(USER, SECRET).unwrap();
+    let second = vault.authenticate(USER, SECRET).unwrap();
+    assert_ne!(first, second);
+
+    vault.deauthenticate(USER, first.clone()).unwrap();
+    assert_err!(
+        vault.deauthenticate(USER, first.clone()),
+        Error::Auth(AuthError::InvalidToken)
+    );
+    assert_err!(
+        vault.create_user(first, "bob", vec![1], vec![]),
+        Error::Auth(AuthError::InvalidToken)
+    );
+    vault.create_user(second, "bob", vec![1], vec![]).unwrap();
+}
+
+/// Administrators can create and delete other users
+pub fn users<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let mut vault: V = fix.create();
+    let token = vault.authenticate(USER, SECRET).unwrap();
+
+    vault
+        .create_user(token.clone(), "bob", b"pw".to_vec(), vec![])
+        .unwrap();
+    assert_err!(
+        vault.create_user(token.clone(), "bob", b"other".to_vec(), vec![]),
+        Error::Auth(AuthError::UserExists)
+    );
+    assert_err!(
+        vault.create_user(token.clone(), USER, b"other".to_vec(), vec![]),
+        Error::Auth(AuthError::UserExists)
+    );
+
+    /* A deleted user is logged out and can't log in again */
+    let bob = vault.authenticate("bob", "pw").unwrap();
+    vault.delete_user(token.clone(), "bob").unwrap();
+    assert_err!(
+        vault.create_user(bob, "carol", vec![1], vec![]),
+        Error::Auth(AuthError::InvalidToken)
+    );
+    assert_err!(
+        vault.authenticate("bob", "pw"),
+        Error::Auth(AuthError::UserNotAuthorised)
+    );
+    assert_err!(
+        vault.delete_user(token.clone(), "bob"),
+        Error::Auth(AuthError::UnknownUser)
+    );
+
+    /* Deleting requires a valid token */
+    vault
+        .create_user(token.clone(), "carol", b"pw".to_vec(), vec![])
+        .unwrap();
+    assert_err!(
+        vault.delete_user(Token::new(), "carol"),
+        Error::Auth(AuthError::InvalidToken)
+    );
+
+    /* Only administrators manage users */
+    let carol = vault.authenticate("carol", "pw").unwrap();
+    assert_err!(
+        vault.create_user(carol.clone(), "dave", vec![1], vec![]),
+        Error::Auth(AuthError::UserNotAuthorised)
+    );
+    assert_err!(
+        vault.delete_user(carol, USER),
+        Error::Auth(AuthError::UserNotAuthorised)
+    );
+
+    let admin = vec![Access::Vault(Role::Admin)];
+    vault
+        .create_user(token.clone(), "dave", b"pw".to_vec(), admin)
+        .unwrap();
+    let dave = vault.authenticate("dave", "pw").unwrap();
+    vault
+        .create_user(dave.clone(), "erin", vec![1], vec![])
+        .unwrap();
+    vault.delete_user(dave.clone(), "carol").unwrap();
+
+    /* The last administrator stays */
+    vault.delete_user(dave, USER).unwrap();
+    let dave = vault.authenticate("dave", "pw").unwrap();
+    assert_err!(
+        vault.delete_user(dave, "dave"),
+        Error::Auth(AuthError::LastAdmin)
+    );
+}
+
+/// Synced records and their data survive re-opening the vault
+pub fn persist_records<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    {
+        let mut vault: V = fix.create();
+        vault.add_record("github", "web", vec!["dev"]).unwrap();
+        vault
+            .add_data("github", "password", text("hunter2"))
+            .unwrap();
+        vault.add_record("empty", "misc", vec![]).unwrap();
+        vault.sync().unwrap();
+    }
+
+    let mut vault: V = fix.reopen();
+    let meta = vault.metadata();
+    assert_eq!(meta.name, fix.name);
+    assert_eq!(meta.location, fix.location);
+    assert_eq!(meta.size, 2);
+    assert!(vault.contains("github"));
+    assert!(vault.contains("empty"));
+
+    vault.pull("github").unwrap();
+    let header = &vault.get_record("github").unwrap().header;
+    assert_eq!(header.category, "web");
+    assert_eq!(header.tags, vec!["dev"]);
+    assert_eq!(vault.get_data("github", "password"), Some(&text("hunter2")));
+
+    /* Pulled records can be changed and synced again */
+    vault
+        .add_data("github", "password", text("correct horse"))
+        .unwrap();
+    vault.sync().unwrap();
+    drop(vault);
+
+    let mut vault: V = fix.reopen();
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "password"),
+        Some(&text("correct horse"))
+    );
+    assert_err!(
+        vault.pull("missing"),
+        Error::Vault(VaultError::UnknownRecord)
+    );
+}
+
+/// Records deleted before a sync stay deleted
+pub fn persist_deletions<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    {
+        let mut vault: V = fix.create();
+        vault.add_record("keep", "misc", vec![]).unwrap();
+        vault.add_record("remove", "misc", vec![]).unwrap();
+        vault.sync().unwrap();
+
+        vault.delete_record("remove").unwrap();
+        vault.sync().unwrap();
+    }
+
+    let vault: V = fix.reopen();
+    assert!(vault.contains("keep"));
+    assert!(!vault.contains("remove"));
+    assert_eq!(vault.metadata().size, 1);
+}
+
+/// Renamed records keep their new name and id after a sync
+pub fn persist_renames<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let id = {
+        let mut vault: V = fix.create();
+        vault.add_record("mail", "personal", vec![]).unwrap();
+        vault.add_data("mail", "user", text("alice")).unwrap();
+        vault.sync().unwrap();
+
+        vault.rename_record("mail", "email").unwrap();
+        vault.sync().unwrap();
+        vault.get_record("email").unwrap().header.id
+    };
+
+    let mut vault: V = fix.reopen();
+    assert!(!vault.contains("mail"));
+    assert!(vault.contains("email"));
+    assert_eq!(vault.metadata().size, 1);
+
+    vault.pull(&id.to_string()).unwrap();
+    assert_eq!(vault.get_record("email").unwrap().header.id, id);
+    assert_eq!(vault.get_data("email", "user"), Some(&text("alice")));
+}
+
+/// Records can be searched right after opening, without pulling them
+pub fn persist_queries<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    {
+        let mut vault: V = fix.create();
+        vault.add_record("ssh", "server", vec!["work"]).unwrap();
+        vault.add_record("mail", "web", vec!["personal"]).unwrap();
+        vault.sync().unwrap();
+    }
+
+    let vault: V = fix.reopen();
+    let found = vault.query(&Query::new().category("server"));
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "ssh");
+    assert_eq!(found[0].tags, vec!["work"]);
+}
+
+/// Synced metadata domains survive re-opening the vault
+pub fn persist_metadata<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    {
+        let mut vault: V = fix.create();
+        vault.meta_add_domain("registry").unwrap();
+        vault
+            .meta_set("registry", "invites", Payload::Number(3))
+            .unwrap();
+        vault.sync().unwrap();
+    }
+
+    let mut vault: V = fix.reopen();
+    assert!(vault.meta_exists("registry"));
+    assert_eq!(
+        vault.meta_get("registry", "invites"),
+        Some(Payload::Number(3))
+    );
+}
+
+/// Users survive re-opening the vault, sessions don't
+pub fn persist_users<V: Vault<TestBody>>() {
+    let fix = Fixture::new();
+    let token = {
+        let mut vault: V = fix.create();
+        let token = vault.authenticate(USER, SECRET).unwrap();
+        let admin = vec![Access::Vault(Role::Admin)];
+        vault
+            .create_user(token.clone(), "bob", b"pw".to_vec(), admin)
+            .unwrap();
+        vault.sync().unwrap();
+        token
+    };
+
+    let mut vault: V = fix.reopen();
+    assert_err!(
+        vault.create_user(token, "carol", vec![1], vec![]),
+        Error::Auth(AuthError::InvalidToken)
+    );
+
+    let bob = vault.authenticate("bob", "pw").unwrap();
+    vault.create_user(bob, "carol", vec![1], vec![]).unwrap();
+    let alice = vault.authenticate(USER, SECRET).unwrap();
+    vault.create_user(alice, "dave", vec![1], vec![]).unwrap();
+}
@@ -11,10 +11,12 @@
 //! compilation work without external crates but not calling
 //! functions at runtime.
 
-use crate::errors::VaultError;
+use crate::codec::{self, Codec};
+use crate::errors::Error as LockError;
 use crate::init::Generator;
 use crate::meta::{MetaDomain, VaultMetadata};
-use crate::record::{EncryptedBody, Header, Payload, Record};
+use crate::query::Query;
+use crate::record::{split_path, EncryptedBody, Header, Payload, Record};
 use crate::users::{Access, Token};
 use serde::{de::DeserializeOwned, Serialize};
 
@@ -26,13 +28,68 @@ use std::error::Error;
 /// data module.
 ///
 /// This allows working with both encrypted and cleartext data bodies.
-pub trait Body: DeserializeOwned + Serialize + Send {
+///
+/// The `Default` value of a body is used as the (empty) body of
+/// newly created records.
+///
+/// Nested values in `Payload::BTreeMap` and `Payload::List` fields
+/// can be addressed with a path of `.` separated map keys and list
+/// indices, such as `server.credentials.0.password`. By default, the
+/// path functions are implemented on top of the field functions,
+/// which only ever see top-level keys. Bodies that keep a history
+/// should override them, to record nested changes in place.
+pub trait Body: DeserializeOwned + Serialize + Send + Default {
     ///Get the value of a field from this body
     fn get_field(&self, key: &str) -> Option<&Payload>;
     /// Set the value of a field
     fn set_field(&mut self, key: &str, value: Payload) -> Option<()>;
+    /// Remove a field, returning it's value
+    fn remove_field(&mut self, key: &str) -> Option<Payload>;
     /// Remove versioning and flatten the data tree to a single level.
     fn flatten(&mut self) -> Option<()>;
+
+    /// Overwrite all secret data in memory, before the body is dropped
+    ///
+    /// Called by backends that evict bodies from memory. The
+    /// default does nothing, for bodies that hold no clear-text.
+    fn zeroize(&mut self) {}
+
+    /// Get a nested value by it's path
+    fn get_path(&self, path: &str) -> Option<&Payload> {
+        let (key, rest) = split_path(path)?;
+        self.get_field(key)?.get_path(&rest)
+    }
+
+    /// Set a nested value by it's path
+    ///
+    /// Missing maps along the path are created. The default
+    /// implementation replaces the whole top-level field.
+    fn set_path(&mut self, path: &str, value: Payload) -> Option<()> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.set_field(key, value);
+        }
+
+        let mut field = self
+            .get_field(key)
+            .cloned()
+            .unwrap_or_else(|| Payload::BTreeMap(Default::default()));
+        field.set_path(&rest, value)?;
+        self.set_field(key, field)
+    }
+
+    /// Remove a nested value by it's path, returning it
+    fn remove_path(&mut self, path: &str) -> Option<Payload> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.remove_field(key);
+        }
+
+        let mut field = self.get_field(key)?.clone();
+        let value = field.remove_path(&rest)?;
+        self.set_field(key, field)?;
+        Some(value)
+    }
 }
 
 /// A simple trait that allows libraries to hook into the
@@ -113,42 +170,67 @@ pub trait FileIO: AutoEncoder {
 /// authentication will need to be backed by some persistence layer
 /// (i.e. lockchain-files)
 ///
+/// All operations that can fail return a `lockchain_core::errors::Error`,
+/// so that storage and authentication failures can be passed on
+/// to callers. Pure lookups return an `Option` instead.
+///
+/// Records are stored by their immutable `Header::id`. Every function
+/// that takes a record accepts either it's (unique) name or it's id in
+/// hyphenated form. Names are looked up first. Changing a record
+/// needs it's body, so records that weren't pulled yet are pulled first.
+///
 pub trait Vault<T>: Send + LoadRecord<T>
 where
     T: Body,
 {
     /// Consumes a vault generator to construct a vault
-    fn new(_: Generator) -> Result<Box<Self>, VaultError>;
+    fn new(_: Generator) -> Result<Box<Self>, LockError>;
     /// Load and open an existing vault
-    fn load(name: &str, location: &str) -> Result<Box<Self>, VaultError>;
+    fn load(name: &str, location: &str) -> Result<Box<Self>, LockError>;
     /// Unlock the vault for a specific user
-    fn authenticate(&mut self, username: &str, secret: &str) -> Token;
+    ///
+    /// Fails with `AuthError::UserNotAuthorised` if the
+    /// user doesn't exist or the secret is wrong.
+    fn authenticate(&mut self, username: &str, secret: &str) -> Result<Token, LockError>;
     /// End a specific user session
-    fn deauthenticate(&mut self, username: &str, _: Token);
+    fn deauthenticate(&mut self, username: &str, _: Token) -> Result<(), LockError>;
     /// Create a new user with a list of initial access rights
     ///
+    /// Only administrators, users with `Access::Vault(Role::Admin)`,
+    /// can manage users. Everybody else gets `AuthError::UserNotAuthorised`.
+    /// The secret becomes the root key of the new user, every other
+    /// access right gets a key of it's own.
+    ///
     /// **Important Note** A backend can make no guarantee for the safety
     /// of it's persistence. This means that a client library author is
     /// responsible for encrypting all required secrets **before** submitting
     /// them to a vault backend!
+    ///
+    /// New users are stored on the next `sync`.
     fn create_user(
         &mut self,
         token: Token,
         username: &str,
         secret: Vec<u8>,
         access: Vec<Access>,
-    ) -> Result<(), ()>;
-    /// Delete a user
-    fn delete_user(&mut self, token: Token, username: &str);
+    ) -> Result<(), LockError>;
+    /// Delete a user, as an administrator
+    ///
+    /// The last administrator of a vault can't be deleted.
+    fn delete_user(&mut self, token: Token, username: &str) -> Result<(), LockError>;
     // / Modify user data, if authenticated as said user
     // fn modify_user(&mut self, token: Token, username: &str) -> Option<&mut User>;
 
     /// Get basic vault metadata
     fn metadata(&self) -> VaultMetadata;
     /// Fetch metadata headers for all records
-    fn fetch(&mut self);
+    ///
+    /// This replaces what the vault holds in memory with the state
+    /// of the backend. Records and metadata domains that were changed
+    /// but not synced yet are lost, call `sync` first to keep them.
+    fn fetch(&mut self) -> Result<(), LockError>;
     /// Pull a specific record from the backend
-    fn pull(&mut self, name: &str);
+    fn pull(&mut self, name: &str) -> Result<(), LockError>;
     /// Sync all changes back to the backend
     ///
     /// Ultimately it's up to the backend to decide
@@ -156,37 +238,61 @@ where
     /// It's free to ignore any sync requests
     /// but they can still be made for backends
     /// which explicitly promise sync requests compliance
-    fn sync(&mut self);
+    fn sync(&mut self) -> Result<(), LockError>;
 
     /// Get a complete record from this vault
     fn get_record(&self, name: &str) -> Option<&Record<T>>;
     /// Probe if a record is contained
     fn contains(&self, name: &str) -> bool;
-    /// Add a new record to this vault
-    fn add_record(&mut self, key: &str, category: &str, tags: Vec<&str>);
+    /// Search the headers of all records, including ones not pulled yet
+    ///
+    /// Never touches record bodies, so it works without the crypto layer
+    fn query(&self, query: &Query) -> Vec<&Header>;
+    /// Add a new, empty record to this vault
+    ///
+    /// Fails with `VaultError::InvalidName` for empty names and
+    /// `VaultError::RecordExists` if a record already has that name.
+    fn add_record(&mut self, key: &str, category: &str, tags: Vec<&str>) -> Result<(), LockError>;
     /// Delete a record from this vault
-    fn delete_record(&mut self, record: &str) -> Option<Record<T>>;
+    ///
+    /// Returns the removed record, without body if it was never pulled
+    fn delete_record(&mut self, record: &str) -> Result<Record<T>, LockError>;
+    /// Give a record a new name, keeping it's id
+    ///
+    /// Fails with `VaultError::RecordExists` if another
+    /// record already has that name.
+    fn rename_record(&mut self, record: &str, name: &str) -> Result<(), LockError>;
 
     /// Add data to an existing record, overwriting existing fields
-    fn add_data(&mut self, record: &str, key: &str, data: Payload) -> Option<()>;
+    ///
+    /// Like all data functions, `key` can be a path into nested data
+    fn add_data(&mut self, record: &str, key: &str, data: Payload) -> Result<(), LockError>;
     /// Get the (latest) value of a specific record data field
     fn get_data(&self, record: &str, key: &str) -> Option<&Payload>;
+    /// Remove a field from a record, returning it's value
+    fn remove_data(&mut self, record: &str, key: &str) -> Result<Payload, LockError>;
 
     /// Adds a domain space to the metadata store inside the vault
-    fn meta_add_domain(&mut self, domain: &str) -> Option<()>;
+    ///
+    /// Fails with `VaultError::InvalidName` for empty names and `userstore`
+    fn meta_add_domain(&mut self, domain: &str) -> Result<(), LockError>;
     /// Returns all records from a meta domain
     fn meta_pull_domain(&self, domain: &str) -> Option<&MetaDomain>;
     /// Entirely replace a meta domain in the store
-    fn meta_push_domain(&mut self, domain: MetaDomain) -> Option<()>;
+    fn meta_push_domain(&mut self, domain: MetaDomain) -> Result<(), LockError>;
     /// Set the value of a field inside a domain. Field names **must not** collide
-    fn meta_set(&mut self, domain: &str, name: &str, data: Payload) -> Option<()>;
+    fn meta_set(&mut self, domain: &str, name: &str, data: Payload) -> Result<(), LockError>;
     /// Get the value of a (unique) field inside a domain
     fn meta_get(&mut self, domain: &str, name: &str) -> Option<Payload>;
+    /// Remove a field from a domain, returning it's value
+    fn meta_remove(&mut self, domain: &str, name: &str) -> Result<Payload, LockError>;
     /// Check if a metadomain exists, regardless of data or depth
     fn meta_exists(&self, domain: &str) -> bool;
 }
 
 /// Auto-implement this trait to serialise types to json
+///
+/// Other formats are available via `to_bytes`, see `codec`.
 pub trait AutoEncoder: Serialize + DeserializeOwned {
     fn encode(&self) -> Result<String, SerdeError> {
         serde_json::to_string(self)
@@ -195,6 +301,16 @@ pub trait AutoEncoder: Serialize + DeserializeOwned {
     fn decode(s: &str) -> Result<Self, SerdeError> {
         serde_json::from_str(s)
     }
+
+    /// Encode with a specific codec
+    fn to_bytes(&self, codec: Codec) -> Result<Vec<u8>, LockError> {
+        codec.encode(self)
+    }
+
+    /// Decode data that was written by any codec
+    fn from_bytes(data: &[u8]) -> Result<Self, LockError> {
+        codec::decode(data)
+    }
 }
 
 /// Include this trait to monkey-patch base64 functions onto String types
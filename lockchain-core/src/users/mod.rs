@@ -19,9 +19,11 @@ mod tokens;
 mod user;
 
 mod secrets;
+mod sessions;
 mod userstore;
 
 pub use self::auth::pam_authenticate;
+pub use self::sessions::Sessions;
 pub use self::tokens::{Request, Token};
 pub use self::user::User;
 pub use self::userstore::UserStore;
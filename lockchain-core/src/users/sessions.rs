@@ -0,0 +1,48 @@
+//! Session tracking for authenticated vault users
+
+use super::tokens::Token;
+
+/// Keeps track of the tokens handed out to authenticated users
+///
+/// A user can have multiple sessions open at the same time,
+/// each with it's own token. Sessions are never persisted.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    active: Vec<(String, Token)>,
+}
+
+impl Sessions {
+    /// Create an empty session store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a new session for a user, returning it's token
+    pub fn open(&mut self, username: &str) -> Token {
+        let token = Token::new();
+        self.active.push((username.into(), token.clone()));
+        token
+    }
+
+    /// Close a single session of a user
+    ///
+    /// Returns `false` if no such session existed
+    pub fn close(&mut self, username: &str, token: &Token) -> bool {
+        let before = self.active.len();
+        self.active.retain(|(u, t)| !(u == username && t == token));
+        before != self.active.len()
+    }
+
+    /// Close all sessions of a user
+    pub fn close_all(&mut self, username: &str) {
+        self.active.retain(|(u, _)| u != username);
+    }
+
+    /// Look up which user a token belongs to
+    pub fn verify(&self, token: &Token) -> Option<&str> {
+        self.active
+            .iter()
+            .find(|(_, t)| t == token)
+            .map(|(u, _)| u.as_str())
+    }
+}
@@ -1,10 +1,14 @@
 //! Merging `KeyStore` and `Userstore` into the same concept
 
-use super::rights::Access;
+use super::rights::{Access, Role};
+use crate::crypto::random;
 use crate::traits::AutoEncoder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// The length of keys created by `grant`
+const KEY_LENGTH: usize = 32;
+
 /// A thin user UserStore
 ///
 /// It's implementation can manage multiple keys per user, of various
@@ -18,6 +22,7 @@ pub struct UserStore {
 #[derive(Serialize, Deserialize)]
 pub struct StoreUser {
     name: String,
+    #[serde(with = "keys")]
     keys: HashMap<Access, Vec<u8>>,
 }
 
@@ -55,6 +60,50 @@ impl UserStore {
         self.store.get_mut(&user).unwrap().keys.insert(access, k);
     }
 
+    /// Give an existing user access, with a new random key for it
+    ///
+    /// Every access of a user has it's own key, none of them are
+    /// derived from the root key.
+    pub fn grant(&mut self, user: &str, access: Access) {
+        if let Some(u) = self.store.get_mut(user) {
+            u.keys.insert(access, random::bytes(KEY_LENGTH));
+        }
+    }
+
+    /// Check if a user administrates the vault, and can manage other users
+    pub fn is_admin(&self, user: &str) -> bool {
+        self.get_key(user, Access::Vault(Role::Admin)).is_some()
+    }
+
+    /// The number of users that administrate the vault
+    pub fn admins(&self) -> usize {
+        self.store.keys().filter(|u| self.is_admin(u)).count()
+    }
+
+    /// Check if a user exists in this store
+    pub fn contains(&self, name: &str) -> bool {
+        self.store.contains_key(name)
+    }
+
+    /// List the names of all users in this store
+    pub fn users(&self) -> Vec<&str> {
+        self.store.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// Verify a secret against the root key of a user
+    ///
+    /// The comparison is done in constant time. As with all
+    /// keys in the store, the secret is assumed to already be
+    /// encrypted (or hashed) by the client.
+    pub fn verify(&self, user: &str, secret: &[u8]) -> bool {
+        match self.get_root_key(user) {
+            Some(key) if key.len() == secret.len() => {
+                key.iter().zip(secret).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+            }
+            _ => false,
+        }
+    }
+
     pub fn get_root_key(&self, user: &str) -> Option<&Vec<u8>> {
         self.store
             .get(user)
@@ -65,3 +114,55 @@ impl UserStore {
         self.store.get(user).map_or(None, |u| u.keys.get(&access))
     }
 }
+
+/// Keys are stored as a list of `(Access, key)` pairs
+///
+/// Most access rights can't be map keys in JSON. Also accepts the
+/// map that keys were stored in before.
+mod keys {
+    use super::Access;
+    use serde::de::{MapAccess, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::collections::HashMap;
+    use std::fmt;
+
+    type Keys = HashMap<Access, Vec<u8>>;
+
+    pub fn serialize<S: Serializer>(keys: &Keys, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(keys.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Keys, D::Error> {
+        if d.is_human_readable() {
+            d.deserialize_any(KeysVisitor)
+        } else {
+            d.deserialize_seq(KeysVisitor)
+        }
+    }
+
+    struct KeysVisitor;
+
+    impl<'de> Visitor<'de> for KeysVisitor {
+        type Value = Keys;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a list of keys")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Keys, A::Error> {
+            let mut keys = HashMap::new();
+            while let Some((access, key)) = seq.next_element()? {
+                keys.insert(access, key);
+            }
+            Ok(keys)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Keys, A::Error> {
+            let mut keys = HashMap::new();
+            while let Some((access, key)) = map.next_entry()? {
+                keys.insert(access, key);
+            }
+            Ok(keys)
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! A version of a record
+//!
+//! A set of versions can be flattened to represent the latest set
+//! of changes of a record, or replayed up to a certain point to
+//! see what a record looked like back then.
+
+use crate::record::Payload;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// An operation that was applied to a version
+///
+/// An operation is either an insert or a delete.
+/// It also carries a string key and a payload value inside
+/// a tuple. These are then summed together as a vector.
+///
+/// This means that if data contradicts itself in the same
+/// version the later edit (call) will override the previous
+///
+/// Changes to nested values are recorded with their full path,
+/// starting with the top-level key, as `InsertAt` and `DeleteAt`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Insert(String, Payload),
+    Delete(String),
+    InsertAt(Vec<String>, Payload),
+    DeleteAt(Vec<String>),
+}
+
+use self::Operation::{Delete, DeleteAt, Insert, InsertAt};
+
+impl Operation {
+    /// The top-level key this operation applies to
+    pub fn key(&self) -> &str {
+        match self {
+            Insert(key, _) | Delete(key) => key,
+            InsertAt(path, _) | DeleteAt(path) => path.first().map_or("", String::as_str),
+        }
+    }
+
+    /// Check if this is a nested operation on exactly this path
+    fn nested_at(&self, path: &[&str]) -> bool {
+        match self {
+            InsertAt(p, _) | DeleteAt(p) => p.iter().map(String::as_str).eq(path.iter().cloned()),
+            _ => false,
+        }
+    }
+}
+
+/// Represents a series of operations done in sequence
+/// that are applied to a record to preserve history of state
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Version {
+    version: u64,
+    date: DateTime<Local>,
+    ops: Vec<Operation>,
+}
+
+impl Version {
+    /// Create a simple new version
+    pub fn new(ver: u64) -> Version {
+        Version {
+            version: ver,
+            date: Local::now(),
+            ops: Vec::new(),
+        }
+    }
+
+    /// The number of this version
+    pub fn number(&self) -> u64 {
+        self.version
+    }
+
+    /// Timestamp when this version was created
+    pub fn date(&self) -> DateTime<Local> {
+        self.date
+    }
+
+    /// The operations in this version, in order
+    pub fn ops(&self) -> &[Operation] {
+        &self.ops
+    }
+
+    /// A simple utility to add an INSERT operation
+    ///
+    /// Replaces any previous operation on the same key,
+    /// including operations on values nested below it
+    pub fn insert(&mut self, key: &str, val: Payload) {
+        self.ops.retain(|op| op.key() != key);
+        self.ops.push(Insert(String::from(key), val));
+    }
+
+    /// A simple utility to add a DELETE operation
+    ///
+    /// Replaces any previous operation on the same key. The
+    /// deletion is always kept, so that a value set in an
+    /// earlier version doesn't come back when replaying.
+    pub fn delete(&mut self, key: &str) {
+        self.ops.retain(|op| op.key() != key);
+        self.ops.push(Delete(String::from(key)));
+    }
+
+    /// Add an INSERT operation for a nested value
+    ///
+    /// `path` starts with the top-level key. Replaces any
+    /// previous operation on the same path.
+    pub fn insert_at(&mut self, path: &[&str], val: Payload) {
+        self.ops.retain(|op| !op.nested_at(path));
+        self.ops.push(InsertAt(owned(path), val));
+    }
+
+    /// Add a DELETE operation for a nested value
+    ///
+    /// `path` starts with the top-level key. Replaces any
+    /// previous operation on the same path.
+    pub fn delete_at(&mut self, path: &[&str]) {
+        self.ops.retain(|op| !op.nested_at(path));
+        self.ops.push(DeleteAt(owned(path)));
+    }
+
+    /// Overwrite all values in this version with zeros
+    pub fn zeroize(&mut self) {
+        for op in &mut self.ops {
+            if let Insert(_, payload) | InsertAt(_, payload) = op {
+                payload.zeroize();
+            }
+        }
+    }
+
+    /// Apply all operations of this version onto an existing tree
+    pub fn apply(&self, map: &mut BTreeMap<String, Payload>) {
+        for op in &self.ops {
+            match op {
+                Insert(key, payload) => {
+                    map.insert(key.clone(), payload.clone());
+                }
+                Delete(key) => {
+                    map.remove(key);
+                }
+                InsertAt(path, payload) => {
+                    if let Some((key, rest)) = path.split_first() {
+                        map.entry(key.clone())
+                            .or_insert_with(|| Payload::BTreeMap(BTreeMap::new()))
+                            .set_path(&borrowed(rest), payload.clone());
+                    }
+                }
+                DeleteAt(path) => {
+                    if let Some((key, rest)) = path.split_first() {
+                        map.get_mut(key)
+                            .and_then(|field| field.remove_path(&borrowed(rest)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Take a version full of operations and flatten it to  a single
+    /// binary search tree that can be included into an embedded record
+    ///
+    /// Non-mutable on the version itself
+    pub fn flatten(&self) -> BTreeMap<String, Payload> {
+        let mut map = BTreeMap::new();
+        self.apply(&mut map);
+        map
+    }
+
+    /// A utility function which merges two versions onto &self
+    ///
+    /// - If a key is present in `other`, `self.key` is overwritten
+    /// - If a key is deleted in `other`, `self.key` is deleted
+    ///
+    /// The merged version takes the higher version number and
+    /// the later date of the two.
+    pub fn merge(&mut self, other: &Version) {
+        for op in &other.ops {
+            match op {
+                Insert(key, payload) => self.insert(key, payload.clone()),
+                Delete(key) => self.delete(key),
+                InsertAt(path, payload) => self.insert_at(&borrowed(path), payload.clone()),
+                DeleteAt(path) => self.delete_at(&borrowed(path)),
+            }
+        }
+
+        self.version = self.version.max(other.version);
+        self.date = self.date.max(other.date);
+    }
+}
+
+fn owned(path: &[&str]) -> Vec<String> {
+    path.iter().map(|s| s.to_string()).collect()
+}
+
+fn borrowed(path: &[String]) -> Vec<&str> {
+    path.iter().map(String::as_str).collect()
+}
@@ -0,0 +1,117 @@
+use lockchain_core::codec::{self, Codec};
+use lockchain_core::traits::AutoEncoder;
+use lockchain_core::users::{Access, Role, UserStore};
+use lockchain_core::{EncryptedBody, MetaDomain, PackedData, Payload, Record};
+use std::collections::HashMap;
+
+fn record() -> Record<EncryptedBody> {
+    let mut rec = Record::new("github", "web", vec!["dev"]);
+    rec.body = Some(EncryptedBody {
+        data: (0..=255).collect(),
+    });
+    rec
+}
+
+#[test]
+fn roundtrip_all_codecs() {
+    let rec = record();
+    let mut fields = HashMap::new();
+    fields.insert("theme".to_owned(), Payload::Text("dark".into()));
+    fields.insert("key".to_owned(), Payload::Bytes(vec![0, 1, 2]));
+    let domain = MetaDomain::new("settings").fill(fields);
+
+    for &c in &[Codec::Json, Codec::Cbor, Codec::Bincode] {
+        let data = rec.to_bytes(c).unwrap();
+        assert_eq!(Codec::detect(&data), Some(c));
+        assert_eq!(Record::from_bytes(&data).unwrap(), rec);
+
+        let data = domain.to_bytes(c).unwrap();
+        let decoded = MetaDomain::from_bytes(&data).unwrap();
+        assert_eq!(decoded.all(), domain.all());
+    }
+
+    /* JSON is the same as `encode` writes */
+    assert_eq!(
+        Codec::Json.encode(&rec).unwrap(),
+        rec.encode().unwrap().into_bytes()
+    );
+    assert_eq!("cbor".parse::<Codec>().unwrap(), Codec::Cbor);
+    assert!("yaml".parse::<Codec>().is_err());
+}
+
+#[test]
+fn decode_mixed_codecs() {
+    let values: Vec<Vec<u8>> = vec![
+        Codec::Json.encode(&vec![1u8, 2, 3]).unwrap(),
+        Codec::Cbor.encode(&vec![1u8, 2, 3]).unwrap(),
+        Codec::Bincode.encode(&vec![1u8, 2, 3]).unwrap(),
+        b"[1,2,3]".to_vec(),
+    ];
+
+    for data in &values {
+        assert_eq!(codec::decode::<Vec<u8>>(data).unwrap(), vec![1, 2, 3]);
+    }
+
+    /* Unknown tags and damaged data are rejected */
+    assert_eq!(Codec::detect(&[0, b'x', 1]), None);
+    assert!(codec::decode::<Vec<u8>>(&[0, b'x', 1]).is_err());
+    assert!(codec::decode::<Vec<u8>>(&[0, b'b', 1]).is_err());
+}
+
+#[test]
+fn binary_data_is_compact() {
+    let rec = record();
+    let json = rec.to_bytes(Codec::Json).unwrap();
+    let bincode = rec.to_bytes(Codec::Bincode).unwrap();
+    let cbor = rec.to_bytes(Codec::Cbor).unwrap();
+
+    /* Base64 in JSON, raw bytes in binary formats */
+    assert!(String::from_utf8(json.clone())
+        .unwrap()
+        .contains(&base64::encode(&(0..=255).collect::<Vec<u8>>())));
+    assert!(bincode.len() < json.len());
+    assert!(cbor.len() < json.len());
+}
+
+#[test]
+fn decode_legacy_packed_data() {
+    /* Bodies used to contain packed data as a JSON string */
+    let legacy = r#"{"data":"{\"nonce\":[1,2],\"iv\":[3],\"data\":[4,5,6]}"}"#;
+    let body: EncryptedBody = serde_json::from_str(legacy).unwrap();
+    let packed = PackedData::from_bytes(&body.data).unwrap();
+    assert_eq!(
+        (packed.nonce, packed.iv, packed.data),
+        (vec![1, 2], vec![3], vec![4, 5, 6])
+    );
+
+    let body = EncryptedBody {
+        data: vec![0, 1, 2],
+    };
+    let json = serde_json::to_string(&body).unwrap();
+    assert_eq!(json, r#"{"data":"AAEC"}"#);
+    assert_eq!(serde_json::from_str::<EncryptedBody>(&json).unwrap(), body);
+}
+
+#[test]
+fn userstore_keys_all_codecs() {
+    let mut users = UserStore::new();
+    users.add_user("alice".into(), vec![1, 2, 3]);
+    users.grant("alice", Access::Vault(Role::Admin));
+    let admin = users.get_key("alice", Access::Vault(Role::Admin)).cloned();
+
+    for &c in &[Codec::Json, Codec::Cbor, Codec::Bincode] {
+        let decoded = UserStore::from_bytes(&users.to_bytes(c).unwrap()).unwrap();
+        assert!(decoded.verify("alice", &[1, 2, 3]));
+        assert!(decoded.is_admin("alice"));
+        assert_eq!(
+            decoded.get_key("alice", Access::Vault(Role::Admin)),
+            admin.as_ref()
+        );
+    }
+
+    /* Keys used to be stored as a map */
+    let legacy = r#"{"store":{"alice":{"name":"alice","keys":{"Root":[1,2,3]}}}}"#;
+    let users: UserStore = serde_json::from_str(legacy).unwrap();
+    assert!(users.verify("alice", &[1, 2, 3]));
+    assert!(!users.is_admin("alice"));
+}
@@ -0,0 +1,173 @@
+use chrono::{Local, TimeZone};
+use lockchain_core::convert::{from_payload, to_payload};
+use lockchain_core::errors::{DataError, Error};
+use lockchain_core::traits::Body;
+use lockchain_core::{MetaDomain, Payload, Record};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Fields(BTreeMap<String, Payload>);
+
+impl Body for Fields {
+    fn get_field(&self, key: &str) -> Option<&Payload> {
+        self.0.get(key)
+    }
+
+    fn set_field(&mut self, key: &str, value: Payload) -> Option<()> {
+        self.0.insert(key.into(), value);
+        Some(())
+    }
+
+    fn remove_field(&mut self, key: &str) -> Option<Payload> {
+        self.0.remove(key)
+    }
+
+    fn flatten(&mut self) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Engine {
+    Postgres,
+    Sqlite(String),
+    Custom { driver: String, port: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DbCreds {
+    user: String,
+    password: String,
+    port: u16,
+    replicas: Vec<String>,
+    timeout: Option<f64>,
+    engine: Engine,
+}
+
+fn creds() -> DbCreds {
+    DbCreds {
+        user: "admin".into(),
+        password: "hunter2".into(),
+        port: 5432,
+        replicas: vec!["a".into(), "b".into()],
+        timeout: None,
+        engine: Engine::Custom {
+            driver: "pq".into(),
+            port: 5433,
+        },
+    }
+}
+
+fn text(s: &str) -> Payload {
+    Payload::Text(s.into())
+}
+
+#[test]
+fn typed_structs() {
+    let payload = to_payload(&creds()).unwrap();
+    match &payload {
+        Payload::BTreeMap(tree) => {
+            assert_eq!(tree.get("user"), Some(&text("admin")));
+            assert_eq!(tree.get("port"), Some(&Payload::Number(5432)));
+            assert_eq!(tree.get("timeout"), None);
+        }
+        p => panic!("Unexpected payload: {:?}", p),
+    }
+    assert_eq!(from_payload::<DbCreds>(payload).unwrap(), creds());
+
+    for engine in [Engine::Postgres, Engine::Sqlite("db.sqlite".into())] {
+        let payload = to_payload(&engine).unwrap();
+        assert_eq!(from_payload::<Engine>(payload).unwrap(), engine);
+    }
+    assert_eq!(to_payload(&Engine::Postgres).unwrap(), text("Postgres"));
+}
+
+#[test]
+fn unrepresentable_values() {
+    for result in [
+        to_payload(&()),
+        to_payload(&None::<u8>),
+        to_payload(&vec![Some(1), None]),
+        to_payload(&u64::MAX),
+    ] {
+        match result.as_ref().map_err(Error::kind) {
+            Err(Error::Data(DataError::FailedEncode)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert(1, "one");
+    assert!(to_payload(&map).is_err());
+
+    match from_payload::<DbCreds>(text("nope"))
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Data(DataError::FailedDecode)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn record_fields() {
+    let mut record: Record<Fields> = Record::new("db", "servers", vec![]);
+    assert!(record.set_typed("db", &creds()).is_err());
+
+    record.body = Some(Fields::default());
+    record.set_typed("db", &creds()).unwrap();
+    record.set_typed("db.port", &5433).unwrap();
+    assert_eq!(record.get_typed::<u16>("db.port").unwrap(), 5433);
+    assert_eq!(record.get_typed::<DbCreds>("db").unwrap().port, 5433);
+
+    match record
+        .get_typed::<u16>("missing")
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Data(DataError::UnknownField)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+
+    let mut domain = MetaDomain::new("settings");
+    domain.set_field("db", to_payload(&creds()).unwrap());
+    assert_eq!(
+        from_payload::<DbCreds>(domain.get_field("db").unwrap().clone()).unwrap(),
+        creds()
+    );
+}
+
+#[test]
+fn json_roundtrip() {
+    let date = Local.ymd(2019, 3, 1).and_hms_milli(12, 0, 0, 250);
+    let mut tree = BTreeMap::new();
+    tree.insert("name".to_owned(), text("github"));
+    tree.insert("key".to_owned(), Payload::Bytes(vec![0, 255]));
+    tree.insert("expires".to_owned(), Payload::DateTime(date));
+    tree.insert("password".to_owned(), Payload::Secret("hunter2".into()));
+    tree.insert(
+        "limits".to_owned(),
+        Payload::List(vec![
+            Payload::Number(-1),
+            Payload::Float(0.5),
+            Payload::Boolean(true),
+        ]),
+    );
+    let payload = Payload::BTreeMap(tree);
+
+    let value = Value::try_from(payload.clone()).unwrap();
+    assert_eq!(value["name"], json!("github"));
+    assert_eq!(value["key"], json!({"$bytes": "AP8="}));
+    assert_eq!(value["password"], json!({"$secret": "hunter2"}));
+    assert_eq!(value["limits"], json!([-1, 0.5, true]));
+    assert_eq!(Payload::try_from(value).unwrap(), payload);
+
+    /* Values without a payload equivalent */
+    assert!(Payload::try_from(json!(null)).is_err());
+    assert!(Payload::try_from(json!(u64::MAX)).is_err());
+    assert!(Payload::try_from(json!({"$bytes": "!"})).is_err());
+    assert!(Value::try_from(Payload::Float(f64::NAN)).is_err());
+}
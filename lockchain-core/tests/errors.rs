@@ -0,0 +1,79 @@
+extern crate lockchain_core as lcc;
+extern crate serde_json;
+
+use crate::lcc::errors::{AuthError, DataError, Error, VaultError};
+use std::error::Error as StdError;
+use std::io;
+
+#[test]
+fn specific_messages() {
+    let errors: Vec<Error> = vec![
+        VaultError::UnknownRecord.into(),
+        VaultError::Locked.into(),
+        AuthError::InvalidToken.into(),
+        DataError::FailedDecode.into(),
+    ];
+
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "VaultError: No such record",
+            "VaultError: Vault is opened by another handle or process",
+            "AuthError: Token doesn't belong to an active session",
+            "DataError: Failed to decode data",
+        ]
+    );
+
+    let e = VaultError::IncompatibleVersion {
+        found: "0.3.0".into(),
+        supported: "0.1.0".into(),
+    };
+    assert_eq!(
+        e.to_string(),
+        "Vault format version 0.3.0 is incompatible with supported version 0.1.0"
+    );
+}
+
+#[test]
+fn context_and_source() {
+    let cause = io::Error::new(io::ErrorKind::InvalidData, "expected value at line 1");
+    let e = Error::from(DataError::FailedDecode)
+        .context("record 'github'")
+        .caused_by(cause);
+
+    assert_eq!(
+        e.to_string(),
+        "DataError: Failed to decode data (record 'github')"
+    );
+    assert_eq!(
+        e.source().map(|s| s.to_string()),
+        Some("expected value at line 1".into())
+    );
+
+    match e.kind() {
+        Error::Data(DataError::FailedDecode) => {}
+        k => panic!("Unexpected kind: {:?}", k),
+    }
+
+    /* Errors without context don't have a source */
+    assert!(Error::from(VaultError::FailedSync).source().is_none());
+}
+
+#[test]
+fn serialise_with_context() {
+    let e = Error::from(AuthError::UnknownUser)
+        .context("user 'bob'")
+        .caused_by(io::Error::other("not serialised"));
+
+    let json = serde_json::to_string(&e).unwrap();
+    assert!(!json.contains("not serialised"));
+
+    let e: Error = serde_json::from_str(&json).unwrap();
+    assert_eq!(e.to_string(), "AuthError: No such user (user 'bob')");
+    assert!(e.source().is_none());
+
+    /* Plain errors keep their old representation */
+    let json = serde_json::to_string(&Error::from(VaultError::Locked)).unwrap();
+    assert_eq!(json, r#"{"Vault":"Locked"}"#);
+}
@@ -0,0 +1,71 @@
+use chrono::{Local, TimeZone};
+use lockchain_core::Payload;
+use std::collections::BTreeMap;
+
+fn roundtrip(p: &Payload) -> Payload {
+    serde_json::from_str(&serde_json::to_string(p).unwrap()).unwrap()
+}
+
+#[test]
+fn encode_new_types() {
+    let date = Local.ymd(2019, 3, 1).and_hms(12, 0, 0);
+    let payloads = vec![
+        Payload::Bytes(vec![0, 1, 2, 255]),
+        Payload::Float(3.5),
+        Payload::DateTime(date),
+        Payload::Secret("hunter2".into()),
+    ];
+
+    for p in &payloads {
+        assert_eq!(&roundtrip(p), p);
+    }
+    assert_eq!(
+        &roundtrip(&Payload::List(payloads.clone())),
+        &Payload::List(payloads)
+    );
+
+    /* Bytes are base64 in text formats */
+    assert_eq!(
+        serde_json::to_string(&Payload::Bytes(b"key".to_vec())).unwrap(),
+        r#"{"Bytes":"a2V5"}"#
+    );
+}
+
+#[test]
+fn decode_old_records() {
+    let json = r#"{"BTreeMap":{"admin":{"Boolean":true},"keys":{"List":[]},"uid":{"Number":1000},"user":{"Text":"alice"}}}"#;
+    let mut tree = BTreeMap::new();
+    tree.insert("user".to_owned(), Payload::Text("alice".into()));
+    tree.insert("admin".to_owned(), Payload::Boolean(true));
+    tree.insert("uid".to_owned(), Payload::Number(1000));
+    tree.insert("keys".to_owned(), Payload::List(vec![]));
+
+    let payload: Payload = serde_json::from_str(json).unwrap();
+    assert_eq!(payload, Payload::BTreeMap(tree));
+    assert_eq!(serde_json::to_string(&payload).unwrap(), json);
+}
+
+#[test]
+fn compare_floats() {
+    assert_eq!(Payload::Float(f64::NAN), Payload::Float(f64::NAN));
+    assert_ne!(Payload::Float(0.1), Payload::Float(0.2));
+    assert_ne!(Payload::Float(1.0), Payload::Number(1));
+    assert_ne!(Payload::Secret("a".into()), Payload::Text("a".into()));
+}
+
+#[test]
+fn zeroize_nested() {
+    let mut tree = BTreeMap::new();
+    tree.insert("password".into(), Payload::Secret("hunter2".into()));
+    tree.insert("key".into(), Payload::Bytes(vec![1, 2, 3]));
+    tree.insert("tries".into(), Payload::List(vec![Payload::Number(3)]));
+
+    let mut payload = Payload::BTreeMap(tree);
+    payload.zeroize();
+
+    let mut tree = BTreeMap::new();
+    tree.insert("password".into(), Payload::Secret("\0".repeat(7)));
+    tree.insert("key".into(), Payload::Bytes(vec![0, 0, 0]));
+    tree.insert("tries".into(), Payload::List(vec![Payload::Number(0)]));
+    assert_eq!(payload, Payload::BTreeMap(tree));
+}
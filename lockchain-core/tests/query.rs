@@ -0,0 +1,169 @@
+use chrono::{Local, TimeZone};
+use lockchain_core::query::{Filter, Query, SortKey};
+use lockchain_core::{Header, Payload, Uuid};
+use std::collections::BTreeMap;
+
+fn header(name: &str, category: &str, tags: &[&str], day: u32, port: Option<i64>) -> Header {
+    let mut fields = BTreeMap::new();
+    if let Some(port) = port {
+        fields.insert("port".into(), Payload::Number(port));
+    }
+
+    Header {
+        id: Uuid::new_v4(),
+        name: name.into(),
+        category: category.into(),
+        tags: tags.iter().map(|&t| t.into()).collect(),
+        fields,
+        date_created: Local.ymd(2019, 3, day).and_hms(12, 0, 0),
+        date_updated: Local.ymd(2019, 4, 10 - day).and_hms(12, 0, 0),
+    }
+}
+
+fn headers() -> Vec<Header> {
+    vec![
+        header("ssh", "server", &["work"], 1, Some(22)),
+        header("github", "web", &["dev", "work"], 2, Some(443)),
+        header("gitlab", "web", &["dev"], 3, Some(443)),
+        header("mail", "web", &["personal"], 4, None),
+        header("db", "server", &["work", "dev"], 5, Some(5432)),
+    ]
+}
+
+fn names(query: &Query, headers: &[Header]) -> Vec<String> {
+    query
+        .run(headers)
+        .into_iter()
+        .map(|h| h.name.clone())
+        .collect()
+}
+
+#[test]
+fn filter_by_category_and_tags() {
+    let all = headers();
+    assert_eq!(
+        names(&Query::new(), &all),
+        vec!["db", "github", "gitlab", "mail", "ssh"]
+    );
+    assert_eq!(
+        names(&Query::new().category("server"), &all),
+        vec!["db", "ssh"]
+    );
+    assert_eq!(
+        names(&Query::new().any_tag(&["personal", "work"]), &all),
+        vec!["db", "github", "mail", "ssh"]
+    );
+    assert_eq!(
+        names(&Query::new().all_tags(&["dev", "work"]), &all),
+        vec!["db", "github"]
+    );
+    assert_eq!(
+        names(&Query::new().category("web").all_tags(&["dev"]), &all),
+        vec!["github", "gitlab"]
+    );
+    assert!(names(&Query::new().category("nothing"), &all).is_empty());
+}
+
+#[test]
+fn filter_by_fields() {
+    let all = headers();
+    assert_eq!(
+        names(&Query::new().field_eq("port", Payload::Number(443)), &all),
+        vec!["github", "gitlab"]
+    );
+    assert_eq!(
+        names(
+            &Query::new().field_range("port", Payload::Number(1)..Payload::Number(1024)),
+            &all
+        ),
+        vec!["github", "gitlab", "ssh"]
+    );
+    assert_eq!(
+        names(
+            &Query::new().field_range("port", Payload::Number(443)..),
+            &all
+        ),
+        vec!["db", "github", "gitlab"]
+    );
+
+    /* Floats and numbers compare by value, other types never match */
+    assert_eq!(
+        names(
+            &Query::new().field_range("port", ..=Payload::Float(22.0)),
+            &all
+        ),
+        vec!["ssh"]
+    );
+    assert!(names(
+        &Query::new().field_range("port", Payload::Text("a".into())..),
+        &all
+    )
+    .is_empty());
+}
+
+#[test]
+fn filter_by_dates() {
+    let all = headers();
+    let day = |d| Local.ymd(2019, 3, d).and_hms(0, 0, 0);
+    assert_eq!(
+        names(&Query::new().created(day(2)..day(4)), &all),
+        vec!["github", "gitlab"]
+    );
+    assert_eq!(names(&Query::new().created(day(5)..), &all), vec!["db"]);
+
+    let day = |d| Local.ymd(2019, 4, d).and_hms(0, 0, 0);
+    assert_eq!(
+        names(&Query::new().updated(..day(7)), &all),
+        vec!["db", "mail"]
+    );
+}
+
+#[test]
+fn sort_and_paginate() {
+    let all = headers();
+    assert_eq!(
+        names(&Query::new().sort_by(SortKey::Created), &all),
+        vec!["ssh", "github", "gitlab", "mail", "db"]
+    );
+    assert_eq!(
+        names(&Query::new().sort_by(SortKey::Updated).descending(), &all),
+        vec!["ssh", "github", "gitlab", "mail", "db"]
+    );
+
+    /* Names break ties, missing fields come last */
+    let by_port = Query::new().sort_by(SortKey::Field("port".into()));
+    assert_eq!(
+        names(&by_port, &all),
+        vec!["ssh", "github", "gitlab", "db", "mail"]
+    );
+    assert_eq!(
+        names(&by_port.clone().descending(), &all),
+        vec!["db", "gitlab", "github", "ssh", "mail"]
+    );
+
+    assert_eq!(
+        names(&by_port.clone().page(0, 2), &all),
+        vec!["ssh", "github"]
+    );
+    assert_eq!(names(&by_port.clone().page(2, 2), &all), vec!["mail"]);
+    assert!(names(&by_port.page(3, 2), &all).is_empty());
+    assert_eq!(
+        names(&Query::new().offset(1).limit(2), &all),
+        vec!["github", "gitlab"]
+    );
+}
+
+#[test]
+fn encode_queries() {
+    let query = Query::new()
+        .category("web")
+        .filter(Filter::AnyTag(vec!["dev".into()]))
+        .field_range("port", Payload::Number(1)..=Payload::Number(1024))
+        .sort_by(SortKey::Name)
+        .page(0, 10);
+
+    let json = serde_json::to_string(&query).unwrap();
+    let decoded: Query = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, query);
+    assert_eq!(names(&decoded, &headers()), names(&query, &headers()));
+}
@@ -4,24 +4,104 @@
 //! should only exist in ephemeral form. All actions are first
 //! encrypted before being written back to a persistence
 //! medium.
+//!
+//! Every change to a body is recorded as a `Version`, so that
+//! overwritten or deleted values can be recovered later on. Call
+//! `flatten()` to throw away the history.
 
 use crate::lcc::traits::{AutoEncoder, Body};
-use crate::lcc::Payload;
+use crate::lcc::{split_path, Payload, Version};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 /// A clear-text representation of a record
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Stored")]
 pub struct DataBody {
     tree: BTreeMap<String, Payload>,
+    history: Vec<Version>,
+}
+
+/// The on-disk form of a body, which might predate versioning
+#[derive(Deserialize)]
+struct Stored {
+    tree: BTreeMap<String, Payload>,
+    #[serde(default)]
+    history: Vec<Version>,
+}
+
+impl From<Stored> for DataBody {
+    fn from(s: Stored) -> Self {
+        let mut body = DataBody {
+            tree: s.tree,
+            history: s.history,
+        };
+
+        /* Bodies without history start out with a single version */
+        if body.history.is_empty() && !body.tree.is_empty() {
+            body.history.push(body.squash(1));
+        }
+
+        body
+    }
 }
 
 impl DataBody {
     pub fn new() -> Self {
         DataBody {
             tree: BTreeMap::new(),
+            history: Vec::new(),
         }
     }
+
+    /// All versions of this body, oldest first
+    pub fn history(&self) -> &[Version] {
+        &self.history
+    }
+
+    /// The number of the most recent version (`0` for an empty body)
+    pub fn version(&self) -> u64 {
+        self.history.last().map_or(0, Version::number)
+    }
+
+    /// Reconstruct this body as it was at a certain version
+    ///
+    /// The returned body only contains the history up to and
+    /// including `ver`. Returns `None` if that version was
+    /// removed by `flatten()` or doesn't exist yet.
+    pub fn at_version(&self, ver: u64) -> Option<DataBody> {
+        let first = self.history.first().map_or(0, Version::number);
+        if ver < first || ver > self.version() {
+            return None;
+        }
+
+        let history: Vec<_> = self
+            .history
+            .iter()
+            .take_while(|v| v.number() <= ver)
+            .cloned()
+            .collect();
+
+        let mut tree = BTreeMap::new();
+        history.iter().for_each(|v| v.apply(&mut tree));
+        Some(DataBody { tree, history })
+    }
+
+    /// Start a new version on top of the history
+    fn next_version(&mut self) -> &mut Version {
+        let ver = Version::new(self.version() + 1);
+        self.history.push(ver);
+        self.history.last_mut().unwrap()
+    }
+
+    /// Create a single version which inserts the current tree
+    fn squash(&self, ver: u64) -> Version {
+        let mut squashed = Version::new(ver);
+        for (key, val) in &self.tree {
+            squashed.insert(key, val.clone());
+        }
+        squashed
+    }
 }
 
 impl AutoEncoder for DataBody {}
@@ -31,12 +111,73 @@ impl Body for DataBody {
         self.tree.get(key)
     }
 
+    /// Set a field, recording the change as a new version
+    ///
+    /// Setting a field to the value it already has is a no-op
     fn set_field(&mut self, key: &str, value: Payload) -> Option<()> {
-        self.tree.insert(key.to_owned(), value)?;
+        if self.tree.get(key) != Some(&value) {
+            self.next_version().insert(key, value.clone());
+            self.tree.insert(key.to_owned(), value);
+        }
+        Some(())
+    }
+
+    /// Remove a field, recording the deletion as a new version
+    fn remove_field(&mut self, key: &str) -> Option<Payload> {
+        let old = self.tree.remove(key)?;
+        self.next_version().delete(key);
+        Some(old)
+    }
+
+    /// Set a nested value in place, recording only the changed path
+    fn set_path(&mut self, path: &str, value: Payload) -> Option<()> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.set_field(key, value);
+        }
+        if self.get_path(path) == Some(&value) {
+            return Some(());
+        }
+
+        /* Setting a path only fails before anything was created */
+        self.tree
+            .entry(key.to_owned())
+            .or_insert_with(|| Payload::BTreeMap(BTreeMap::new()))
+            .set_path(&rest, value.clone())?;
+        self.next_version()
+            .insert_at(&[&[key], &rest[..]].concat(), value);
         Some(())
     }
 
+    /// Remove a nested value in place, recording only the removed path
+    fn remove_path(&mut self, path: &str) -> Option<Payload> {
+        let (key, rest) = split_path(path)?;
+        if rest.is_empty() {
+            return self.remove_field(key);
+        }
+
+        let old = self.tree.get_mut(key)?.remove_path(&rest)?;
+        self.next_version().delete_at(&[&[key], &rest[..]].concat());
+        Some(old)
+    }
+
+    /// Wipe all values and their history
+    fn zeroize(&mut self) {
+        self.tree.values_mut().for_each(Payload::zeroize);
+        self.history.iter_mut().for_each(Version::zeroize);
+    }
+
+    /// Squash the history into a single version
+    ///
+    /// The remaining version keeps the latest version number,
+    /// so that numbering continues where it left off.
     fn flatten(&mut self) -> Option<()> {
-        None
+        let ver = self.version();
+        self.history.clear();
+        if ver > 0 {
+            let squashed = self.squash(ver);
+            self.history.push(squashed);
+        }
+        Some(())
     }
 }
@@ -5,7 +5,7 @@
 
 use crate::lcc::crypto::{random, Key};
 use crate::lcc::traits::{AutoEncoder, Encryptable, EncryptionHandler};
-use crate::lcc::{EncryptedBody, PackedData};
+use crate::lcc::{Codec, EncryptedBody, PackedData};
 
 use super::databody::DataBody;
 use miscreant::{Aead, Aes256SivAead};
@@ -54,16 +54,17 @@ impl AesEngine {
 
 impl EncryptionHandler<DataBody> for AesEngine {
     fn encrypt(&mut self, item: DataBody) -> EncryptedBody {
-        let ser = item.encode().unwrap();
+        let ser = item.to_bytes(Codec::Bincode).unwrap();
         let data = self
-            .encrypt_primitive(&ser.as_bytes().to_vec())
-            .encode()
+            .encrypt_primitive(&ser)
+            .to_bytes(Codec::Bincode)
             .unwrap();
         EncryptedBody { data }
     }
 
+    /// Also decrypts bodies that were encoded as JSON by older versions
     fn decrypt(&mut self, item: EncryptedBody) -> Option<DataBody> {
-        let packed = PackedData::decode(&item.data).ok()?;
-        Some(DataBody::decode(&String::from_utf8(self.decrypt_primitive(&packed)?).ok()?).ok()?)
+        let packed = PackedData::from_bytes(&item.data).ok()?;
+        DataBody::from_bytes(&self.decrypt_primitive(&packed)?).ok()
     }
 }
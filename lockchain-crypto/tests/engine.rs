@@ -0,0 +1,39 @@
+use lockchain_core::crypto::{Key, KeyType};
+use lockchain_core::traits::{AutoEncoder, Body, EncryptionHandler};
+use lockchain_core::{EncryptedBody, PackedData, Payload};
+use lockchain_crypto::{AesEngine, DataBody};
+
+fn body() -> DataBody {
+    let mut body = DataBody::new();
+    body.set_field("user", Payload::Text("alice".into()));
+    body.set_field("key", Payload::Bytes(vec![0; 256]));
+    body
+}
+
+#[test]
+fn encrypt_compactly() {
+    let (mut engine, body) = (AesEngine::new(Key::new(KeyType::Aes256)), body());
+    let encrypted = engine.encrypt(body.clone());
+
+    /* The key is stored twice (in the tree and history), each byte
+     * of it would take up at least two bytes in a list of numbers */
+    assert!(encrypted.data.len() < 1024);
+    assert_eq!(engine.decrypt(encrypted), Some(body));
+}
+
+#[test]
+fn decrypt_legacy_bodies() {
+    let (mut engine, body) = (AesEngine::new(Key::new(KeyType::Aes256)), body());
+    let packed = PackedData::from_bytes(&engine.encrypt(body.clone()).data).unwrap();
+
+    /* Older versions stored packed data as JSON, bytes as lists */
+    let legacy = serde_json::json!({
+        "nonce": packed.nonce,
+        "iv": packed.iv,
+        "data": packed.data,
+    });
+    let encrypted = EncryptedBody {
+        data: legacy.to_string().into_bytes(),
+    };
+    assert_eq!(engine.decrypt(encrypted), Some(body));
+}
@@ -0,0 +1,135 @@
+use lockchain_core::traits::{AutoEncoder, Body};
+use lockchain_core::{Operation, Payload};
+use lockchain_crypto::DataBody;
+
+fn text(s: &str) -> Payload {
+    Payload::Text(s.into())
+}
+
+fn body() -> DataBody {
+    let mut body = DataBody::new();
+    body.set_field("user", text("alice"));
+    body.set_field("password", text("1234"));
+    body.set_field("password", text("hunter2"));
+    body.remove_field("user");
+    body
+}
+
+#[test]
+fn record_changes() {
+    let mut body = body();
+    assert_eq!(body.version(), 4);
+    assert_eq!(body.get_field("user"), None);
+    assert_eq!(body.get_field("password"), Some(&text("hunter2")));
+
+    let ops: Vec<_> = body.history().iter().map(|v| v.ops().to_vec()).collect();
+    assert_eq!(
+        ops,
+        vec![
+            vec![Operation::Insert("user".into(), text("alice"))],
+            vec![Operation::Insert("password".into(), text("1234"))],
+            vec![Operation::Insert("password".into(), text("hunter2"))],
+            vec![Operation::Delete("user".into())],
+        ]
+    );
+
+    /* Unchanged values and missing keys don't create versions */
+    body.set_field("password", text("hunter2"));
+    assert_eq!(body.remove_field("user"), None);
+    assert_eq!(body.version(), 4);
+}
+
+#[test]
+fn recover_old_versions() {
+    let body = body();
+
+    let old = body.at_version(2).unwrap();
+    assert_eq!(old.version(), 2);
+    assert_eq!(old.history().len(), 2);
+    assert_eq!(old.get_field("user"), Some(&text("alice")));
+    assert_eq!(old.get_field("password"), Some(&text("1234")));
+
+    assert_eq!(body.at_version(4), Some(body.clone()));
+    assert_eq!(body.at_version(5), None);
+    assert_eq!(body.at_version(0), None);
+}
+
+#[test]
+fn flatten_history() {
+    let mut body = body();
+    body.flatten().unwrap();
+
+    assert_eq!(body.version(), 4);
+    assert_eq!(body.history().len(), 1);
+    assert_eq!(
+        body.history()[0].ops(),
+        &[Operation::Insert("password".into(), text("hunter2"))]
+    );
+    assert_eq!(body.get_field("password"), Some(&text("hunter2")));
+    assert_eq!(body.at_version(2), None);
+
+    /* Numbering continues after the squashed version */
+    body.set_field("user", text("bob"));
+    assert_eq!(body.version(), 5);
+    assert_eq!(body.at_version(4).unwrap().get_field("user"), None);
+}
+
+#[test]
+fn encode_history() {
+    let body = body();
+    let decoded = DataBody::decode(&body.encode().unwrap()).unwrap();
+    assert_eq!(decoded, body);
+}
+
+#[test]
+fn decode_unversioned() {
+    let body = DataBody::decode(r#"{"tree":{"password":{"Text":"hunter2"}}}"#).unwrap();
+    assert_eq!(body.version(), 1);
+    assert_eq!(body.get_field("password"), Some(&text("hunter2")));
+    assert_eq!(
+        body.at_version(1).unwrap().get_field("password"),
+        Some(&text("hunter2"))
+    );
+}
+
+#[test]
+fn version_nested_changes() {
+    let mut body = DataBody::new();
+    body.set_path("server.password", text("1234")).unwrap();
+    body.set_path("server.password", text("hunter2")).unwrap();
+    assert_eq!(body.remove_path("server.password"), Some(text("hunter2")));
+    assert_eq!(body.version(), 3);
+
+    let old = body.at_version(1).unwrap();
+    assert_eq!(old.get_path("server.password"), Some(&text("1234")));
+    assert_eq!(body.get_path("server.password"), None);
+}
+
+#[test]
+fn record_nested_paths() {
+    let path = |p: &str| p.split('.').map(String::from).collect::<Vec<_>>();
+    let mut body = DataBody::new();
+    body.set_path("server.host", text("example.com")).unwrap();
+    body.set_path("server.login.user", text("alice")).unwrap();
+    body.set_path("server.login.user", text("bob")).unwrap();
+    body.remove_path("server.host").unwrap();
+
+    /* Only the changed values are versioned, not the whole field */
+    assert_eq!(
+        body.history()[2].ops(),
+        &[Operation::InsertAt(path("server.login.user"), text("bob"))]
+    );
+    assert_eq!(
+        body.history()[3].ops(),
+        &[Operation::DeleteAt(path("server.host"))]
+    );
+
+    let old = body.at_version(2).unwrap();
+    assert_eq!(old.get_path("server.host"), Some(&text("example.com")));
+    assert_eq!(old.get_path("server.login.user"), Some(&text("alice")));
+    assert_eq!(body.at_version(4), Some(body.clone()));
+
+    /* Paths through values that aren't maps or lists fail without a version */
+    assert_eq!(body.set_path("server.login.user.name", text("eve")), None);
+    assert_eq!(body.version(), 4);
+}
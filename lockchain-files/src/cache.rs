@@ -0,0 +1,170 @@
+//! Limits on the record bodies a vault keeps in memory
+//!
+//! Pulled bodies stay in memory until they are evicted by the
+//! `CachePolicy` of a vault. Eviction drops the least recently
+//! used bodies first, and zeroizes them if the `Body` supports it
+//! (see `Body::zeroize`). Headers are never evicted.
+//!
+//! Bodies that were changed since the last `sync` are never
+//! evicted, so that no changes are lost. There is no background
+//! thread: idle bodies are evicted the next time the vault is
+//! used, or when calling `FileVault::trim`.
+
+use crate::lcc::Uuid;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Limits for the record bodies a `FileVault` keeps in memory
+///
+/// The default policy is unbounded, keeping every pulled body
+/// until the vault is closed.
+///
+/// ```
+/// # use lockchain_files::CachePolicy;
+/// # use std::time::Duration;
+/// let policy = CachePolicy::default()
+///     .max_entries(64)
+///     .max_bytes(1 << 20)
+///     .max_idle(Duration::from_secs(300));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// The maximum number of loaded bodies
+    pub max_entries: Option<usize>,
+    /// The maximum (encoded) size of all loaded records
+    pub max_bytes: Option<usize>,
+    /// How long a body can go unused before it is evicted
+    pub max_idle: Option<Duration>,
+}
+
+impl CachePolicy {
+    /// Keep at most `max` bodies in memory
+    pub fn max_entries(self, max: usize) -> Self {
+        Self {
+            max_entries: Some(max),
+            ..self
+        }
+    }
+
+    /// Keep at most `max` bytes of records in memory
+    ///
+    /// The most recently used body is always kept,
+    /// even if it is larger than that.
+    pub fn max_bytes(self, max: usize) -> Self {
+        Self {
+            max_bytes: Some(max),
+            ..self
+        }
+    }
+
+    /// Evict bodies that weren't used for longer than `max`
+    pub fn max_idle(self, max: Duration) -> Self {
+        Self {
+            max_idle: Some(max),
+            ..self
+        }
+    }
+}
+
+/// Usage of a single loaded body
+#[derive(Debug)]
+struct Usage {
+    size: usize,
+    tick: Cell<u64>,
+    used: Cell<Instant>,
+}
+
+/// Bookkeeping for the bodies loaded into a vault
+///
+/// Reads only take a shared reference, which is why the
+/// usage counters are kept in `Cell`s.
+#[derive(Debug, Default)]
+pub(crate) struct BodyCache {
+    pub(crate) policy: CachePolicy,
+    entries: HashMap<Uuid, Usage>,
+    clock: Cell<u64>,
+}
+
+impl BodyCache {
+    fn tick(&self) -> u64 {
+        let tick = self.clock.get() + 1;
+        self.clock.set(tick);
+        tick
+    }
+
+    /// Start tracking a body that was just loaded (or created)
+    pub(crate) fn insert(&mut self, id: Uuid, size: usize) {
+        let usage = Usage {
+            size,
+            tick: Cell::new(self.tick()),
+            used: Cell::new(Instant::now()),
+        };
+        self.entries.insert(id, usage);
+    }
+
+    /// Mark a body as used
+    pub(crate) fn touch(&self, id: &Uuid) {
+        if let Some(usage) = self.entries.get(id) {
+            usage.tick.set(self.tick());
+            usage.used.set(Instant::now());
+        }
+    }
+
+    /// Update the sizes of bodies that were just written
+    pub(crate) fn resize(&mut self, sizes: &HashMap<Uuid, usize>) {
+        for (id, &size) in sizes {
+            if let Some(usage) = self.entries.get_mut(id) {
+                usage.size = size;
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &Uuid) {
+        self.entries.remove(id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Find the bodies that need to be evicted to satisfy the policy
+    ///
+    /// Idle bodies are always evicted. After that, the least recently
+    /// used bodies are evicted until the vault is within it's limits,
+    /// but the most recently used one is always kept. Bodies in
+    /// `changed` are never evicted.
+    pub(crate) fn victims(&self, changed: &HashSet<Uuid>) -> Vec<Uuid> {
+        let mut lru: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(id, _)| !changed.contains(id))
+            .collect();
+        lru.sort_by_key(|(_, u)| u.tick.get());
+        let newest = self.entries.values().map(|u| u.tick.get()).max();
+
+        let now = Instant::now();
+        let mut count = self.entries.len();
+        let mut bytes: usize = self.entries.values().map(|u| u.size).sum();
+
+        lru.into_iter()
+            .filter(|(_, u)| {
+                let idle = self
+                    .policy
+                    .max_idle
+                    .is_some_and(|max| now.duration_since(u.used.get()) > max);
+                let full = self.policy.max_entries.is_some_and(|max| count > max)
+                    || self.policy.max_bytes.is_some_and(|max| bytes > max);
+
+                if idle || (full && Some(u.tick.get()) != newest) {
+                    count -= 1;
+                    bytes -= u.size;
+                    true
+                } else {
+                    false
+                }
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
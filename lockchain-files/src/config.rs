@@ -1,17 +1,14 @@
-use std::{
-    error::Error,
-    fmt,
-    fs::{File, OpenOptions as OO},
-    io::{self, Write},
-    path::PathBuf,
-    time::SystemTime,
-};
+use std::{error::Error, fmt, fs::File, io, path::PathBuf, time::SystemTime};
 
+use crate::fs::primitive;
 use crate::utils::FileToString;
 use semver::Version;
 use serde_yaml;
 
-use crate::lcc::{errors::VaultError, VaultType};
+use crate::lcc::{errors::VaultError, Codec, VaultType};
+
+/// The vault format version written by this library
+pub const VAULT_VERSION: &str = "0.3.0";
 
 /// A set of errors around `lockchain-files` configs
 #[derive(Debug)]
@@ -38,6 +35,17 @@ impl fmt::Display for ConfigError {
 
 impl Error for ConfigError {}
 
+impl From<ConfigError> for VaultError {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            ConfigError::IncompatibleVersion(found, supported) => {
+                VaultError::IncompatibleVersion { found, supported }
+            }
+            ConfigError::ConfigCorrupted => VaultError::CorruptedConfig,
+        }
+    }
+}
+
 /// The configuration describing a file vault
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VaultConfig {
@@ -46,6 +54,11 @@ pub struct VaultConfig {
     pub vault_type: ConfigType,
     pub created_at: SystemTime,
     pub modified_at: SystemTime,
+    /// How records and metadata are encoded
+    ///
+    /// Vaults that predate this setting are JSON encoded.
+    #[serde(default)]
+    pub codec: Codec,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,9 +69,9 @@ pub enum ConfigType {
 }
 
 impl VaultConfig {
-    pub fn new(vt: &VaultType) -> Result<Self, VaultError> {
+    pub fn new(vt: &VaultType, codec: Codec) -> Result<Self, VaultError> {
         Ok(Self {
-            version: "0.1".into(),
+            version: VAULT_VERSION.into(),
             vault_type: match vt {
                 &VaultType::SoloUser { .. } => ConfigType::SoloUser,
                 &VaultType::Administrated { .. } => ConfigType::Administrated,
@@ -66,6 +79,7 @@ impl VaultConfig {
             },
             created_at: SystemTime::now(),
             modified_at: SystemTime::now(),
+            codec,
         })
     }
 
@@ -73,10 +87,14 @@ impl VaultConfig {
         let mut cfg_path = vault.clone();
         cfg_path.push("vault.cfg");
 
-        let t = serde_yaml::to_string(self).unwrap();
-        let mut f = OO::new().create(true).write(true).open(cfg_path)?;
-        f.write_all(t.as_bytes())?;
-        Ok(())
+        primitive::atomic_write(&cfg_path, &self.to_bytes()?)
+    }
+
+    /// Serialise a configuration, as it is stored in `vault.cfg`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        serde_yaml::to_string(self)
+            .map(String::into_bytes)
+            .map_err(io::Error::other)
     }
 
     /// Attempts to load a configuration – returning detailed errors
@@ -84,26 +102,47 @@ impl VaultConfig {
         let mut cfg_path = vault.clone();
         cfg_path.push("vault.cfg");
 
-        let cfg: VaultConfig = match File::open(cfg_path.as_path()) {
-            Ok(mut f) => match f.get_string() {
-                Ok(s) => match serde_yaml::from_str(&s) {
-                    Ok(c) => c,
-                    Err(_) => return Err(ConfigError::ConfigCorrupted),
-                },
-                Err(_) => return Err(ConfigError::ConfigCorrupted),
-            },
-            Err(_) => return Err(ConfigError::ConfigCorrupted),
-        };
+        match File::open(cfg_path.as_path()).map(|mut f| f.get_string()) {
+            Ok(Ok(s)) => Self::from_bytes(s.as_bytes()),
+            _ => Err(ConfigError::ConfigCorrupted),
+        }
+    }
+
+    /// Parse the contents of a `vault.cfg`, checking it's version
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ConfigError> {
+        let cfg: VaultConfig =
+            serde_yaml::from_slice(data).map_err(|_| ConfigError::ConfigCorrupted)?;
 
-        let version = match Version::parse(&cfg.version) {
-            Ok(v) => v,
-            Err(_) => return Err(ConfigError::ConfigCorrupted),
+        let version = match parse_version(&cfg.version) {
+            Some(v) => v,
+            None => return Err(ConfigError::ConfigCorrupted),
         };
 
-        if version != Version::parse("0.1").unwrap() {
-            Err(ConfigError::IncompatibleVersion(cfg.version, "0.1".into()))
+        let library = parse_version(VAULT_VERSION).unwrap();
+        if !compatible(&version, &library) {
+            Err(ConfigError::IncompatibleVersion(
+                cfg.version,
+                VAULT_VERSION.into(),
+            ))
         } else {
             Ok(cfg)
         }
     }
 }
+
+/// Check if two format versions share the same vault layout
+///
+/// Patch releases never change the layout of a vault
+pub(crate) fn compatible(a: &Version, b: &Version) -> bool {
+    (a.major, a.minor) == (b.major, b.minor)
+}
+
+/// Parse a vault format version
+///
+/// Older vaults only recorded `major.minor`, which isn't
+/// valid semver, so a missing patch level is assumed to be `0`.
+pub(crate) fn parse_version(v: &str) -> Option<Version> {
+    Version::parse(v)
+        .or_else(|_| Version::parse(&format!("{}.0", v)))
+        .ok()
+}
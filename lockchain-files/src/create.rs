@@ -4,11 +4,10 @@
 use crate::lcc::errors::VaultError;
 use crate::lcc::{
     crypto::{Key, KeyType},
+    state::VaultState,
     traits::{Body, Vault},
-    users::UserStore,
     Generator, VaultType,
 };
-use std::collections::HashMap;
 
 use crate::config::{ConfigError, VaultConfig};
 use crate::fs::Filesystem;
@@ -16,6 +15,8 @@ use crate::FileVault;
 
 impl<T: Body> FileVault<T> {
     /// A small utility to create a new file vault
+    ///
+    /// Fails with `VaultError::AlreadyExists` if the vault folder exists
     pub(crate) fn create(gen: Generator) -> Result<Self, VaultError> {
         let (name, location) = Self::get_path(&gen)?;
         let vault_type = gen
@@ -24,29 +25,36 @@ impl<T: Body> FileVault<T> {
             .ok_or(VaultError::IncompleteGenerator)?;
 
         let fs = Filesystem::new(location, name);
+        if fs.root.exists() {
+            return Err(VaultError::AlreadyExists);
+        }
         fs.scaffold().map_err(|_| VaultError::FailedCreation)?;
+        let lock = fs.lock()?;
+
+        #[cfg(feature = "git-backend")]
+        crate::git::init(&fs.root).map_err(|_| VaultError::FailedCreation)?;
 
-        let config = VaultConfig::new(&vault_type)?;
-        let mut users = UserStore::new();
+        let config = VaultConfig::new(&vault_type, gen.codec.unwrap_or_default())?;
 
         /* At this point we'll have to create some user */
-        use self::VaultType::*;
-        match vault_type {
-            SoloUser { username, secret } => users.add_user(username.clone(), secret.clone()),
-            Administrated { secret } => users.add_user("Admin".into(), secret.clone()),
-        }
+        let mut state = VaultState::create(vault_type);
+        state.dirty.index = true;
 
         let mut me = Self {
             config,
             fs,
-            users,
-            records: HashMap::new(),
-            headers: HashMap::new(),
-            metadata: HashMap::new(),
+            lock,
+            state,
+            cache: Default::default(),
+            sums: Default::default(),
+            dirty_config: true,
+            writer: None,
+            #[cfg(feature = "watch")]
+            watcher: None,
         };
 
         /* Make sure to sync all changes made after scaffold */
-        me.sync();
+        me.sync().map_err(|_| VaultError::FailedCreation)?;
 
         Ok(me)
     }
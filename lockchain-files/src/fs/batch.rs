@@ -0,0 +1,64 @@
+//! A set of file changes that are written together
+
+use std::collections::HashMap;
+use std::io;
+
+use super::{checksum, FileType, Filesystem};
+
+/// Files to write or remove, applied with `Filesystem::apply`
+///
+/// Changing the same file twice only keeps the latest change,
+/// so batches can be merged cheaply before they are written.
+#[derive(Debug, Default)]
+pub struct Batch {
+    files: HashMap<(FileType, String), Option<Vec<u8>>>,
+}
+
+impl Batch {
+    /// Write a file, and return the checksum it will have
+    pub fn write(&mut self, types: FileType, name: &str, contents: Vec<u8>) -> String {
+        let sum = checksum(&contents);
+        self.files.insert((types, name.into()), Some(contents));
+        sum
+    }
+
+    /// Remove a file, if it exists
+    pub fn remove(&mut self, types: FileType, name: &str) {
+        self.files.insert((types, name.into()), None);
+    }
+
+    /// Add the changes of a newer batch, which take precedence
+    pub fn merge(&mut self, newer: Batch) {
+        self.files.extend(newer.files);
+    }
+
+    /// The number of files to write or remove
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl Filesystem {
+    /// Write all changes of a batch
+    ///
+    /// The header index is written last, so that it never
+    /// refers to record files that weren't written yet.
+    pub fn apply(&self, batch: &Batch) -> Result<(), io::Error> {
+        let (index, files): (Vec<_>, Vec<_>) = batch
+            .files
+            .iter()
+            .partition(|((types, _), _)| *types == FileType::Index);
+
+        files
+            .into_iter()
+            .chain(index)
+            .try_for_each(|((types, name), contents)| match contents {
+                Some(contents) => self.write(*types, name, contents.clone()),
+                None => self.remove(*types, name),
+            })
+    }
+}
@@ -0,0 +1,51 @@
+//! Advisory locking for vault directories
+//!
+//! Only one `FileVault` (in any process) may have a vault open at a
+//! time. The lock is taken on a `vault.lock` file in the vault root
+//! and is released when the `VaultLock` is dropped, or the holding
+//! process exits.
+
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// The name of the lock file inside a vault directory
+pub const LOCK_FILE: &str = "vault.lock";
+
+/// An exclusive lock held on a vault directory
+#[derive(Debug)]
+pub struct VaultLock {
+    file: File,
+}
+
+impl VaultLock {
+    /// Attempt to acquire the lock for the vault at `root`
+    ///
+    /// Never blocks. If another handle holds the lock already,
+    /// an error of kind `WouldBlock` is returned.
+    pub fn acquire(root: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(root.join(LOCK_FILE))?;
+
+        file.try_lock_exclusive().map_err(|e| {
+            if e.kind() == fs2::lock_contended_error().kind() {
+                io::Error::new(io::ErrorKind::WouldBlock, e)
+            } else {
+                e
+            }
+        })?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
@@ -9,24 +9,26 @@
 //! which will return either `Ok(())` or the first error in the list
 //! of operations.
 
-use crate::lcc::traits::{AutoEncoder, Body};
+use crate::lcc::crypto::hashing;
+use crate::lcc::errors::VaultError;
+use crate::lcc::traits::AutoEncoder;
 
-use std::collections::HashMap;
 use std::error::Error;
-use std::io::{self, Write};
+use std::io;
 use std::{
-    fs::{self, File, OpenOptions},
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use crate::utils::FileToString;
-use crate::FileVault;
+mod batch;
+mod lock;
+pub(crate) mod primitive;
 
-mod primitive;
-use self::primitive::*;
-use crate::userstore::DiskMirror;
+pub use self::batch::Batch;
+pub use self::lock::{VaultLock, LOCK_FILE};
+pub use self::primitive::{file_name, valid_name, FileType};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Filesystem {
     pub name: String,
     pub path: String,
@@ -49,6 +51,18 @@ impl Filesystem {
         }
     }
 
+    /// Create a representation of an existing vault directory
+    pub(crate) fn at(root: &Path) -> Self {
+        let name = root.file_stem().and_then(|n| n.to_str()).unwrap_or("");
+        let path = root.parent().and_then(|p| p.to_str()).unwrap_or("");
+
+        Self {
+            name: name.to_owned(),
+            path: path.to_owned(),
+            root: root.to_path_buf(),
+        }
+    }
+
     /// Create required directories
     pub fn scaffold(&self) -> Result<(), io::Error> {
         fs::create_dir_all(&self.root)?;
@@ -58,79 +72,168 @@ impl Filesystem {
         Ok(())
     }
 
-    /// Load all files of a certain type into a Vec<String>
-    pub fn fetch<T: AutoEncoder>(&self, types: FileType) -> Result<Vec<T>, Box<Error>> {
-        Ok(fs::read_dir(match types {
-            FileType::Record => self.root.join("records"),
-            FileType::Metadata => self.root.join("metadata"),
-            _ => self.root.clone(),
-        })?
-        .into_iter()
-        .filter_map(|r| r.ok())
-        .filter(|f| match f.file_type() {
-            Ok(vf) => vf.is_file(),
-            _ => false,
+    /// Take the exclusive lock on this vault
+    ///
+    /// Fails with `VaultError::Locked` if the vault is already
+    /// opened somewhere else.
+    pub fn lock(&self) -> Result<VaultLock, VaultError> {
+        VaultLock::acquire(&self.root).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock => VaultError::Locked,
+            _ => VaultError::FailedLoading,
         })
-        .map(|de| de.path())
-        .filter_map(|p| p.into_os_string().into_string().ok())
-        .filter_map(|s| File::open(s).ok())
-        .filter_map(|mut f| f.get_string().ok())
-        .filter_map(|s| T::decode(&s).ok())
-        .collect())
+    }
+
+    /// Load all files of a certain type into a Vec<String>
+    pub fn fetch<T: AutoEncoder>(&self, types: FileType) -> Result<Vec<T>, Box<dyn Error>> {
+        Ok(primitive::list_files(types, &self.root)?
+            .into_iter()
+            .filter_map(|name| self.read(types, &name).ok())
+            .filter_map(|vec| T::from_bytes(&vec).ok())
+            .collect())
     }
 
     /// Retrieve a single record from the cached vault
-    pub fn pull<T: AutoEncoder>(&self, _types: FileType, _id: &str) -> Result<T, Box<Error>> {
-        // Ok(T::decode(
-        //     &File::open(self.root.join(&format!("{}.{}", id, file_ending!(types))))?
-        //         .get_string()?,
-        // )?)
-        unimplemented!()
+    pub fn pull<T: AutoEncoder>(&self, types: FileType, id: &str) -> Result<T, Box<dyn Error>> {
+        let content = self.read(types, id)?;
+        Ok(T::from_bytes(&content)?)
     }
 
-    pub fn sync_vault<T: Body>(&self, vault: &FileVault<T>) -> Result<(), io::Error> {
-        vault.config.save(&self.root)?;
+    /// Write a file, along with it's checksum
+    pub fn write(&self, types: FileType, name: &str, contents: Vec<u8>) -> Result<(), io::Error> {
+        self.write_summed(types, name, contents).map(|_| ())
+    }
+
+    /// Write a file along with it's checksum, and return the checksum
+    pub fn write_summed(
+        &self,
+        types: FileType,
+        name: &str,
+        contents: Vec<u8>,
+    ) -> Result<String, io::Error> {
+        let sum = checksum(&contents);
+        primitive::write_file(types, &self.root, name, contents)?;
         primitive::write_file(
-            FileType::Metadata,
+            FileType::Checksum,
             &self.root,
-            "userstore",
-            vault.users.to_disk(),
+            &sum_name(types, name),
+            sum.clone().into_bytes(),
         )?;
+        Ok(sum)
+    }
 
-        Ok(())
+    /// Read a file, refusing to return data that doesn't match it's checksum
+    ///
+    /// Files without a checksum are returned as they are.
+    pub fn read(&self, types: FileType, name: &str) -> Result<Vec<u8>, io::Error> {
+        let contents = primitive::read_file(types, &self.root, name)?;
+        match self.verify(types, name, &contents)? {
+            Some(false) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Checksum mismatch for '{}'", sum_name(types, name)),
+            )),
+            _ => Ok(contents),
+        }
     }
 
-    /// Respond to a sync request
-    #[deprecated]
-    pub fn sync<T>(&self, _data: &HashMap<String, T>, _types: FileType) -> Result<(), Box<Error>>
-    where
-        T: AutoEncoder,
-    {
-        unimplemented!()
-        // data.into_iter()
-        //     .map(|(k, v)| (k, v.encode().ok()))
-        //     .map(|(k, v)| {
-        //         (
-        //             match types {
-        //                 FileType::Record => self.root.join("records"),
-        //                 FileType::Metadata => self.root.join("metadata"),
-        //                 _ => self.root.join("."),
-        //             }.join(format!("{}.{}", k, file_ending!(types))),
-        //             v,
-        //         )
-        //     }).filter(|(_, v)| v.is_some())
-        //     .map(|(k, v)| (k, v.unwrap()))
-        //     .map(|(path, data): (PathBuf, String)| {
-        //         (OO::new().create(true).write(true).open(path), data)
-        //     }).filter(|(path, _)| path.is_ok())
-        //     .map(|(file, data)| (file.unwrap(), data))
-        //     .for_each(|(mut file, data)| {
-        //         file.write_all(data.as_bytes())
-        //             .expect("Failed to write file!")
-        //     });
-
-        // Ok(())
+    /// Compare file contents against their stored checksum
+    ///
+    /// Returns `None` if no checksum exists for the file
+    pub fn verify(
+        &self,
+        types: FileType,
+        name: &str,
+        contents: &[u8],
+    ) -> Result<Option<bool>, io::Error> {
+        Ok(self
+            .stored_sum(types, name)?
+            .map(|sum| sum == checksum(contents)))
+    }
+
+    /// Read the stored checksum of a file, without reading the file itself
+    ///
+    /// Returns `None` if no checksum exists for the file
+    pub fn stored_sum(&self, types: FileType, name: &str) -> Result<Option<String>, io::Error> {
+        match primitive::read_file(FileType::Checksum, &self.root, &sum_name(types, name)) {
+            Ok(sum) => Ok(Some(String::from_utf8_lossy(&sum).into_owned())),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List the names of all files of a type that have a checksum
+    pub fn checksums(&self, types: FileType) -> Result<Vec<String>, io::Error> {
+        let ending = format!(".{}", types.ending());
+        Ok(primitive::list_files(FileType::Checksum, &self.root)?
+            .into_iter()
+            .filter_map(|name| name.strip_suffix(&ending).map(|n| n.to_owned()))
+            .collect())
+    }
+
+    /// Remove a file, along with it's checksum
+    pub fn remove(&self, types: FileType, name: &str) -> Result<(), io::Error> {
+        ignore_missing(primitive::remove_file(types, &self.root, name))?;
+        ignore_missing(primitive::remove_file(
+            FileType::Checksum,
+            &self.root,
+            &sum_name(types, name),
+        ))
+    }
+
+    /// Move a file out of the way, into the `quarantine` folder
+    ///
+    /// The checksum of the file is removed, the file itself is kept
+    /// around for manual inspection.
+    pub fn quarantine(&self, types: FileType, name: &str) -> Result<PathBuf, io::Error> {
+        let dir = self.root.join("quarantine");
+        fs::create_dir_all(&dir)?;
+
+        let file_name = sum_name(types, name);
+        let mut target = dir.join(&file_name);
+        let mut ctr = 1;
+        while target.exists() {
+            target = dir.join(format!("{}.{}", file_name, ctr));
+            ctr += 1;
+        }
+
+        fs::rename(
+            primitive::type_path(types, &self.root).join(&file_name),
+            &target,
+        )?;
+        ignore_missing(primitive::remove_file(
+            FileType::Checksum,
+            &self.root,
+            &file_name,
+        ))?;
+        Ok(target)
     }
 
     /************* Private utility functions*************/
 }
+
+/// The name of the checksum file for a file of some type
+fn sum_name(types: FileType, name: &str) -> String {
+    file_name(types, name)
+}
+
+/// The name of the file in `checksums/` that holds the checksum of a file
+///
+/// Stores that lay out files like a `FileVault` use this to write
+/// checksums that a `FileVault` can verify.
+pub fn sum_file(types: FileType, name: &str) -> String {
+    file_name(FileType::Checksum, &sum_name(types, name))
+}
+
+/// Hex-encoded blake2 checksum of some file contents
+pub fn checksum(contents: &[u8]) -> String {
+    hashing::checksum(contents)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn ignore_missing(res: Result<(), io::Error>) -> Result<(), io::Error> {
+    match res {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
@@ -2,11 +2,14 @@
 
 #![allow(dead_code)]
 
-use std::fs::OpenOptions;
+use crate::lcc::store;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Result, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// A set of files that exist inside a `FileVault`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
 pub enum FileType {
     /// A data record file
     Record,
@@ -16,25 +19,30 @@ pub enum FileType {
     Checksum,
     /// _The_ config file
     Config,
-    #[doc(hidden)]
-    __NonExhaustive,
+    /// The header index, next to the config
+    Index,
 }
 
-/// Construct a file ending for a specific match result
-macro_rules! file_ending {
-    ($type:expr) => {
-        match $type {
-            &FileType::Record => "record",
-            &FileType::Metadata => "meta",
-            &FileType::Checksum => "sum",
-            &FileType::Config => "cfg",
-            _ => "dat",
+impl FileType {
+    /// The file ending of files of this type, without the dot
+    pub fn ending(self) -> &'static str {
+        match self {
+            FileType::Record => "record",
+            FileType::Metadata => "meta",
+            FileType::Checksum => "sum",
+            FileType::Config => "cfg",
+            FileType::Index => "index",
         }
-    };
+    }
+}
+
+/// The name of a file in the vault, i.e. `<name>.record`
+pub fn file_name(tt: FileType, name: &str) -> String {
+    format!("{}.{}", name, tt.ending())
 }
 
 #[inline]
-fn type_path(tt: FileType, root: &PathBuf) -> PathBuf {
+pub(crate) fn type_path(tt: FileType, root: &PathBuf) -> PathBuf {
     use self::FileType::*;
     let mut path = root.clone();
     match tt {
@@ -47,24 +55,62 @@ fn type_path(tt: FileType, root: &PathBuf) -> PathBuf {
     path
 }
 
+/// The full path of a file in the vault
+pub(crate) fn file_path(tt: FileType, root: &PathBuf, name: &str) -> PathBuf {
+    type_path(tt, root).join(file_name(tt, name))
+}
+
 pub(crate) fn write_file(
     tt: FileType,
     root: &PathBuf,
     name: &str,
     contents: Vec<u8>,
 ) -> Result<()> {
-    let file_name = format!("{}.{}", name, file_ending!(&tt));
+    let file_name = file_name(tt, name);
     let mut path = type_path(tt, &root);
     path.push(file_name);
 
-    let mut file = OpenOptions::new().write(true).create(true).open(path)?;
-    file.write_all(&contents)?;
+    atomic_write(&path, &contents)
+}
+
+/// Write a file so that it's either fully replaced or left untouched
+///
+/// Contents are first written to a temporary file in the same
+/// directory, which is flushed to disk and then renamed over
+/// the target path.
+pub(crate) fn atomic_write(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let tmp = dir.join(format!(
+        ".{}.tmp",
+        path.file_name().and_then(|n| n.to_str()).unwrap_or("file")
+    ));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, path)?;
+    sync_dir(dir)
+}
+
+/// Make sure a rename in a directory is persisted
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()
+}
 
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> Result<()> {
     Ok(())
 }
 
 pub(crate) fn read_file(tt: FileType, root: &PathBuf, name: &str) -> Result<Vec<u8>> {
-    let file_name = format!("{}.{}", name, file_ending!(&tt));
+    let file_name = file_name(tt, name);
     let mut path = type_path(tt, &root);
     path.push(file_name);
 
@@ -72,3 +118,28 @@ pub(crate) fn read_file(tt: FileType, root: &PathBuf, name: &str) -> Result<Vec<
     let mut buffer = Vec::<u8>::new();
     file.read_to_end(&mut buffer).map(|_| buffer)
 }
+
+pub(crate) fn remove_file(tt: FileType, root: &PathBuf, name: &str) -> Result<()> {
+    let file_name = file_name(tt, name);
+    let mut path = type_path(tt, root);
+    path.push(file_name);
+
+    fs::remove_file(path)
+}
+
+/// Check if a record or domain name can be used as a file name
+///
+/// Hidden names are reserved for temporary files.
+pub fn valid_name(name: &str) -> bool {
+    store::valid_key(name)
+}
+
+/// List the names (without file ending) of all files of a type
+pub(crate) fn list_files(tt: FileType, root: &PathBuf) -> Result<Vec<String>> {
+    let ending = format!(".{}", tt.ending());
+    Ok(fs::read_dir(type_path(tt, root))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter_map(|name| name.strip_suffix(&ending).map(|n| n.to_owned()))
+        .collect())
+}
@@ -0,0 +1,328 @@
+//! Versioning of vault directories via git
+//!
+//! Only available with the `git-backend` feature. The vault root
+//! becomes a git repository and every `sync` creates a commit,
+//! naming the records that were added, updated or removed.
+//!
+//! Record files are named after the record id. Names are read
+//! from the record headers, as they were at the time of a commit.
+//!
+//! Because all records are stored encrypted, the repository can be
+//! pushed anywhere to replicate the vault, without exposing secrets.
+
+use crate::lcc::errors::{about, Error as LockError, VaultError};
+use crate::lcc::state::StoredHeader;
+use crate::lcc::traits::{AutoEncoder, Body, Vault};
+use crate::lcc::{Record, Uuid};
+use git2::{
+    build::CheckoutBuilder, Delta, DiffFile, IndexAddOption, Oid, Repository, Signature, Sort, Tree,
+};
+use std::path::Path;
+
+use crate::config::VaultConfig;
+use crate::fs::{primitive, FileType, LOCK_FILE};
+use crate::userstore::DiskMirror;
+use crate::FileVault;
+
+/// Files that are never committed
+const IGNORED: &[&str] = &[LOCK_FILE, ".*.tmp", "quarantine/"];
+
+/// A single commit in the history of a vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The (hex-encoded) commit id
+    pub id: String,
+    /// The commit message
+    pub message: String,
+    /// Commit time, in seconds since the unix epoch
+    pub time: i64,
+    /// Names of the records that were changed by the commit
+    pub records: Vec<String>,
+    /// Ids of the records that were changed by the commit
+    pub ids: Vec<Uuid>,
+}
+
+/// A single record that changed between two trees
+struct Change {
+    delta: Delta,
+    id: Uuid,
+    name: String,
+}
+
+/// Initialise a repository for a vault, if it doesn't have one yet
+pub(crate) fn init(root: &Path) -> Result<(), git2::Error> {
+    if Repository::open(root).is_err() {
+        Repository::init(root)?;
+    }
+
+    let ignore = root.join(".gitignore");
+    if !ignore.exists() {
+        let content = IGNORED.join("\n") + "\n";
+        primitive::atomic_write(&ignore, content.as_bytes())
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Commit the current state of the vault directory
+///
+/// Does nothing if there are no changes since the last commit.
+/// If no message is provided, one is generated from the changes.
+pub(crate) fn commit(root: &Path, message: Option<&str>) -> Result<Option<Oid>, git2::Error> {
+    let repo = Repository::open(root)?;
+
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"].iter(), None)?;
+    index.write()?;
+
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+
+    let message = match parent {
+        Some(ref p) if p.tree_id() == tree.id() => return Ok(None),
+        Some(ref p) => match message {
+            Some(m) => m.to_owned(),
+            None => describe(&changes(&repo, Some(&p.tree()?), &tree)?),
+        },
+        None => message.unwrap_or("Create vault").to_owned(),
+    };
+
+    let sig = repo
+        .signature()
+        .or_else(|_| Signature::now("lockchain", "lockchain@localhost"))?;
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, &message, &tree, &parents)
+        .map(Some)
+}
+
+/// Find all records that changed between two trees, ordered by name
+fn changes(repo: &Repository, old: Option<&Tree>, new: &Tree) -> Result<Vec<Change>, git2::Error> {
+    let diff = repo.diff_tree_to_tree(old, Some(new), None)?;
+    let mut changes: Vec<_> = diff
+        .deltas()
+        .filter_map(|d| {
+            let file = match d.status() {
+                Delta::Deleted => d.old_file(),
+                _ => d.new_file(),
+            };
+            let id = record_id(file.path()?)?;
+            Some(Change {
+                delta: d.status(),
+                id,
+                name: record_name(repo, &file).unwrap_or_else(|| id.to_string()),
+            })
+        })
+        .collect();
+
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(changes)
+}
+
+/// Extract a record id from a path in the repository
+fn record_id(path: &Path) -> Option<Uuid> {
+    if path.parent()? != Path::new("records") {
+        return None;
+    }
+
+    let name = path.file_name()?.to_str()?;
+    Uuid::parse_str(name.strip_suffix(".record")?).ok()
+}
+
+/// Read the name of a record from it's header
+fn record_name(repo: &Repository, file: &DiffFile) -> Option<String> {
+    let blob = repo.find_blob(file.id()).ok()?;
+    let stored = StoredHeader::from_bytes(blob.content()).ok()?;
+    Some(stored.header.name)
+}
+
+/// Generate a commit message from a list of changes
+fn describe(changes: &[Change]) -> String {
+    let group = |delta: Delta| {
+        changes
+            .iter()
+            .filter(|c| c.delta == delta)
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    let parts: Vec<String> = [
+        ("added", group(Delta::Added)),
+        ("updated", group(Delta::Modified)),
+        ("removed", group(Delta::Deleted)),
+    ]
+    .iter()
+    .filter(|(_, names)| !names.is_empty())
+    .map(|(verb, names)| format!("{} {}", verb, names.join(", ")))
+    .collect();
+
+    if parts.is_empty() {
+        "Sync vault metadata".into()
+    } else {
+        format!("Sync vault: {}", parts.join("; "))
+    }
+}
+
+fn failed(e: git2::Error) -> LockError {
+    LockError::from(VaultError::FailedVersioning).caused_by(e)
+}
+
+impl<T: Body> FileVault<T> {
+    /// List the commit history of this vault, newest first
+    ///
+    /// If a record name or id is provided, only commits that
+    /// changed this record are returned. Names are matched
+    /// against the name a record had at the time of a commit,
+    /// unless they belong to a current record.
+    pub fn history(&self, record: Option<&str>) -> Result<Vec<HistoryEntry>, LockError> {
+        self.settle()?;
+        let repo = Repository::open(&self.fs.root).map_err(failed)?;
+        let mut walk = repo.revwalk().map_err(failed)?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+            .map_err(failed)?;
+        walk.push_head().map_err(failed)?;
+
+        let current = record.and_then(|r| self.state.resolve(r));
+
+        let mut history = Vec::new();
+        for id in walk {
+            let commit = repo.find_commit(id.map_err(failed)?).map_err(failed)?;
+            let old = match commit.parent(0) {
+                Ok(p) => Some(p.tree().map_err(failed)?),
+                Err(_) => None,
+            };
+
+            let changes =
+                changes(&repo, old.as_ref(), &commit.tree().map_err(failed)?).map_err(failed)?;
+            let matches = |c: &Change| match current {
+                Some(id) => c.id == id,
+                None => record.is_some_and(|r| c.name == r || c.id.to_string() == r),
+            };
+
+            if record.is_none() || changes.iter().any(matches) {
+                history.push(HistoryEntry {
+                    id: commit.id().to_string(),
+                    message: commit.message().unwrap_or("").to_owned(),
+                    time: commit.time().seconds(),
+                    records: changes.iter().map(|c| c.name.clone()).collect(),
+                    ids: changes.iter().map(|c| c.id).collect(),
+                });
+            }
+        }
+
+        Ok(history)
+    }
+
+    /// Read a record as it was at a past commit
+    ///
+    /// `revision` can be anything git understands, such as
+    /// a commit id, `HEAD~2` or a tag. The record can be given
+    /// by id, by it's current name or by the name it had at
+    /// that revision.
+    pub fn record_at(&self, record: &str, revision: &str) -> Result<Record<T>, LockError> {
+        self.settle()?;
+        let repo = Repository::open(&self.fs.root).map_err(failed)?;
+        let tree = repo
+            .revparse_single(revision)
+            .and_then(|o| o.peel_to_tree())
+            .map_err(|e| about(failed(e), "revision", revision))?;
+
+        let decode = |oid: Oid| -> Result<Record<T>, LockError> {
+            let blob = repo.find_blob(oid).map_err(failed)?;
+            Record::from_bytes(blob.content())
+        };
+        let unknown = || {
+            LockError::from(VaultError::FailedLoading)
+                .context(format!("record '{}' at revision '{}'", record, revision))
+        };
+
+        if let Some(id) = self
+            .state
+            .resolve(record)
+            .or_else(|| Uuid::parse_str(record).ok())
+        {
+            let path = Path::new("records").join(format!("{}.record", id));
+            if let Ok(entry) = tree.get_path(&path) {
+                return decode(entry.id())
+                    .map_err(|e| about(VaultError::FailedLoading, "record", record).caused_by(e));
+            }
+        }
+
+        /* Fall back to the names records had at that revision */
+        let records = tree
+            .get_path(Path::new("records"))
+            .and_then(|e| e.to_object(&repo))
+            .and_then(|o| o.peel_to_tree())
+            .map_err(|_| unknown())?;
+
+        records
+            .iter()
+            .filter(|e| e.name().is_some_and(|n| n.ends_with(".record")))
+            .filter_map(|e| decode(e.id()).ok())
+            .find(|r| r.header.name == record)
+            .ok_or_else(unknown)
+    }
+
+    /// Bring back a single record from a past commit
+    ///
+    /// The restored record keeps it's id and the name it had at
+    /// that time. If another record uses that name by now, this
+    /// fails with `VaultError::RecordExists`.
+    ///
+    /// The restored record is synced (and committed) right away.
+    pub fn restore_record(&mut self, record: &str, revision: &str) -> Result<(), LockError> {
+        let record = self.record_at(record, revision)?;
+        let id = record.header.id;
+        if self
+            .state
+            .named(&record.header.name)
+            .is_some_and(|other| other != id)
+        {
+            return Err(about(
+                VaultError::RecordExists,
+                "record",
+                &record.header.name,
+            ));
+        }
+
+        self.state.insert(record);
+        self.state.dirty.record(id);
+        self.cache.insert(id, 0);
+
+        self.flush()
+    }
+
+    /// Roll the entire vault back to the state of a past commit
+    ///
+    /// History is never rewritten. Instead a new commit is created,
+    /// which restores the old state. All unsynced changes are lost
+    /// and the in-memory state, including the vault config, is
+    /// reloaded from disk.
+    pub fn rollback(&mut self, revision: &str) -> Result<(), LockError> {
+        self.settle()?;
+        let repo = Repository::open(&self.fs.root).map_err(failed)?;
+        let target = repo
+            .revparse_single(revision)
+            .and_then(|o| o.peel_to_commit())
+            .map_err(|e| about(failed(e), "revision", revision))?;
+
+        repo.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().force()))
+            .map_err(failed)?;
+
+        let short = target.id().to_string()[..8].to_owned();
+        commit(&self.fs.root, Some(&format!("Roll back to {}", short))).map_err(failed)?;
+
+        /* Reload everything that might have changed */
+        self.config = VaultConfig::load(&self.fs.root)
+            .map_err(|e| about(VaultError::from(e), "file", "vault.cfg"))?;
+        self.dirty_config = false;
+        let users = self
+            .fs
+            .read(FileType::Metadata, "userstore")
+            .map_err(|e| about(VaultError::FailedLoading, "file", "userstore").caused_by(e))?;
+        self.state.users = *DiskMirror::from_disk(users)
+            .map_err(|_| about(VaultError::FailedLoading, "file", "userstore"))?;
+        self.fetch()
+    }
+}
@@ -0,0 +1,122 @@
+//! A persistent index of all record headers
+//!
+//! Opening a vault only needs the headers of it's records. Instead
+//! of decoding every record file, they are read from a single
+//! `headers.index` file in the vault root, which is rewritten
+//! whenever records changed. Bodies are only read when a record is pulled.
+//!
+//! Each entry remembers the checksum of the record file it was
+//! taken from. Opening a vault only compares the indexed ids with
+//! the names of the record files, so that no other file has to be
+//! read. If records were added or removed behind the back of the
+//! vault (i.e. by a file-sync tool), or the index itself is missing
+//! or damaged, it is rebuilt from the record files. Records that
+//! changed in place are noticed when they are pulled, which updates
+//! their entry.
+
+use crate::lcc::state::{HeaderIndex, IndexEntry, StoredHeader, INDEX};
+use crate::lcc::traits::{AutoEncoder, Body};
+use crate::lcc::Uuid;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::fs::{primitive, Batch, FileType};
+use crate::FileVault;
+
+/// Record checksums, as listed in the header index
+pub(crate) type Sums = HashMap<Uuid, Option<String>>;
+
+impl<T: Body> FileVault<T> {
+    fn encode_index(&self) -> io::Result<Vec<u8>> {
+        let mut entries: Vec<_> = self
+            .state
+            .headers()
+            .map(|h| IndexEntry {
+                sum: self.sums.get(&h.id).cloned().unwrap_or(None),
+                header: h.clone(),
+            })
+            .collect();
+        entries.sort_by_key(|e| e.header.id);
+
+        self.encode(&HeaderIndex { entries })
+    }
+
+    /// Add the current header index to a batch of writes
+    pub(crate) fn stage_index(&self, batch: &mut Batch) -> io::Result<()> {
+        batch.write(FileType::Index, INDEX, self.encode_index()?);
+        Ok(())
+    }
+
+    fn write_index(&self) -> io::Result<()> {
+        self.fs.write(FileType::Index, INDEX, self.encode_index()?)
+    }
+
+    /// Load the header index, rebuilding it if it is missing or stale
+    pub(crate) fn load_index(&mut self) -> io::Result<()> {
+        match self.read_index()? {
+            Some(index) => {
+                self.state.clear();
+                self.sums.clear();
+                for IndexEntry { sum, header } in index.entries {
+                    self.sums.insert(header.id, sum);
+                    self.state.insert_header(header);
+                }
+                Ok(())
+            }
+            None => self.rebuild_index(),
+        }
+    }
+
+    /// Read the index, if it exists and matches the record files
+    fn read_index(&self) -> io::Result<Option<HeaderIndex>> {
+        let index = match self
+            .fs
+            .read(FileType::Index, INDEX)
+            .ok()
+            .and_then(|c| HeaderIndex::from_bytes(&c).ok())
+        {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        /* Files not named after a record id aren't indexed anyway */
+        let files: HashSet<String> = primitive::list_files(FileType::Record, &self.fs.root)?
+            .into_iter()
+            .filter(|name| Uuid::parse_str(name).is_ok())
+            .collect();
+        let listed = index
+            .entries
+            .iter()
+            .all(|e| files.contains(&e.header.id.to_string()));
+
+        Ok(Some(index).filter(|i| listed && files.len() == i.entries.len()))
+    }
+
+    /// Read the headers of all record files and write a new index
+    ///
+    /// Files that can't be read are skipped, `self_test` reports them.
+    fn rebuild_index(&mut self) -> io::Result<()> {
+        self.state.clear();
+        self.sums.clear();
+
+        for name in primitive::list_files(FileType::Record, &self.fs.root)? {
+            let stored = self
+                .fs
+                .read(FileType::Record, &name)
+                .ok()
+                .and_then(|c| StoredHeader::from_bytes(&c).ok());
+
+            /* Only files named after their record are part of the vault */
+            match stored {
+                Some(StoredHeader { header }) if header.id.to_string() == name => {
+                    let sum = self.fs.stored_sum(FileType::Record, &name)?;
+                    self.sums.insert(header.id, sum);
+                    self.state.insert_header(header);
+                }
+                _ => {}
+            }
+        }
+
+        self.write_index()
+    }
+}
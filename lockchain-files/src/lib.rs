@@ -6,31 +6,58 @@
 //!
 //! All further documentation can be found in `FileVault`
 
+extern crate fs2;
+#[cfg(feature = "git-backend")]
+extern crate git2;
 extern crate lockchain_core as lcc;
+#[cfg(feature = "watch")]
+extern crate notify;
 extern crate semver;
 
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+extern crate serde_json;
 extern crate serde_yaml;
 
-use crate::lcc::traits::{Body, LoadRecord, Vault};
+use crate::lcc::state::VaultState;
+use crate::lcc::traits::{AutoEncoder, Body, LoadRecord, Vault};
 use crate::lcc::{
-    errors::VaultError,
-    users::{Access, Token, UserStore},
-    Generator, Header, MetaDomain, Payload, Record, VaultMetadata,
+    errors::{about, DataError, Error as LockError, VaultError},
+    Generator, MetaDomain, Payload, Record, Uuid, VaultMetadata,
 };
-use std::collections::HashMap;
+use std::io;
 
+mod cache;
 mod config;
 mod create;
 pub mod fs;
+#[cfg(feature = "git-backend")]
+mod git;
+mod index;
 mod load;
+pub mod migrate;
+mod recode;
+mod selftest;
+mod storage;
 mod userstore;
 mod utils;
+#[cfg(feature = "watch")]
+mod watch;
+mod writer;
 
+use crate::cache::BodyCache;
+pub use crate::cache::CachePolicy;
 pub use crate::config::{ConfigError, VaultConfig};
-use crate::fs::Filesystem;
+use crate::fs::{primitive, FileType, Filesystem, VaultLock};
+pub use crate::selftest::SelfTestReport;
+pub use crate::storage::FileStore;
+use crate::writer::Writer;
+
+#[cfg(feature = "git-backend")]
+pub use crate::git::HistoryEntry;
+#[cfg(feature = "watch")]
+pub use crate::watch::{is_conflict_copy, VaultEvent};
 
 /// Persistence mapper to a folder and file structure
 ///
@@ -45,7 +72,10 @@ use crate::fs::Filesystem;
 /// replicating all functionality in memory
 /// while providing async operations on-disk.
 ///
-/// Requests on files are debounced!
+/// Only records, metadata domains and users that changed
+/// are written on `sync`. With `set_background_sync`, writes
+/// are debounced and happen on a background thread, `flush`
+/// waits for them.
 ///
 /// The internal layout should not be assumed
 /// and isn't stabilised with the crate version
@@ -55,174 +85,255 @@ use crate::fs::Filesystem;
 /// The version of a vault is written in it's coniguration
 /// which can be read via `json-compat` shims,
 /// in case the layout and scheme ever changes.
+/// Opening an older vault fails with `VaultError::MigrationRequired`
+/// if it can be upgraded via the `migrate` module.
 ///
 /// The vault folder is safe to copy around –
 /// all vault metadata is kept inside it.
+///
+/// A vault can only be opened by a single `FileVault`
+/// at a time. Attempting to open it from a second handle
+/// (in any process) fails with `VaultError::Locked`.
+/// All files are written atomically.
+///
+/// Pulled record bodies are kept in memory according to a
+/// `CachePolicy`, which is unbounded by default.
+///
+/// Records are stored in files named after their id, so
+/// that any name can be used and records can be renamed.
+/// Their headers are also kept in a single index file, so
+/// that opening a vault doesn't require reading every record.
+///
+/// Files are JSON encoded, unless another `Codec` was chosen
+/// via the `Generator` or `set_codec`.
+///
+/// Every record and metadata file has a checksum, which is
+/// verified before data is read. `self_test` checks the entire
+/// vault and can repair damage done by external tools.
+///
+/// With the `git-backend` feature, the vault folder is also
+/// a git repository and every `sync` creates a commit. See
+/// `history`, `record_at`, `restore_record` and `rollback`.
+///
+/// With the `watch` feature, a vault can `watch` it's folder
+/// for changes made by file-sync tools, see `poll_changes`.
+///
+/// The same folder can also be used as a `FileStore`, to back a
+/// generic `StoreVault` from `lockchain-core`.
 pub struct FileVault<T: Body> {
     /// A representation of the cached vault config
     config: VaultConfig,
     /// Filesystem wrapper utility
     fs: Filesystem,
-    /// A userstore utility derived from Metadata
-    users: UserStore,
-    /// A mapping of loaded records
-    records: HashMap<String, Record<T>>,
-    /// An index of all existing headers
-    headers: HashMap<String, Header>,
-    /// A map of all metadata files
-    metadata: HashMap<String, MetaDomain>,
+    /// The exclusive lock held on the vault directory
+    #[allow(dead_code)]
+    lock: VaultLock,
+    /// Users, record headers, loaded records and metadata files
+    state: VaultState<T>,
+    /// Usage of loaded records, to evict them by policy
+    cache: BodyCache,
+    /// Checksums of the record files the headers were read from
+    sums: index::Sums,
+    /// Whether the config changed since the last sync
+    dirty_config: bool,
+    /// The background writer, if writes are debounced
+    writer: Option<Writer>,
+    /// The watch on the vault folder, if there is one
+    #[cfg(feature = "watch")]
+    watcher: Option<watch::Watcher>,
 }
 
-impl<T: Body> LoadRecord<T> for FileVault<T> {}
-
-impl<T: Body> Vault<T> for FileVault<T> {
-    fn new(gen: Generator) -> Result<Box<FileVault<T>>, VaultError> {
-        Self::create(gen).map(|s| Box::new(s))
-    }
-
-    fn load(name: &str, location: &str) -> Result<Box<Self>, VaultError> {
-        Self::load(name, location).map(|s| Box::new(s))
+impl<T: Body> FileVault<T> {
+    /// Resolve a record and make sure it is loaded
+    fn pulled(&mut self, record: &str) -> Result<(), LockError> {
+        let id = self.state.find(record)?;
+        if !self.state.is_pulled(&id) {
+            self.pull(record)?;
+        }
+        self.cache.touch(&id);
+        Ok(())
     }
 
-    fn create_user(
-        &mut self,
-        _token: Token,
-        _username: &str,
-        _secret: Vec<u8>,
-        _access: Vec<Access>,
-    ) -> Result<(), ()> {
-        unimplemented!()
+    /// The limits for record bodies kept in memory
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.cache.policy
     }
 
-    fn delete_user(&mut self, _token: Token, _username: &str) {
-        unimplemented!()
+    /// Change the limits for record bodies kept in memory
+    ///
+    /// Bodies that exceed the new limits are evicted right away.
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.cache.policy = policy;
+        self.trim();
     }
 
-    fn authenticate(&mut self, _username: &str, _secret: &str) -> Token {
-        unimplemented!()
+    /// Evict all bodies that exceed the cache policy
+    ///
+    /// This happens on every `pull` and `sync` anyway. Call it
+    /// periodically to get rid of idle bodies in between.
+    pub fn trim(&mut self) {
+        for id in self.cache.victims(&self.state.dirty.records) {
+            self.evict(&id);
+        }
     }
 
-    fn deauthenticate(&mut self, _username: &str, _: Token) {
-        unimplemented!()
+    /// Drop a loaded body, zeroizing it first
+    fn evict(&mut self, id: &Uuid) {
+        self.cache.remove(id);
+        self.state.evict(id);
     }
 
-    fn metadata(&self) -> VaultMetadata {
-        unimplemented!()
-    }
-
-    /// Caches all files from disk to memory
-    fn fetch(&mut self) {
-        // self.records.clear();
-        // self.metadata.clear();
-
-        // self.fs
-        //     .fetch::<Record<T>>(FileType::Record)
-        //     .unwrap()
-        //     .into_iter()
-        //     .map(|rec| (rec.header.name.clone(), rec))
-        //     .for_each(|x| {
-        //         self.records.insert(x.0, x.1);
-        //     });
-
-        // self.fs
-        //     .fetch::<MetaDomain>(FileType::Metadata)
-        //     .unwrap()
-        //     .into_iter()
-        //     .map(|rec| (rec.name().into(), rec))
-        //     .for_each(|x| {
-        //         self.metadata.insert(x.0, x.1);
-        //     });
-        unimplemented!()
+    /// Drop all loaded bodies, zeroizing them first
+    fn evict_all(&mut self) {
+        self.cache.clear();
+        self.state.evict_all();
     }
+}
 
-    /// Make sure a single record is loaded
-    fn pull(&mut self, _name: &str) {
-        // self.records.remove(name);
-        // self.records.insert(
-        //     name.to_owned(),
-        //     self.fs.pull::<Record<T>>(FileType::Record, name).unwrap(),
-        // );
-        unimplemented!()
+impl<T: Body> Drop for FileVault<T> {
+    /// Waits for queued writes, but doesn't sync unsynced changes
+    fn drop(&mut self) {
+        self.writer.take();
+        self.evict_all();
     }
+}
 
-    fn sync(&mut self) {
-        self.fs.sync_vault(&self).unwrap();
+impl<T: Body> LoadRecord<T> for FileVault<T> {}
 
-        // self.fs
-        //     .sync::<Record<T>>(&self.records, FileType::Record)
-        //     .unwrap();
-        // self.fs
-        //     .sync::<MetaDomain>(&self.metadata, FileType::Metadata)
-        //     .unwrap();
-        // unimplemented!()
+impl<T: Body> Vault<T> for FileVault<T> {
+    fn new(gen: Generator) -> Result<Box<FileVault<T>>, LockError> {
+        let path = format!(
+            "{}/{}",
+            gen.location.clone().unwrap_or_default(),
+            gen.name.clone().unwrap_or_default()
+        );
+        Self::create(gen)
+            .map(Box::new)
+            .map_err(|e| about(e, "vault", &path))
     }
 
-    fn get_record(&self, _name: &str) -> Option<&Record<T>> {
-        // self.records.get(name)
-        unimplemented!()
+    fn load(name: &str, location: &str) -> Result<Box<Self>, LockError> {
+        Self::load(name, location)
+            .map(Box::new)
+            .map_err(|e| about(e, "vault", format!("{}/{}", location, name)))
     }
 
-    fn contains(&self, _name: &str) -> bool {
-        // self.records.contains_key(name)
-        unimplemented!()
+    lcc::forward_to_state! {
+        state: authenticate, deauthenticate, create_user, delete_user, contains, query,
+            meta_pull_domain, meta_set, meta_get, meta_remove, meta_exists;
+        pulled: rename_record, add_data, remove_data
     }
 
-    fn add_record(&mut self, _key: &str, _category: &str, _tags: Vec<&str>) {
-        // self.records
-        //     .insert(key.to_owned(), Record::new(key, category, tags));
-        unimplemented!()
+    fn metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            name: self.fs.name.clone(),
+            location: self.fs.path.clone(),
+            size: self.state.len(),
+        }
     }
 
-    fn delete_record(&mut self, _record: &str) -> Option<Record<T>> {
-        // self.records.remove(record)
-        unimplemented!()
+    /// Caches all record headers and metadata domains from disk to memory
+    ///
+    /// Record bodies are not kept around, use `pull` to load them.
+    /// Files that can't be read are skipped, `self_test` reports them.
+    /// Unsynced changes to records and domains are lost.
+    fn fetch(&mut self) -> Result<(), LockError> {
+        self.settle()?;
+        self.load_index().map_err(|e| {
+            about(VaultError::FailedLoading, "path", self.fs.root.display()).caused_by(e)
+        })?;
+        let domains = self
+            .fs
+            .fetch::<MetaDomain>(FileType::Metadata)
+            .map_err(|_| VaultError::FailedLoading)?;
+
+        self.evict_all();
+        self.state.reset();
+        self.state.metadata = domains
+            .into_iter()
+            .map(|dom| (dom.name().into(), dom))
+            .collect();
+
+        Ok(())
     }
 
-    fn add_data(&mut self, _record: &str, _key: &str, _data: Payload) -> Option<()> {
-        // self.records.get_mut(record)?.add_data(key, data)
-        unimplemented!()
+    /// Make sure a single record is loaded
+    ///
+    /// Waits for queued writes first, so that the latest version is read.
+    fn pull(&mut self, name: &str) -> Result<(), LockError> {
+        self.settle()?;
+        let id = self.state.find(name)?;
+        let file = id.to_string();
+        let content = self.fs.read(FileType::Record, &file).map_err(|e| {
+            match e.kind() {
+                io::ErrorKind::InvalidData => about(DataError::FailedDecode, "record", name),
+                _ => about(DataError::FailedRead, "record", name),
+            }
+            .caused_by(e)
+        })?;
+        let rec = Record::<T>::from_bytes(&content)
+            .map_err(|e| about(DataError::FailedDecode, "record", name).caused_by(e))?;
+
+        /* The index entry is stale if the file changed since it was written */
+        let sum = self
+            .fs
+            .stored_sum(FileType::Record, &file)
+            .map_err(|e| about(DataError::FailedRead, "record", name).caused_by(e))?;
+        if self.sums.get(&id) != Some(&sum) {
+            self.sums.insert(id, sum);
+            self.state.dirty.index = true;
+        }
+
+        self.state.insert(rec);
+        self.cache.insert(id, content.len());
+        self.trim();
+        Ok(())
     }
 
-    fn get_data(&self, _record: &str, _key: &str) -> Option<&Payload> {
-        // self.records.get(record)?.get_data(key)
-        unimplemented!()
+    /// Writes everything that changed since the last sync
+    ///
+    /// With background sync, changes are only queued. Use `flush`
+    /// to wait for them.
+    fn sync(&mut self) -> Result<(), LockError> {
+        self.persist()?;
+        self.trim();
+        Ok(())
     }
 
-    fn meta_add_domain(&mut self, _domain: &str) -> Option<()> {
-        // if self.metadata.contains_key(domain) {
-        //     None
-        // } else {
-        //     self.metadata.insert(domain.into(), MetaDomain::new(domain));
-        //     Some(())
-        // }
-        unimplemented!()
+    fn get_record(&self, name: &str) -> Option<&Record<T>> {
+        let id = self.state.resolve(name)?;
+        self.cache.touch(&id);
+        self.state.record(&id)
     }
 
-    fn meta_pull_domain(&self, _domain: &str) -> Option<&MetaDomain> {
-        // self.metadata.get(domain)
-        unimplemented!()
+    fn add_record(&mut self, key: &str, category: &str, tags: Vec<&str>) -> Result<(), LockError> {
+        let id = self.state.add_record(key, category, tags)?;
+        self.cache.insert(id, 0);
+        Ok(())
     }
 
-    fn meta_push_domain(&mut self, _domain: MetaDomain) -> Option<()> {
-        // self.metadata
-        //     .insert(domain.name().into(), domain)
-        //     .map_or((), |_| ()) // We don't care about `None`
-        //     .into()
-        unimplemented!()
+    fn delete_record(&mut self, record: &str) -> Result<Record<T>, LockError> {
+        let removed = self.state.delete_record(record)?;
+        self.cache.remove(&removed.header.id);
+        Ok(removed)
     }
 
-    fn meta_set(&mut self, _domain: &str, _name: &str, _data: Payload) -> Option<()> {
-        // self.metadata.get_mut(domain)?.set_field(name, data)
-        unimplemented!()
+    fn get_data(&self, record: &str, key: &str) -> Option<&Payload> {
+        self.get_record(record)?.get_data(key)
     }
 
-    fn meta_get(&mut self, _domain: &str, _name: &str) -> Option<Payload> {
-        // Some(self.metadata.get(domain)?.get_field(name)?.clone())
-        unimplemented!()
+    /// Fails with `VaultError::InvalidName` for names that can't be used as file names
+    fn meta_add_domain(&mut self, domain: &str) -> Result<(), LockError> {
+        if !primitive::valid_name(domain) {
+            return Err(about(VaultError::InvalidName, "domain", domain));
+        }
+        self.state.meta_add_domain(domain)
     }
 
-    fn meta_exists(&self, _domain: &str) -> bool {
-        // self.metadata.contains_key(domain)
-        unimplemented!()
+    fn meta_push_domain(&mut self, domain: MetaDomain) -> Result<(), LockError> {
+        if !primitive::valid_name(domain.name()) {
+            return Err(about(VaultError::InvalidName, "domain", domain.name()));
+        }
+        self.state.meta_push_domain(domain)
     }
 }
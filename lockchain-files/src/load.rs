@@ -1,32 +1,60 @@
-//! A small submodule which handles all aspects of vault creation
-#![allow(unused_imports)]
+//! A small submodule which handles all aspects of vault loading
 
 use crate::lcc::errors::VaultError;
-use crate::lcc::{traits::Body, Generator};
-use std::collections::HashMap;
+use crate::lcc::state::VaultState;
+use crate::lcc::traits::{Body, Vault};
 
 use crate::config::{ConfigError, VaultConfig};
-use crate::fs::Filesystem;
+use crate::fs::{primitive, FileType, Filesystem};
+use crate::migrate::Migrator;
+use crate::userstore::DiskMirror;
 use crate::FileVault;
 
 impl<T: Body> FileVault<T> {
     /// A small utility to load an existing file vault
-    pub(crate) fn load(_name: &str, _location: &str) -> Result<Self, VaultError> {
-        unimplemented!()
-
-        // self.config = match VaultConfig::load(&self.fs.root) {
-        //     Ok(cfg) => cfg,
-        //     _ => return Err(VaultError::FailedLoading),
-        // };
-        // Ok(Box::new(self))
-
-        // Self {
-        //     meta_info: (name.into(), location.into()),
-        //     records: HashMap::new(),
-        //     config: VaultConfig::new(Gene),
-        //     metadata: HashMap::new(),
-        //     fs: Filesystem::new(location, name),
-        //     users: UserStoreMapper::new(),
-        // }.load()
+    ///
+    /// Reads and validates the vault configuration, restores the
+    /// userstore and then rebuilds the header index and metadata map.
+    pub(crate) fn load(name: &str, location: &str) -> Result<Self, VaultError> {
+        let fs = Filesystem::new(location, name);
+        if !fs.root.is_dir() {
+            return Err(VaultError::InvalidPath);
+        }
+
+        let lock = fs.lock()?;
+
+        let config = match VaultConfig::load(&fs.root) {
+            Ok(cfg) => cfg,
+            Err(ConfigError::IncompatibleVersion(found, supported)) => {
+                /* Offer an upgrade if there is a way to do so */
+                return Err(match Migrator::new().check(&found) {
+                    Err(e) => e,
+                    Ok(()) => VaultError::IncompatibleVersion { found, supported },
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let users = primitive::read_file(FileType::Metadata, &fs.root, "userstore")
+            .map_err(|_| VaultError::FailedLoading)
+            .and_then(|vec| DiskMirror::from_disk(vec).map_err(|_| VaultError::FailedLoading))?;
+
+        #[cfg(feature = "git-backend")]
+        crate::git::init(&fs.root).map_err(|_| VaultError::FailedVersioning)?;
+
+        let mut me = Self {
+            config,
+            fs,
+            lock,
+            state: VaultState::new(*users),
+            cache: Default::default(),
+            sums: Default::default(),
+            dirty_config: false,
+            writer: None,
+            #[cfg(feature = "watch")]
+            watcher: None,
+        };
+
+        me.fetch().map_err(|_| VaultError::FailedLoading)?;
+        Ok(me)
     }
 }
@@ -0,0 +1,459 @@
+//! Upgrade vaults between on-disk format versions
+//!
+//! The layout of a file vault isn't stable between minor versions
+//! of this crate. Every change to it comes with a `Migration` step
+//! which upgrades a vault directory from one format version to the
+//! next. A `Migrator` keeps a registry of these steps and chains them
+//! together to bring an old vault up to `VAULT_VERSION`.
+//!
+//! Before any step is applied, the entire vault directory is copied
+//! to a backup folder next to it. All steps can also be run as a
+//! dry-run, which only reports what would be changed.
+//!
+//! ```ignore
+//! let report = Migrator::new().migrate("~/vaults", "personal", false)?;
+//! println!("Backup written to {:?}", report.backup);
+//! ```
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, ConfigError, VaultConfig, VAULT_VERSION};
+use crate::fs::{primitive, FileType, Filesystem, VaultLock, LOCK_FILE};
+use crate::lcc::errors::VaultError;
+use crate::lcc::state::{HeaderIndex, IndexEntry, StoredHeader, INDEX};
+use crate::lcc::traits::AutoEncoder;
+use crate::lcc::{Codec, Uuid};
+use semver::Version;
+use serde_json;
+use serde_yaml::{self, Value};
+
+/// A single upgrade step between two vault format versions
+pub trait Migration {
+    /// The format version this step upgrades from
+    fn source_version(&self) -> Version;
+    /// The format version this step upgrades to
+    fn target_version(&self) -> Version;
+    /// A short description of what the step changes
+    fn describe(&self) -> String;
+    /// Apply the step to the vault directory at `root`
+    ///
+    /// When `dry_run` is set, nothing may be written to disk.
+    /// Returns a list of (planned) changes in human-readable form.
+    fn apply(&self, root: &Path, dry_run: bool) -> io::Result<Vec<String>>;
+}
+
+/// Errors that can occur while migrating a vault
+#[derive(Debug)]
+pub enum MigrationError {
+    /// No chain of registered steps leads between these two versions
+    NoUpgradePath(String, String),
+    /// The vault configuration couldn't be read
+    Config(ConfigError),
+    /// The vault is opened somewhere else
+    Locked,
+    /// An I/O error occured while backing up or upgrading the vault
+    Io(io::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::MigrationError::*;
+        match self {
+            NoUpgradePath(from, to) => write!(f, "No upgrade path from '{}' to '{}'", from, to),
+            Config(e) => write!(f, "{}", e),
+            Locked => write!(f, "Vault is currently opened by another process"),
+            Io(e) => write!(f, "I/O error during migration: {}", e),
+        }
+    }
+}
+
+impl Error for MigrationError {}
+
+impl From<io::Error> for MigrationError {
+    fn from(e: io::Error) -> Self {
+        MigrationError::Io(e)
+    }
+}
+
+impl From<ConfigError> for MigrationError {
+    fn from(e: ConfigError) -> Self {
+        MigrationError::Config(e)
+    }
+}
+
+impl From<MigrationError> for VaultError {
+    fn from(e: MigrationError) -> Self {
+        match e {
+            MigrationError::NoUpgradePath(found, supported) => {
+                VaultError::IncompatibleVersion { found, supported }
+            }
+            MigrationError::Config(e) => e.into(),
+            MigrationError::Locked => VaultError::Locked,
+            MigrationError::Io(_) => VaultError::FailedMigration,
+        }
+    }
+}
+
+/// The outcome of a (possibly dry-run) migration
+#[derive(Debug)]
+pub struct MigrationReport {
+    /// The format version the vault was in
+    pub from: Version,
+    /// The format version the vault is (or would be) in now
+    pub to: Version,
+    /// Where the original vault was backed up to
+    ///
+    /// Is `None` for dry-runs and vaults that were already up to date
+    pub backup: Option<PathBuf>,
+    /// The description and list of changes for each applied step
+    pub steps: Vec<(String, Vec<String>)>,
+}
+
+/// A registry of migration steps
+pub struct Migrator {
+    steps: Vec<Box<dyn Migration>>,
+}
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Migrator {
+    /// Create a migrator with all steps that ship with this crate
+    ///
+    /// - `0.1` → `0.2`: give every record an id and store
+    ///   it in a file named after that id
+    /// - `0.2` → `0.3`: write checksums for files that have none,
+    ///   and a header index
+    pub fn new() -> Self {
+        Self::empty().register(RecordIds).register(SumsAndIndex)
+    }
+
+    /// Create a migrator without any registered steps
+    pub fn empty() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Register an additional migration step
+    pub fn register<M: Migration + 'static>(mut self, step: M) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Compute the chain of steps needed to get from one version to another
+    ///
+    /// Only steps that move a vault to a newer `major.minor` version
+    /// are considered. Steps between patch levels of a version don't
+    /// change the format, so they are never needed.
+    pub fn plan(
+        &self,
+        from: &Version,
+        to: &Version,
+    ) -> Result<Vec<&dyn Migration>, MigrationError> {
+        let mut current = from.clone();
+        let mut plan = Vec::new();
+
+        while !config::compatible(&current, to) {
+            let step = self
+                .steps
+                .iter()
+                .filter(|s| s.target_version() > s.source_version())
+                .filter(|s| !config::compatible(&s.source_version(), &s.target_version()))
+                .find(|s| config::compatible(&s.source_version(), &current))
+                .ok_or_else(|| MigrationError::NoUpgradePath(from.to_string(), to.to_string()))?;
+
+            current = step.target_version();
+            plan.push(step.as_ref());
+        }
+
+        Ok(plan)
+    }
+
+    /// Check if a vault in format `found` can be opened, or upgraded
+    ///
+    /// Returns `VaultError::MigrationRequired` when a chain of steps
+    /// exists to bring the vault up to date, and
+    /// `VaultError::IncompatibleVersion` when it doesn't.
+    pub fn check(&self, found: &str) -> Result<(), VaultError> {
+        let library = config::parse_version(VAULT_VERSION).unwrap();
+        let version = config::parse_version(found).ok_or(VaultError::CorruptedConfig)?;
+
+        if config::compatible(&version, &library) {
+            return Ok(());
+        }
+
+        match self.plan(&version, &library) {
+            Ok(_) => Err(VaultError::MigrationRequired {
+                found: found.into(),
+                supported: VAULT_VERSION.into(),
+            }),
+            Err(_) => Err(VaultError::IncompatibleVersion {
+                found: found.into(),
+                supported: VAULT_VERSION.into(),
+            }),
+        }
+    }
+
+    /// Upgrade the vault `name` in `location` to the current format version
+    ///
+    /// The vault is locked for the duration of the migration and
+    /// backed up before the first step is applied. With `dry_run`
+    /// nothing is written and the report contains planned changes.
+    pub fn migrate(
+        &self,
+        location: &str,
+        name: &str,
+        dry_run: bool,
+    ) -> Result<MigrationReport, MigrationError> {
+        let fs = Filesystem::new(location, name);
+        let _lock = VaultLock::acquire(&fs.root).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock => MigrationError::Locked,
+            _ => MigrationError::Io(e),
+        })?;
+
+        let found = read_version(&fs.root)?;
+        let from = config::parse_version(&found).ok_or(ConfigError::ConfigCorrupted)?;
+        let to = config::parse_version(VAULT_VERSION).unwrap();
+        let plan = self.plan(&from, &to)?;
+
+        let backup = if dry_run || plan.is_empty() {
+            None
+        } else {
+            Some(backup(&fs, &found)?)
+        };
+
+        let mut steps = Vec::new();
+        for step in plan {
+            let changes = step.apply(&fs.root, dry_run)?;
+            if !dry_run {
+                write_version(&fs.root, &step.target_version().to_string())?;
+            }
+
+            steps.push((step.describe(), changes));
+        }
+
+        /* Make sure the result is actually something we can open */
+        if !dry_run {
+            VaultConfig::load(&fs.root)?;
+        }
+
+        Ok(MigrationReport {
+            from,
+            to,
+            backup,
+            steps,
+        })
+    }
+}
+
+/// Give every record an id and name it's file after it (`0.1` → `0.2`)
+///
+/// Records used to be stored in files named after the record.
+/// Files that fail their checksum are left alone, so that a
+/// self-test can deal with them afterwards.
+struct RecordIds;
+
+impl Migration for RecordIds {
+    fn source_version(&self) -> Version {
+        Version::new(0, 1, 0)
+    }
+
+    fn target_version(&self) -> Version {
+        Version::new(0, 2, 0)
+    }
+
+    fn describe(&self) -> String {
+        "Store records by id instead of by name".into()
+    }
+
+    fn apply(&self, root: &Path, dry_run: bool) -> io::Result<Vec<String>> {
+        let fs = Filesystem::at(root);
+        let mut changes = Vec::new();
+
+        for name in primitive::list_files(FileType::Record, &fs.root)? {
+            let mut record: serde_json::Value = match fs
+                .read(FileType::Record, &name)
+                .and_then(|c| serde_json::from_slice(&c).map_err(io::Error::from))
+            {
+                Ok(record) => record,
+                Err(e) => {
+                    changes.push(format!("Skipped record '{}': {}", name, e));
+                    continue;
+                }
+            };
+
+            let header = match record.get_mut("header").and_then(|h| h.as_object_mut()) {
+                Some(header) => header,
+                None => {
+                    changes.push(format!("Skipped record '{}': no header", name));
+                    continue;
+                }
+            };
+
+            let id = match header.get("id").and_then(|id| id.as_str()) {
+                Some(id) => id.to_owned(),
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    header.insert("id".into(), id.clone().into());
+                    id
+                }
+            };
+
+            if id == name {
+                continue;
+            }
+
+            changes.push(format!("Moved record '{}' to '{}'", name, id));
+            if !dry_run {
+                fs.write(FileType::Record, &id, serde_json::to_vec(&record)?)?;
+                fs.remove(FileType::Record, &name)?;
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+/// Write missing checksums and the header index (`0.2` → `0.3`)
+///
+/// Newer versions also frame files written by a binary codec and
+/// store encrypted bodies as base64. Files of older vaults are all
+/// plain JSON, which is still read as it is, so they are left alone.
+struct SumsAndIndex;
+
+impl Migration for SumsAndIndex {
+    fn source_version(&self) -> Version {
+        Version::new(0, 2, 0)
+    }
+
+    fn target_version(&self) -> Version {
+        Version::new(0, 3, 0)
+    }
+
+    fn describe(&self) -> String {
+        "Write checksums and a header index".into()
+    }
+
+    fn apply(&self, root: &Path, dry_run: bool) -> io::Result<Vec<String>> {
+        let fs = Filesystem::at(root);
+        let mut changes = Vec::new();
+        if !dry_run {
+            fs::create_dir_all(primitive::type_path(FileType::Checksum, &fs.root))?;
+        }
+
+        for tt in [FileType::Record, FileType::Metadata] {
+            for name in primitive::list_files(tt, &fs.root)? {
+                if fs.stored_sum(tt, &name)?.is_some() {
+                    continue;
+                }
+
+                changes.push(format!(
+                    "Added checksum for '{}'",
+                    primitive::file_name(tt, &name)
+                ));
+                if !dry_run {
+                    let contents = primitive::read_file(tt, &fs.root, &name)?;
+                    fs.write(tt, &name, contents)?;
+                }
+            }
+        }
+
+        /* Files not named after their record aren't part of the vault */
+        let mut entries = Vec::new();
+        for name in primitive::list_files(FileType::Record, &fs.root)? {
+            let stored = primitive::read_file(FileType::Record, &fs.root, &name)
+                .ok()
+                .and_then(|c| StoredHeader::from_bytes(&c).ok());
+            match stored {
+                Some(StoredHeader { header }) if header.id.to_string() == name => {
+                    let sum = fs.stored_sum(FileType::Record, &name)?;
+                    entries.push(IndexEntry { sum, header });
+                }
+                _ => changes.push(format!("Skipped record '{}': not readable", name)),
+            }
+        }
+        entries.sort_by_key(|e| e.header.id);
+
+        changes.push(format!("Indexed {} records", entries.len()));
+        if !dry_run {
+            let index = HeaderIndex { entries }
+                .to_bytes(Codec::Json)
+                .map_err(io::Error::other)?;
+            fs.write(FileType::Index, INDEX, index)?;
+        }
+
+        Ok(changes)
+    }
+}
+
+/// Read the raw format version of a vault
+///
+/// The config is read as a generic YAML document so that
+/// older layouts of the config itself can still be parsed.
+fn read_version(root: &Path) -> Result<String, MigrationError> {
+    let content =
+        fs::read_to_string(root.join("vault.cfg")).map_err(|_| ConfigError::ConfigCorrupted)?;
+    let cfg: Value = serde_yaml::from_str(&content).map_err(|_| ConfigError::ConfigCorrupted)?;
+
+    cfg.get("version")
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_owned())
+        .ok_or_else(|| ConfigError::ConfigCorrupted.into())
+}
+
+/// Bump the format version in a vault config, leaving all other fields alone
+fn write_version(root: &Path, version: &str) -> Result<(), MigrationError> {
+    let path = root.join("vault.cfg");
+    let content = fs::read_to_string(&path)?;
+    let mut cfg: Value =
+        serde_yaml::from_str(&content).map_err(|_| ConfigError::ConfigCorrupted)?;
+
+    match cfg.as_mapping_mut() {
+        Some(map) => map.insert("version".into(), version.into()),
+        None => return Err(ConfigError::ConfigCorrupted.into()),
+    };
+
+    let content = serde_yaml::to_string(&cfg).map_err(|_| ConfigError::ConfigCorrupted)?;
+    Ok(primitive::atomic_write(&path, content.as_bytes())?)
+}
+
+/// Copy the vault directory next to itself, before it is modified
+fn backup(fs: &Filesystem, version: &str) -> io::Result<PathBuf> {
+    let base = PathBuf::from(&fs.path).join(format!("{}.vault.{}.backup", fs.name, version));
+
+    let mut target = base.clone();
+    let mut ctr = 1;
+    while target.exists() {
+        target = PathBuf::from(format!("{}-{}", base.display(), ctr));
+        ctr += 1;
+    }
+
+    copy_dir(&fs.root, &target)?;
+    Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == LOCK_FILE {
+            continue;
+        }
+
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Switching a vault to another codec
+//!
+//! Files written by any codec can be read, so changing the
+//! codec of a vault doesn't require converting it right away.
+//! Every file is written with the new codec the next time it
+//! changes, until then the vault contains a mix of both.
+//! `FileVault::recode` converts all remaining files at once.
+
+use crate::lcc::errors::{about, Error as LockError, VaultError};
+use crate::lcc::state::INDEX;
+use crate::lcc::traits::{AutoEncoder, Body};
+use crate::lcc::users::UserStore;
+use crate::lcc::{Codec, MetaDomain, Record};
+use std::io;
+
+use crate::fs::{Batch, FileType};
+use crate::writer::write_out;
+use crate::FileVault;
+
+impl<T: Body> FileVault<T> {
+    /// The codec that files are written with
+    pub fn codec(&self) -> Codec {
+        self.config.codec
+    }
+
+    /// Write files with a different codec from now on
+    ///
+    /// Existing files are converted as they change, or all at
+    /// once by `recode`. Takes effect on the next `sync`.
+    pub fn set_codec(&mut self, codec: Codec) {
+        if self.config.codec != codec {
+            self.config.codec = codec;
+            self.dirty_config = true;
+        }
+    }
+
+    /// Rewrite all files that were written with a different codec
+    ///
+    /// Syncs all changes first, then returns the number of
+    /// files that were converted.
+    pub fn recode(&mut self) -> Result<usize, LockError> {
+        self.flush()?;
+        let batch = self.outdated().map_err(|e| {
+            about(VaultError::FailedSync, "path", self.fs.root.display()).caused_by(e)
+        })?;
+
+        let count = batch.len();
+        if count > 0 {
+            write_out(&self.fs, &batch)?;
+        }
+        Ok(count)
+    }
+
+    /// Convert all files that use another codec, and stage the index if needed
+    fn outdated(&mut self) -> io::Result<Batch> {
+        let mut batch = Batch::default();
+        let mut sums = Vec::new();
+
+        for id in self.state.headers().map(|h| h.id) {
+            let name = id.to_string();
+            if let Some(contents) = self.convert::<Record<T>>(FileType::Record, &name)? {
+                sums.push((id, batch.write(FileType::Record, &name, contents)));
+            }
+        }
+
+        if let Some(contents) = self.convert::<UserStore>(FileType::Metadata, "userstore")? {
+            batch.write(FileType::Metadata, "userstore", contents);
+        }
+        for name in self.state.metadata.keys() {
+            if let Some(contents) = self.convert::<MetaDomain>(FileType::Metadata, name)? {
+                batch.write(FileType::Metadata, name, contents);
+            }
+        }
+
+        let index = self.fs.read(FileType::Index, INDEX).ok();
+        let current = index.and_then(|c| Codec::detect(&c));
+        if current != Some(self.config.codec) || !sums.is_empty() {
+            for (id, sum) in sums {
+                self.sums.insert(id, Some(sum));
+            }
+            self.stage_index(&mut batch)?;
+        }
+        Ok(batch)
+    }
+
+    /// Re-encode a file, unless it already uses the current codec
+    ///
+    /// Files that can't be decoded are left alone.
+    fn convert<V: AutoEncoder>(&self, tt: FileType, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let contents = match self.fs.read(tt, name) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if Codec::detect(&contents) == Some(self.config.codec) {
+            return Ok(None);
+        }
+        match V::from_bytes(&contents) {
+            Ok(value) => self.encode(&value).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
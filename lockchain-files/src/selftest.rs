@@ -0,0 +1,177 @@
+//! Verifies the integrity of a vault against it's checksums
+//!
+//! Every record and metadata file written by a `FileVault` has a
+//! blake2 checksum in the `checksums` folder. External tools (such as
+//! file-sync daemons) can damage or add files behind the back of the
+//! vault, which is caught here before bad data is read.
+
+use crate::lcc::errors::{about, Error as LockError, VaultError};
+use crate::lcc::traits::{AutoEncoder, Body};
+use crate::lcc::users::UserStore;
+use crate::lcc::{MetaDomain, Record, Uuid};
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+
+use crate::fs::{primitive, FileType};
+use crate::userstore::DiskMirror;
+use crate::FileVault;
+
+/// The result of a vault self-test
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Files that have a checksum, but don't exist anymore
+    pub missing: Vec<(FileType, String)>,
+    /// Files that exist, but have no checksum
+    pub orphaned: Vec<(FileType, String)>,
+    /// Files that don't match their checksum
+    pub mismatched: Vec<(FileType, String)>,
+    /// Files that were moved to the `quarantine` folder during repair
+    pub quarantined: Vec<PathBuf>,
+}
+
+impl SelfTestReport {
+    /// Returns `true` if no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.orphaned.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl<T: Body> FileVault<T> {
+    /// Check all record and metadata files against their checksums
+    ///
+    /// Without `repair` this only reports problems.
+    ///
+    /// With `repair` the vault attempts to fix them:
+    ///
+    /// - Damaged files are moved to the `quarantine` folder. If
+    ///   the vault holds an intact copy in memory, it is written back.
+    /// - Files without checksum are adopted if they decode properly
+    ///   and are named after their content (for example files written
+    ///   before checksums existed). Others, like conflict-copies
+    ///   created by sync tools, are quarantined.
+    /// - Checksums of missing files are removed, as well as the
+    ///   records they describe (unless held in memory).
+    ///
+    /// If problems remain after repairing the vault, or files
+    /// can't be read or written, this function fails with
+    /// `VaultError::FailedSelfTest`.
+    pub fn self_test(&mut self, repair: bool) -> Result<SelfTestReport, LockError> {
+        self.settle()?;
+        let root = self.fs.root.display().to_string();
+        let failed = |e: io::Error| about(VaultError::FailedSelfTest, "path", &root).caused_by(e);
+
+        let mut report = self.check().map_err(failed)?;
+        if !repair || report.is_clean() {
+            return Ok(report);
+        }
+
+        report.quarantined = self.repair(&report).map_err(failed)?;
+        match self.check().map_err(failed)? {
+            ref r if r.is_clean() => Ok(report),
+            _ => Err(about(VaultError::FailedSelfTest, "path", &root)),
+        }
+    }
+
+    fn check(&self) -> io::Result<SelfTestReport> {
+        let mut report = SelfTestReport::default();
+
+        for &tt in &[FileType::Record, FileType::Metadata] {
+            let files: HashSet<String> = primitive::list_files(tt, &self.fs.root)?
+                .into_iter()
+                .collect();
+            let sums: HashSet<String> = self.fs.checksums(tt)?.into_iter().collect();
+
+            for name in sums.difference(&files) {
+                report.missing.push((tt, name.clone()));
+            }
+
+            for name in files.difference(&sums) {
+                report.orphaned.push((tt, name.clone()));
+            }
+
+            for name in files.intersection(&sums) {
+                let contents = primitive::read_file(tt, &self.fs.root, name)?;
+                if self.fs.verify(tt, name, &contents)? == Some(false) {
+                    report.mismatched.push((tt, name.clone()));
+                }
+            }
+        }
+
+        report.missing.sort();
+        report.orphaned.sort();
+        report.mismatched.sort();
+        Ok(report)
+    }
+
+    fn repair(&mut self, report: &SelfTestReport) -> io::Result<Vec<PathBuf>> {
+        let mut quarantined = Vec::new();
+
+        for &(tt, ref name) in &report.mismatched {
+            quarantined.push(self.fs.quarantine(tt, name)?);
+            self.restore(tt, name)?;
+        }
+
+        for &(tt, ref name) in &report.orphaned {
+            let contents = primitive::read_file(tt, &self.fs.root, name)?;
+            if self.adoptable(tt, name, &contents) {
+                self.fs.write(tt, name, contents)?;
+            } else {
+                quarantined.push(self.fs.quarantine(tt, name)?);
+            }
+        }
+
+        for &(tt, ref name) in &report.missing {
+            self.fs.remove(tt, name)?;
+            self.restore(tt, name)?;
+        }
+
+        Ok(quarantined)
+    }
+
+    /// Write back a file from memory, or forget about it if that isn't possible
+    fn restore(&mut self, tt: FileType, name: &str) -> io::Result<()> {
+        match tt {
+            FileType::Record => {
+                let id = match Uuid::parse_str(name) {
+                    Ok(id) => id,
+                    Err(_) => return Ok(()),
+                };
+                match self.state.record(&id) {
+                    Some(rec) if rec.body.is_some() => self.fs.write(tt, name, self.encode(rec)?),
+                    _ => {
+                        self.cache.remove(&id);
+                        self.state.dirty.remove(id);
+                        self.state.forget(&id);
+                        Ok(())
+                    }
+                }
+            }
+            FileType::Metadata if name == "userstore" => {
+                self.fs
+                    .write(tt, name, self.state.users.to_disk(self.config.codec))
+            }
+            FileType::Metadata => match self.state.metadata.get(name) {
+                Some(domain) => self.fs.write(tt, name, self.encode(domain)?),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if a file without checksum is a legitimate part of the vault
+    fn adoptable(&self, tt: FileType, name: &str, contents: &[u8]) -> bool {
+        match tt {
+            FileType::Record => Record::<T>::from_bytes(contents)
+                .map(|r| r.header.id.to_string() == name)
+                .unwrap_or(false),
+            FileType::Metadata if name == "userstore" => {
+                UserStore::from_disk(contents.to_vec()).is_ok()
+            }
+            FileType::Metadata => MetaDomain::from_bytes(contents)
+                .map(|d| d.name() == name)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
@@ -1,2 +1,188 @@
-//! Implementation details for filesystem activities such as `pull`, `fetch` and `sync`
+//! A `BlobStore` on top of a vault folder
+//!
+//! Blobs are kept in the same files that a `FileVault` uses, so the
+//! same folder can be opened by either. The checksum of a file is
+//! used as it's revision.
 
+use crate::lcc::errors::{about, DataError, Error as LockError, VaultError};
+use crate::lcc::store::{Blob, BlobStore, Condition, Locate, Revision, Space};
+use crate::lcc::Generator;
+use std::io;
+
+use crate::config::{ConfigError, VaultConfig};
+use crate::fs::{checksum, primitive, FileType, Filesystem, VaultLock};
+use crate::migrate::Migrator;
+
+/// Stores blobs in a vault folder, for use with a `StoreVault`
+///
+/// Holds the lock on the vault folder, like a `FileVault` does.
+/// Keys must be valid file names.
+#[derive(Debug)]
+pub struct FileStore {
+    fs: Filesystem,
+    #[allow(dead_code)]
+    lock: VaultLock,
+}
+
+impl FileStore {
+    /// The folder that blobs are stored in
+    pub fn root(&self) -> &::std::path::Path {
+        &self.fs.root
+    }
+
+    /// The current revision of a file, if it exists
+    ///
+    /// Files without a checksum are summed on the fly.
+    fn revision(&self, tt: FileType, key: &str) -> io::Result<Option<Revision>> {
+        if let Some(sum) = self.fs.stored_sum(tt, key)? {
+            return Ok(Some(sum));
+        }
+        match primitive::read_file(tt, &self.fs.root, key) {
+            Ok(contents) => Ok(Some(checksum(&contents))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check a condition against the file a blob is stored in
+    fn check(&self, space: Space, key: &str, condition: &Condition) -> Result<FileType, LockError> {
+        let tt = FileType::from(space);
+        if !primitive::valid_name(key) {
+            return Err(about(VaultError::InvalidName, "key", key));
+        }
+        if *condition != Condition::Any {
+            let current = self.revision(tt, key).map_err(|e| failed_read(key, e))?;
+            if !condition.holds(current.as_ref()) {
+                return Err(Condition::failed(space, key));
+            }
+        }
+        Ok(tt)
+    }
+}
+
+impl BlobStore for FileStore {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        let tt = FileType::from(space);
+        let data = match self.fs.read(tt, key) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(failed_read(key, e)),
+        };
+        let revision = match self.fs.stored_sum(tt, key) {
+            Ok(Some(sum)) => sum,
+            Ok(None) => checksum(&data),
+            Err(e) => return Err(failed_read(key, e)),
+        };
+        Ok(Some(Blob { data, revision }))
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        let tt = self.check(space, key, condition)?;
+        self.fs
+            .write_summed(tt, key, data)
+            .map_err(|e| about(DataError::FailedWrite, "key", key).caused_by(e))
+    }
+
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        let tt = self.check(space, key, condition)?;
+        self.fs
+            .remove(tt, key)
+            .map_err(|e| about(DataError::FailedWrite, "key", key).caused_by(e))
+    }
+
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        let tt = FileType::from(space);
+        let names = primitive::list_files(tt, &self.fs.root).map_err(|e| failed_read("*", e))?;
+
+        let mut blobs = Vec::new();
+        for name in names {
+            if let Some(rev) = self
+                .revision(tt, &name)
+                .map_err(|e| failed_read(&name, e))?
+            {
+                blobs.push((name, rev));
+            }
+        }
+        Ok(blobs)
+    }
+}
+
+impl Locate for FileStore {
+    /// Set up a new vault folder, with a configuration
+    ///
+    /// Fails with `VaultError::AlreadyExists` if the folder exists
+    fn create(gen: &Generator) -> Result<Self, LockError> {
+        let (name, location) = match gen {
+            &Generator {
+                name: Some(ref n),
+                location: Some(ref l),
+                ..
+            } => (n, l),
+            _ => return Err(VaultError::IncompleteGenerator.into()),
+        };
+        let vault_type = gen
+            .user_type
+            .as_ref()
+            .ok_or(VaultError::IncompleteGenerator)?;
+
+        let fs = Filesystem::new(location, name);
+        if fs.root.exists() {
+            return Err(about(VaultError::AlreadyExists, "path", fs.root.display()));
+        }
+        fs.scaffold().map_err(|e| {
+            about(VaultError::FailedCreation, "path", fs.root.display()).caused_by(e)
+        })?;
+        let lock = fs.lock()?;
+
+        VaultConfig::new(vault_type, gen.codec.unwrap_or_default())?
+            .save(&fs.root)
+            .map_err(|e| {
+                about(VaultError::FailedCreation, "path", fs.root.display()).caused_by(e)
+            })?;
+        Ok(Self { fs, lock })
+    }
+
+    /// Open an existing vault folder
+    ///
+    /// Fails like `FileVault::load` for vaults that need to be migrated.
+    fn open(name: &str, location: &str) -> Result<Self, LockError> {
+        let fs = Filesystem::new(location, name);
+        if !fs.root.is_dir() {
+            return Err(about(VaultError::InvalidPath, "path", fs.root.display()));
+        }
+        let lock = fs.lock()?;
+
+        match VaultConfig::load(&fs.root) {
+            Ok(_) => Ok(Self { fs, lock }),
+            Err(ConfigError::IncompatibleVersion(found, supported)) => {
+                Err(match Migrator::new().check(&found) {
+                    Err(e) => e,
+                    Ok(()) => VaultError::IncompatibleVersion { found, supported },
+                }
+                .into())
+            }
+            Err(e) => Err(VaultError::from(e).into()),
+        }
+    }
+}
+
+/// The files a space is stored in
+impl From<Space> for FileType {
+    fn from(space: Space) -> Self {
+        match space {
+            Space::Records => FileType::Record,
+            Space::Metadata => FileType::Metadata,
+            Space::Index => FileType::Index,
+        }
+    }
+}
+
+fn failed_read(key: &str, e: io::Error) -> LockError {
+    about(DataError::FailedRead, "key", key).caused_by(e)
+}
@@ -1,20 +1,20 @@
 //! Implements serialization, desrialization for UserStore
 
-use crate::lcc::{errors::DataError, traits::AutoEncoder, users::UserStore};
+use crate::lcc::{errors::DataError, traits::AutoEncoder, users::UserStore, Codec};
 
 pub trait DiskMirror {
-    fn to_disk(&self) -> Vec<u8>;
+    fn to_disk(&self, codec: Codec) -> Vec<u8>;
     fn from_disk(_: Vec<u8>) -> Result<Box<Self>, DataError>;
 }
 
 impl DiskMirror for UserStore {
-    fn to_disk(&self) -> Vec<u8> {
-        self.encode().unwrap().into_bytes()
+    fn to_disk(&self, codec: Codec) -> Vec<u8> {
+        self.to_bytes(codec).unwrap()
     }
 
     fn from_disk(vec: Vec<u8>) -> Result<Box<Self>, DataError> {
-        Self::decode(::std::str::from_utf8(vec.as_slice()).map_err(|_| DataError::FailedDecode)?)
-            .map(|s| Box::new(s))
+        Self::from_bytes(&vec)
+            .map(Box::new)
             .map_err(|_| DataError::FailedDecode)
     }
 }
@@ -0,0 +1,308 @@
+//! Noticing changes made to a vault folder by other tools
+//!
+//! Vault folders are meant to be synced with file-sync tools,
+//! which change files while a vault is open. With the `watch`
+//! feature, a `FileVault` can watch it's folder (via inotify
+//! on Linux) and apply those changes to it's headers and
+//! metadata domains.
+//!
+//! Changes are collected in the background, and applied when
+//! calling `FileVault::poll_changes`. Files are only read once
+//! they match their checksum, so half-synced files are picked
+//! up once the sync tool is done with them.
+//!
+//! If something changed on disk that also has unsynced changes
+//! in memory, nothing is applied and a conflict is reported
+//! instead. The in-memory version overwrites the file on the
+//! next `sync`, unless it is reloaded via `fetch`.
+
+use crate::lcc::errors::{about, Error as LockError, VaultError};
+use crate::lcc::state::StoredHeader;
+use crate::lcc::traits::{AutoEncoder, Body};
+use crate::lcc::{MetaDomain, Uuid};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use crate::fs::{primitive, FileType};
+use crate::FileVault;
+
+/// Something that changed in the vault folder
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VaultEvent {
+    /// A new record file appeared, it's header was added
+    RecordAdded(Uuid),
+    /// A record file changed, it's header was reloaded
+    ///
+    /// If the body was loaded, it is dropped and needs
+    /// to be pulled again.
+    RecordChanged(Uuid),
+    /// A record file was removed, and so was the record
+    RecordRemoved(Uuid),
+    /// A record file changed or was removed, while
+    /// the record has unsynced changes
+    RecordConflict(Uuid),
+    /// A metadata domain was added or changed, and reloaded
+    DomainChanged(String),
+    /// A metadata domain file was removed, and so was the domain
+    DomainRemoved(String),
+    /// A metadata domain file changed or was removed, while
+    /// the domain has unsynced changes
+    DomainConflict(String),
+    /// A sync tool created a conflict copy of a vault file
+    ///
+    /// Conflict copies are never loaded. Use `self_test` to
+    /// quarantine them, after merging them by hand.
+    ConflictCopy(PathBuf),
+}
+
+/// The file a change happened to
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Touched {
+    File(FileType, String),
+    ConflictCopy(PathBuf),
+}
+
+/// A running watch on a vault folder
+pub(crate) struct Watcher {
+    _watcher: RecommendedWatcher,
+    /// The vault folder, as it appears in events
+    root: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+}
+
+/// Check if a file name looks like a conflict copy made by a sync tool
+///
+/// This recognises copies made by Syncthing, Dropbox, Nextcloud
+/// and ownCloud, which keep the original name as a prefix.
+pub fn is_conflict_copy(file_name: &str) -> bool {
+    let name = file_name.to_lowercase();
+    ["sync-conflict", "conflicted copy", "_conflict-"]
+        .iter()
+        .any(|marker| name.contains(marker))
+}
+
+/// Find out which vault file a path refers to
+fn classify(root: &Path, path: &Path) -> Option<Touched> {
+    let dir = path.parent()?.strip_prefix(root).ok()?.to_str()?;
+    let file = path.file_name()?.to_str()?;
+
+    let (types, name) = match dir {
+        "records" => (FileType::Record, file.strip_suffix(".record")?),
+        "metadata" => (FileType::Metadata, file.strip_suffix(".meta")?),
+        "checksums" => match file.strip_suffix(".record.sum") {
+            Some(name) => (FileType::Record, name),
+            None => (FileType::Metadata, file.strip_suffix(".meta.sum")?),
+        },
+        _ => return None,
+    };
+
+    if !is_conflict_copy(name) {
+        Some(Touched::File(types, name.to_owned()))
+    } else if dir == "checksums" {
+        None
+    } else {
+        Some(Touched::ConflictCopy(path.to_path_buf()))
+    }
+}
+
+impl<T: Body> FileVault<T> {
+    /// Start watching the vault folder for changes made by other tools
+    ///
+    /// Changes are applied by `poll_changes`.
+    pub fn watch(&mut self) -> Result<(), LockError> {
+        let (sender, events) = mpsc::channel();
+        let (watcher, root) = self
+            .fs
+            .root
+            .canonicalize()
+            .map_err(notify::Error::io)
+            .and_then(|root| {
+                let mut watcher = notify::recommended_watcher(move |event| {
+                    let _ = sender.send(event);
+                })?;
+                watcher.watch(&root, RecursiveMode::Recursive)?;
+                Ok((watcher, root))
+            })
+            .map_err(|e| {
+                about(VaultError::FailedInitalise, "path", self.fs.root.display()).caused_by(e)
+            })?;
+
+        self.watcher = Some(Watcher {
+            _watcher: watcher,
+            root,
+            events,
+        });
+        Ok(())
+    }
+
+    /// Stop watching the vault folder
+    pub fn unwatch(&mut self) {
+        self.watcher = None;
+    }
+
+    /// Apply all changes made to the vault folder since the last call
+    ///
+    /// Returns what changed, ordered by kind. Changes made by this
+    /// vault itself are not reported. If the vault isn't watched,
+    /// nothing changes.
+    pub fn poll_changes(&mut self) -> Result<Vec<VaultEvent>, LockError> {
+        let mut touched = BTreeSet::new();
+        let mut rescan = false;
+        if let Some(ref watcher) = self.watcher {
+            for event in watcher.events.try_iter() {
+                match event {
+                    Ok(event) => {
+                        rescan |= event.need_rescan();
+                        touched.extend(
+                            event
+                                .paths
+                                .iter()
+                                .filter_map(|p| classify(&watcher.root, p)),
+                        );
+                    }
+                    Err(_) => rescan = true,
+                }
+            }
+        }
+        if touched.is_empty() && !rescan {
+            return Ok(vec![]);
+        }
+
+        /* Make sure our own writes are done, so they aren't taken for external ones */
+        self.settle()?;
+        if rescan {
+            touched.extend(self.everything().map_err(|e| {
+                about(VaultError::FailedLoading, "path", self.fs.root.display()).caused_by(e)
+            })?);
+        }
+
+        let mut events: Vec<_> = touched
+            .into_iter()
+            .filter_map(|touched| match touched {
+                Touched::File(FileType::Record, name) => self.refresh_record(&name),
+                Touched::File(_, name) => self.refresh_domain(&name),
+                Touched::ConflictCopy(path) => Some(VaultEvent::ConflictCopy(path)),
+            })
+            .collect();
+        events.sort();
+        events.dedup();
+        Ok(events)
+    }
+
+    /// All files that are, or should be, in the vault
+    fn everything(&self) -> io::Result<Vec<Touched>> {
+        let mut all = Vec::new();
+        for &tt in &[FileType::Record, FileType::Metadata] {
+            for name in primitive::list_files(tt, &self.fs.root)? {
+                all.push(if is_conflict_copy(&name) {
+                    Touched::ConflictCopy(primitive::file_path(tt, &self.fs.root, &name))
+                } else {
+                    Touched::File(tt, name)
+                });
+            }
+        }
+
+        all.extend(
+            self.state
+                .headers()
+                .map(|h| Touched::File(FileType::Record, h.id.to_string())),
+        );
+        all.extend(
+            self.state
+                .metadata
+                .keys()
+                .map(|name| Touched::File(FileType::Metadata, name.clone())),
+        );
+        Ok(all)
+    }
+
+    /// Reload a record header from disk, if it changed
+    fn refresh_record(&mut self, name: &str) -> Option<VaultEvent> {
+        let id = Uuid::parse_str(name).ok()?;
+        let known = self.state.header(&id).is_some();
+        let dirty = self.state.dirty.records.contains(&id);
+
+        let header = match self.fs.read(FileType::Record, name) {
+            Ok(contents) => {
+                let stored = StoredHeader::from_bytes(&contents).ok()?;
+                Some(stored.header).filter(|h| h.id == id)?
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                /* Records that were never written can't be removed */
+                return match (known, dirty) {
+                    (false, _) => None,
+                    (true, true) if self.sums.contains_key(&id) => {
+                        Some(VaultEvent::RecordConflict(id))
+                    }
+                    (true, true) => None,
+                    (true, false) => {
+                        self.cache.remove(&id);
+                        self.state.forget(&id);
+                        self.sums.remove(&id);
+                        self.state.dirty.index = true;
+                        Some(VaultEvent::RecordRemoved(id))
+                    }
+                };
+            }
+            /* Not fully synced yet, or damaged */
+            Err(_) => return None,
+        };
+
+        let sum = self.fs.stored_sum(FileType::Record, name).ok()?;
+        if known && self.sums.get(&id) == Some(&sum) {
+            return None;
+        }
+        if dirty {
+            return Some(VaultEvent::RecordConflict(id));
+        }
+
+        self.evict(&id);
+        self.state.insert_header(header);
+        self.sums.insert(id, sum);
+        self.state.dirty.index = true;
+        Some(if known {
+            VaultEvent::RecordChanged(id)
+        } else {
+            VaultEvent::RecordAdded(id)
+        })
+    }
+
+    /// Reload a metadata domain from disk, if it changed
+    ///
+    /// The userstore is never reloaded.
+    fn refresh_domain(&mut self, name: &str) -> Option<VaultEvent> {
+        if name == "userstore" {
+            return None;
+        }
+        let dirty = self.state.dirty.domains.contains(name);
+
+        let domain = match self.fs.read(FileType::Metadata, name) {
+            Ok(contents) => MetaDomain::from_bytes(&contents)
+                .ok()
+                .filter(|d| d.name() == name)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return match (self.state.meta_exists(name), dirty) {
+                    (false, _) => None,
+                    (true, true) => Some(VaultEvent::DomainConflict(name.into())),
+                    (true, false) => {
+                        self.state.metadata.remove(name);
+                        Some(VaultEvent::DomainRemoved(name.into()))
+                    }
+                };
+            }
+            Err(_) => return None,
+        };
+
+        if self.state.metadata.get(name) == Some(&domain) {
+            None
+        } else if dirty {
+            Some(VaultEvent::DomainConflict(name.into()))
+        } else {
+            self.state.metadata.insert(name.into(), domain);
+            Some(VaultEvent::DomainChanged(name.into()))
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Writing changes back to disk
+//!
+//! A vault keeps track of which records, metadata domains and
+//! users changed since the last `sync`, and only writes those
+//! files (along with the header index).
+//!
+//! Optionally, writes are handed to a background thread, which
+//! collects them for a debounce interval and then writes them in
+//! one go. This makes `sync` cheap enough to call after every
+//! change, even when changes come in bursts. `FileVault::flush`
+//! blocks until everything is on disk.
+
+use crate::lcc::errors::{about, Error as LockError, VaultError};
+use crate::lcc::state::USERS;
+use crate::lcc::traits::{AutoEncoder, Body, Vault};
+use crate::lcc::Uuid;
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::fs::{Batch, FileType, Filesystem};
+use crate::userstore::DiskMirror;
+use crate::FileVault;
+
+enum Message {
+    Write(Batch),
+    Flush(Sender<Result<(), LockError>>),
+}
+
+/// A background thread that writes batches to disk
+pub(crate) struct Writer {
+    sender: Option<Sender<Message>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Writer {
+    /// Start writing to a vault directory, waiting `debounce` before each write
+    pub(crate) fn spawn(fs: Filesystem, debounce: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run(&fs, &receiver, debounce));
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue a batch, to be written once the debounce interval is over
+    pub(crate) fn send(&self, batch: Batch) -> Result<(), LockError> {
+        self.sender
+            .as_ref()
+            .and_then(|s| s.send(Message::Write(batch)).ok())
+            .ok_or_else(|| VaultError::FailedSync.into())
+    }
+
+    /// Write all queued batches right away, and wait for them
+    ///
+    /// Batches that failed to write are kept and retried, the
+    /// error is returned here.
+    pub(crate) fn flush(&self) -> Result<(), LockError> {
+        let (reply, result) = mpsc::channel();
+        self.sender
+            .as_ref()
+            .and_then(|s| s.send(Message::Flush(reply)).ok())
+            .ok_or(VaultError::FailedSync)?;
+        result.recv().map_err(|_| VaultError::FailedSync)?
+    }
+}
+
+impl Drop for Writer {
+    /// Write all queued batches and stop the thread
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(fs: &Filesystem, receiver: &Receiver<Message>, debounce: Duration) {
+    let mut pending = Batch::default();
+    let mut due: Option<Instant> = None;
+
+    loop {
+        let message = match due {
+            Some(due) => receiver.recv_timeout(due.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match message {
+            Ok(Message::Write(batch)) => {
+                pending.merge(batch);
+                due = due.or_else(|| Some(Instant::now() + debounce));
+                continue;
+            }
+            Ok(Message::Flush(reply)) => {
+                let _ = reply.send(write(fs, &mut pending));
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = write(fs, &mut pending);
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = write(fs, &mut pending);
+                return;
+            }
+        }
+
+        /* Failed writes are retried after another interval */
+        due = if pending.is_empty() {
+            None
+        } else {
+            Some(Instant::now() + debounce)
+        };
+    }
+}
+
+/// Write a batch, and clear it if that succeeded
+fn write(fs: &Filesystem, pending: &mut Batch) -> Result<(), LockError> {
+    if !pending.is_empty() {
+        write_out(fs, pending)?;
+        *pending = Batch::default();
+    }
+    Ok(())
+}
+
+/// Apply a batch, and commit it with the `git-backend` feature
+pub(crate) fn write_out(fs: &Filesystem, batch: &Batch) -> Result<(), LockError> {
+    fs.apply(batch)
+        .map_err(|e| about(VaultError::FailedSync, "path", fs.root.display()).caused_by(e))?;
+
+    #[cfg(feature = "git-backend")]
+    crate::git::commit(&fs.root, None)
+        .map_err(|e| about(VaultError::FailedVersioning, "path", fs.root.display()).caused_by(e))?;
+
+    Ok(())
+}
+
+impl<T: Body> FileVault<T> {
+    /// Write everything that changed since the last sync
+    ///
+    /// With a background writer, the changes are only queued.
+    pub(crate) fn persist(&mut self) -> Result<(), LockError> {
+        let (batch, sizes) = self.changes().map_err(|e| {
+            about(VaultError::FailedSync, "path", self.fs.root.display()).caused_by(e)
+        })?;
+
+        match self.writer {
+            Some(ref writer) if !batch.is_empty() => writer.send(batch)?,
+            Some(_) => {}
+            None => write_out(&self.fs, &batch)?,
+        }
+
+        self.cache.resize(&sizes);
+        self.state.dirty = Default::default();
+        self.dirty_config = false;
+        Ok(())
+    }
+
+    /// Collect all changed files, along with the sizes of changed records
+    fn changes(&mut self) -> io::Result<(Batch, HashMap<Uuid, usize>)> {
+        let mut batch = Batch::default();
+        let mut sizes = HashMap::new();
+
+        if self.dirty_config {
+            self.config.save(&self.fs.root)?;
+        }
+        let dirty = &self.state.dirty;
+        if dirty.users {
+            let users = self.state.users.to_disk(self.config.codec);
+            batch.write(FileType::Metadata, USERS, users);
+        }
+
+        for id in &dirty.records {
+            if let Some(rec) = self.state.record(id) {
+                let contents = self.encode(rec)?;
+                sizes.insert(*id, contents.len());
+                let sum = batch.write(FileType::Record, &id.to_string(), contents);
+                self.sums.insert(*id, Some(sum));
+            }
+        }
+        for id in &dirty.removed {
+            batch.remove(FileType::Record, &id.to_string());
+            self.sums.remove(id);
+        }
+
+        for name in &dirty.domains {
+            match self.state.metadata.get(name) {
+                Some(domain) => {
+                    batch.write(FileType::Metadata, name, self.encode(domain)?);
+                }
+                None => batch.remove(FileType::Metadata, name),
+            }
+        }
+
+        if dirty.index || dirty.any_records() {
+            self.stage_index(&mut batch)?;
+        }
+
+        Ok((batch, sizes))
+    }
+
+    /// Encode a file with the codec of this vault
+    pub(crate) fn encode<V: AutoEncoder>(&self, value: &V) -> io::Result<Vec<u8>> {
+        value
+            .to_bytes(self.config.codec)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Turn background writes on or off
+    ///
+    /// With `Some(debounce)`, `sync` only queues changes, which are
+    /// written by a background thread `debounce` after the first
+    /// queued change. With `None`, `sync` writes changes right away
+    /// again. Queued changes are written before switching.
+    pub fn set_background_sync(&mut self, debounce: Option<Duration>) -> Result<(), LockError> {
+        self.settle()?;
+        self.writer = debounce.map(|d| Writer::spawn(self.fs.clone(), d));
+        Ok(())
+    }
+
+    /// Sync all changes and block until they are written to disk
+    ///
+    /// Errors of earlier background writes are returned here.
+    pub fn flush(&mut self) -> Result<(), LockError> {
+        self.sync()?;
+        self.settle()
+    }
+
+    /// Wait for the background writer, if there is one
+    pub(crate) fn settle(&self) -> Result<(), LockError> {
+        match self.writer {
+            Some(ref writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
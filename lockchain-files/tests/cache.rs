@@ -0,0 +1,120 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::crypto::DataBody;
+use crate::files::{CachePolicy, FileVault};
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const NAMES: [&str; 3] = ["github", "gitlab", "mail"];
+
+/// The sample vault, with a third record
+fn create(dir: &TempDir) -> FileVault<DataBody> {
+    let mut vault = common::sample(dir);
+    vault.add_record("gitlab", "web", vec![]).unwrap();
+    vault
+        .add_data("gitlab", "password", Payload::Text("hunter3".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    vault
+}
+
+fn loaded(vault: &FileVault<DataBody>) -> Vec<&str> {
+    NAMES
+        .iter()
+        .cloned()
+        .filter(|n| vault.get_record(n).is_some())
+        .collect()
+}
+
+#[test]
+fn evict_least_recently_used() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = create(&dir);
+    assert_eq!(loaded(&vault), NAMES.to_vec());
+
+    vault.set_cache_policy(CachePolicy::default().max_entries(2));
+    assert_eq!(loaded(&vault).len(), 2);
+
+    /* Reading a record keeps it around */
+    vault.pull("github").unwrap();
+    vault.pull("gitlab").unwrap();
+    vault.get_record("github");
+    vault.pull("mail").unwrap();
+    assert_eq!(loaded(&vault), vec!["github", "mail"]);
+
+    /* Headers stay, and evicted bodies can be pulled again */
+    assert!(vault.contains("gitlab"));
+    assert_eq!(vault.metadata().size, 3);
+    vault.pull("gitlab").unwrap();
+    assert_eq!(
+        vault.get_data("gitlab", "password"),
+        Some(&Payload::Text("hunter3".into()))
+    );
+}
+
+#[test]
+fn keep_changed_bodies() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = create(&dir);
+    vault.set_cache_policy(CachePolicy::default().max_entries(1));
+
+    /* Changes are kept in memory until they are synced */
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.add_record("ssh", "server", vec![]).unwrap();
+    vault.pull("mail").unwrap();
+    assert!(vault.get_record("github").is_some());
+    assert!(vault.get_record("ssh").is_some());
+
+    vault.sync().unwrap();
+    assert_eq!(loaded(&vault), Vec::<&str>::new());
+    assert!(vault.get_record("ssh").is_some());
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+}
+
+#[test]
+fn limit_loaded_bytes() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = create(&dir);
+
+    /* The most recently used body is kept, even if it is too large */
+    vault.set_cache_policy(CachePolicy::default().max_bytes(1));
+    assert_eq!(loaded(&vault).len(), 1);
+    vault.pull("gitlab").unwrap();
+    assert_eq!(loaded(&vault), vec!["gitlab"]);
+
+    vault.set_cache_policy(CachePolicy::default().max_bytes(1 << 20));
+    vault.pull("github").unwrap();
+    vault.pull("mail").unwrap();
+    assert_eq!(loaded(&vault), NAMES.to_vec());
+}
+
+#[test]
+fn evict_idle_bodies() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = create(&dir);
+    vault.set_cache_policy(CachePolicy::default().max_idle(Duration::from_millis(50)));
+    assert_eq!(loaded(&vault), NAMES.to_vec());
+
+    thread::sleep(Duration::from_millis(100));
+    vault.pull("mail").unwrap();
+    assert_eq!(loaded(&vault), vec!["mail"]);
+
+    thread::sleep(Duration::from_millis(100));
+    vault.trim();
+    assert!(loaded(&vault).is_empty());
+    assert_eq!(vault.metadata().size, 3);
+}
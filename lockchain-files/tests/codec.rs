@@ -0,0 +1,88 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::lcc::traits::Vault;
+use crate::lcc::{Codec, Payload};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn codec_of(path: &Path) -> Option<Codec> {
+    Codec::detect(&fs::read(path).unwrap())
+}
+
+#[test]
+fn write_with_chosen_codec() {
+    let dir = TempDir::new().unwrap();
+    let vault = common::sample_with(&dir, Codec::Cbor);
+    let root = common::root(&dir);
+
+    assert_eq!(vault.codec(), Codec::Cbor);
+    assert_eq!(
+        codec_of(&common::record_file(&dir, &vault, "github")),
+        Some(Codec::Cbor)
+    );
+    for file in &["metadata/settings.meta", "metadata/userstore.meta"] {
+        assert_eq!(codec_of(&root.join(file)), Some(Codec::Cbor));
+    }
+    assert!(fs::read_to_string(root.join("vault.cfg"))
+        .unwrap()
+        .contains("codec: cbor"));
+    drop(vault);
+
+    let mut vault = common::reopen(&dir);
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    assert!(vault.meta_exists("settings"));
+}
+
+#[test]
+fn migrate_gradually() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample_with(&dir, Codec::Json);
+    let (github, mail) = (
+        common::record_file(&dir, &vault, "github"),
+        common::record_file(&dir, &vault, "mail"),
+    );
+
+    /* Only files that change are converted */
+    vault.set_codec(Codec::Bincode);
+    vault
+        .add_data("github", "password", Payload::Text("hunter3".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    assert_eq!(codec_of(&github), Some(Codec::Bincode));
+    assert_eq!(codec_of(&mail), Some(Codec::Json));
+    drop(vault);
+
+    /* Vaults with mixed codecs can be read */
+    let mut vault = common::reopen(&dir);
+    assert_eq!(vault.codec(), Codec::Bincode);
+    vault.pull("github").unwrap();
+    vault.pull("mail").unwrap();
+    assert_eq!(
+        vault.get_data("mail", "user"),
+        Some(&Payload::Text("bob".into()))
+    );
+
+    /* Everything else is converted at once */
+    assert!(vault.recode().unwrap() > 0);
+    assert_eq!(codec_of(&mail), Some(Codec::Bincode));
+    assert_eq!(vault.recode().unwrap(), 0);
+    drop(vault);
+
+    let mut vault = common::reopen(&dir);
+    assert!(vault.self_test(false).unwrap().is_clean());
+    vault.pull("mail").unwrap();
+    assert_eq!(
+        vault.get_data("mail", "user"),
+        Some(&Payload::Text("bob".into()))
+    );
+}
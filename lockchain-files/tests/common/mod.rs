@@ -0,0 +1,81 @@
+//! Fixtures that are shared between the integration tests
+//!
+//! Every test works on a vault called `test` inside it's own
+//! temporary directory, owned by the solo user `alice`.
+#![allow(dead_code)]
+
+use crate::crypto::DataBody;
+use crate::files::FileVault;
+use crate::lcc::query::Query;
+use crate::lcc::traits::Vault;
+use crate::lcc::{Codec, Generator, Payload, Uuid, VaultType};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// A generator for the test vault, to be finalised by the caller
+pub fn generator(dir: &TempDir) -> Generator {
+    Generator::new()
+        .path("test", dir.path().to_str().unwrap())
+        .user_type(VaultType::SoloUser {
+            username: "alice".into(),
+            secret: vec![1, 2, 3, 4],
+        })
+}
+
+/// Create an empty test vault
+pub fn create(dir: &TempDir) -> FileVault<DataBody> {
+    generator(dir).finalise().unwrap()
+}
+
+/// Load the test vault from disk
+pub fn reopen(dir: &TempDir) -> FileVault<DataBody> {
+    *Vault::load("test", dir.path().to_str().unwrap()).unwrap()
+}
+
+/// The directory of the test vault
+pub fn root(dir: &TempDir) -> PathBuf {
+    dir.path().join("test.vault")
+}
+
+/// The id of a record, pulled or not
+pub fn id(vault: &FileVault<DataBody>, name: &str) -> Uuid {
+    vault
+        .query(&Query::new())
+        .into_iter()
+        .find(|h| h.name == name)
+        .unwrap()
+        .id
+}
+
+/// The file a record of the test vault is stored in
+pub fn record_file(dir: &TempDir, vault: &FileVault<DataBody>, name: &str) -> PathBuf {
+    root(dir).join(format!("records/{}.record", id(vault, name)))
+}
+
+/// Create a test vault with two records and a metadata domain
+///
+/// `github` holds a `password` (`hunter2`) and a `user` (`alice`),
+/// `mail` only a `user` (`bob`). The vault is synced, so everything
+/// is already on disk.
+pub fn sample(dir: &TempDir) -> FileVault<DataBody> {
+    sample_with(dir, Codec::default())
+}
+
+/// The sample vault, written with a specific codec
+pub fn sample_with(dir: &TempDir, codec: Codec) -> FileVault<DataBody> {
+    let mut vault: FileVault<DataBody> = generator(dir).codec(codec).finalise().unwrap();
+    vault.add_record("github", "web", vec!["dev"]).unwrap();
+    vault
+        .add_data("github", "password", Payload::Text("hunter2".into()))
+        .unwrap();
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    vault
+        .add_data("mail", "user", Payload::Text("bob".into()))
+        .unwrap();
+    vault.meta_add_domain("settings").unwrap();
+    vault.sync().unwrap();
+    vault
+}
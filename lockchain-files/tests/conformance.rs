@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate lockchain_core as lcc;
+extern crate lockchain_files as files;
+
+use crate::files::FileVault;
+use crate::lcc::testing::TestBody;
+
+vault_conformance!(file_vault, FileVault<TestBody>);
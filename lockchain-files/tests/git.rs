@@ -0,0 +1,132 @@
+#![cfg(feature = "git-backend")]
+
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::lcc::errors::{Error, VaultError};
+use crate::lcc::traits::Vault;
+use crate::lcc::{Codec, Payload};
+use tempfile::TempDir;
+
+#[test]
+fn commit_on_sync() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+    assert!(dir.path().join("test.vault/.git").is_dir());
+
+    vault.add_record("github", "web", vec![]).unwrap();
+    vault.add_record("gitlab", "web", vec![]).unwrap();
+    vault.sync().unwrap();
+
+    /* Nothing changed, nothing to commit */
+    vault.sync().unwrap();
+
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.delete_record("gitlab").unwrap();
+    vault.sync().unwrap();
+
+    let history = vault.history(None).unwrap();
+    let messages: Vec<_> = history.iter().map(|h| h.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Sync vault: updated github; removed gitlab",
+            "Sync vault: added github, gitlab",
+            "Create vault",
+        ]
+    );
+
+    let github = vault.history(Some("github")).unwrap();
+    assert_eq!(github.len(), 2);
+    assert_eq!(github[0].records, vec!["github", "gitlab"]);
+}
+
+#[test]
+fn recover_old_record() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    vault
+        .add_data("mail", "password", Payload::Text("old".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    vault
+        .add_data("mail", "password", Payload::Text("new".into()))
+        .unwrap();
+    vault.sync().unwrap();
+
+    let old = vault.record_at("mail", "HEAD~1").unwrap();
+    assert_eq!(old.get_data("password"), Some(&Payload::Text("old".into())));
+    assert!(vault.record_at("nothing", "HEAD").is_err());
+
+    vault.restore_record("mail", "HEAD~1").unwrap();
+    assert_eq!(
+        vault.get_data("mail", "password"),
+        Some(&Payload::Text("old".into()))
+    );
+    assert_eq!(vault.history(Some("mail")).unwrap().len(), 3);
+}
+
+#[test]
+fn rollback_vault() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault.add_record("one", "misc", vec![]).unwrap();
+    vault.sync().unwrap();
+    let checkpoint = vault.history(None).unwrap()[0].id.clone();
+
+    vault.delete_record("one").unwrap();
+    vault.add_record("two", "misc", vec![]).unwrap();
+    vault.set_codec(Codec::Cbor);
+    vault.sync().unwrap();
+    assert!(!vault.contains("one"));
+
+    /* The config is rolled back along with the records */
+    vault.rollback(&checkpoint).unwrap();
+    assert!(vault.contains("one"));
+    assert!(!vault.contains("two"));
+    assert_eq!(vault.codec(), Codec::Json);
+    assert!(vault.self_test(false).unwrap().is_clean());
+
+    let latest = &vault.history(None).unwrap()[0];
+    assert_eq!(latest.message, format!("Roll back to {}", &checkpoint[..8]));
+}
+
+#[test]
+fn follow_renames() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    vault.sync().unwrap();
+    vault.rename_record("mail", "email").unwrap();
+    vault.sync().unwrap();
+
+    let history = vault.history(Some("email")).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].message, "Sync vault: updated email");
+    assert_eq!(history[1].records, vec!["mail"]);
+    assert_eq!(history[0].ids, history[1].ids);
+
+    /* Old names still work for past revisions */
+    let old = vault.record_at("mail", "HEAD~1").unwrap();
+    assert_eq!(old.header.name, "mail");
+
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    match vault
+        .restore_record("email", "HEAD~1")
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::RecordExists)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
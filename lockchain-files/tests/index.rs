@@ -0,0 +1,116 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+#[test]
+fn open_from_index() {
+    let dir = TempDir::new().unwrap();
+    let vault = common::sample(&dir);
+    let root = common::root(&dir);
+    let github = common::record_file(&dir, &vault, "github");
+    assert!(root.join("headers.index").is_file());
+    drop(vault);
+
+    /* Headers come from the index, bodies are only read on demand */
+    let mut vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    assert_eq!(vault.get_record("github"), None);
+    assert_eq!(vault.metadata().size, 2);
+
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    assert_eq!(
+        vault.get_data("github", "password"),
+        Some(&Payload::Text("hunter2".into()))
+    );
+    drop(vault);
+
+    /* Damaged record files don't affect the index, only pulling them */
+    let content = fs::read_to_string(&github).unwrap();
+    fs::write(&github, content.replace("hunter2", "hunter3")).unwrap();
+
+    let mut vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    assert!(vault.pull("github").is_err());
+}
+
+#[test]
+fn rebuild_missing_index() {
+    let dir = TempDir::new().unwrap();
+    let vault = common::sample(&dir);
+    let root = common::root(&dir);
+    drop(vault);
+
+    fs::remove_file(root.join("headers.index")).unwrap();
+    let vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    assert!(vault.contains("mail"));
+    assert!(root.join("headers.index").is_file());
+    drop(vault);
+
+    /* A damaged index fails it's checksum and is rebuilt as well */
+    fs::write(root.join("headers.index"), "{\"entries\":[]}").unwrap();
+    let vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    assert!(vault.contains("mail"));
+}
+
+#[test]
+fn rebuild_stale_index() {
+    let dir = TempDir::new().unwrap();
+    let vault = common::sample(&dir);
+    let root = common::root(&dir);
+    let github = common::record_file(&dir, &vault, "github");
+    let mail = common::record_file(&dir, &vault, "mail");
+    let sum = |p: &Path| {
+        root.join("checksums")
+            .join(p.file_name().unwrap())
+            .with_extension("record.sum")
+    };
+    drop(vault);
+
+    /* Keep a copy of the old record, i.e. from another device */
+    let old = fs::read(&github).unwrap();
+    let old_sum = fs::read(sum(&github)).unwrap();
+
+    let mut vault = common::reopen(&dir);
+    vault.rename_record("github", "gh").unwrap();
+    vault.sync().unwrap();
+    drop(vault);
+
+    /* A sync tool brings back the old version, which is noticed on pull */
+    fs::write(&github, old).unwrap();
+    fs::write(sum(&github), old_sum).unwrap();
+
+    let mut vault = common::reopen(&dir);
+    assert!(vault.contains("gh"));
+    vault.pull("gh").unwrap();
+    assert!(vault.contains("github"));
+    assert!(!vault.contains("gh"));
+    vault.sync().unwrap();
+    drop(vault);
+
+    let vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    drop(vault);
+
+    /* Removed records are noticed right away */
+    fs::remove_file(&mail).unwrap();
+    fs::remove_file(sum(&mail)).unwrap();
+
+    let vault = common::reopen(&dir);
+    assert!(vault.contains("github"));
+    assert!(!vault.contains("gh"));
+    assert!(!vault.contains("mail"));
+    assert_eq!(vault.metadata().size, 1);
+}
@@ -0,0 +1,138 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::crypto::DataBody;
+use crate::files::FileVault;
+use crate::lcc::errors::{Error, VaultError};
+use crate::lcc::traits::Vault;
+use crate::lcc::{Payload, VaultType};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn reopen_vault() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+
+    {
+        let mut vault: FileVault<DataBody> = common::generator(&dir)
+            .user_type(VaultType::Administrated {
+                secret: vec![1, 2, 3, 4],
+            })
+            .finalise()
+            .unwrap();
+        vault.add_record("ssh", "keys", vec!["server"]).unwrap();
+        vault
+            .add_data("ssh", "passphrase", Payload::Text("correct horse".into()))
+            .unwrap();
+        vault.meta_add_domain("registry").unwrap();
+        vault.sync().unwrap();
+    }
+
+    let mut vault: FileVault<DataBody> = *Vault::load("test", path).unwrap();
+    assert!(vault.contains("ssh"));
+    assert!(vault.meta_exists("registry"));
+    assert_eq!(vault.metadata().size, 1);
+
+    vault.pull("ssh").unwrap();
+    assert_eq!(
+        vault.get_data("ssh", "passphrase"),
+        Some(&Payload::Text("correct horse".into()))
+    );
+}
+
+#[test]
+fn missing_vault() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+
+    match FileVault::<DataBody>::load("nothing", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::InvalidPath)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Loaded a vault that doesn't exist"),
+    }
+}
+
+#[test]
+fn existing_vault() {
+    let dir = TempDir::new().unwrap();
+    common::create(&dir);
+
+    match common::generator(&dir)
+        .finalise::<FileVault<DataBody>, _>()
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::AlreadyExists)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Created a vault over an existing one"),
+    }
+}
+
+#[test]
+fn corrupted_config() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    common::create(&dir);
+
+    let cfg = dir.path().join("test.vault/vault.cfg");
+    fs::write(&cfg, "this is not yaml: [").unwrap();
+
+    match FileVault::<DataBody>::load("test", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::CorruptedConfig)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Loaded a vault with a corrupted config"),
+    }
+}
+
+#[test]
+fn incompatible_version() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    common::create(&dir);
+
+    let cfg = dir.path().join("test.vault/vault.cfg");
+    let content = fs::read_to_string(&cfg).unwrap();
+    fs::write(&cfg, content.replace("0.3.0", "7.0.0")).unwrap();
+
+    match FileVault::<DataBody>::load("test", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::IncompatibleVersion { found, supported })) => {
+            assert_eq!(found, "7.0.0");
+            assert_eq!(supported, "0.3.0");
+        }
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Loaded a vault with an incompatible version"),
+    }
+}
+
+#[test]
+fn locked_vault() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let vault = common::create(&dir);
+
+    match FileVault::<DataBody>::load("test", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::Locked)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Opened a vault twice"),
+    }
+
+    drop(vault);
+    assert!(FileVault::<DataBody>::load("test", path).is_ok());
+}
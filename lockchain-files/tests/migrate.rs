@@ -0,0 +1,319 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate semver;
+extern crate tempfile;
+
+use crate::crypto::DataBody;
+use crate::files::migrate::{Migration, MigrationError, Migrator};
+use crate::files::FileVault;
+use crate::lcc::errors::{Error, VaultError};
+use crate::lcc::traits::Vault;
+use crate::lcc::{Generator, Payload, VaultType};
+use semver::Version;
+use std::{fs, io, path::Path};
+use tempfile::TempDir;
+
+/// A made-up format change: records used to end in `.rec`
+struct RenameRecords;
+
+impl Migration for RenameRecords {
+    fn source_version(&self) -> Version {
+        Version::parse("0.0.3").unwrap()
+    }
+
+    fn target_version(&self) -> Version {
+        Version::parse("0.1.0").unwrap()
+    }
+
+    fn describe(&self) -> String {
+        "Rename record files to `.record`".into()
+    }
+
+    fn apply(&self, root: &Path, dry_run: bool) -> io::Result<Vec<String>> {
+        let mut changes = Vec::new();
+        for entry in fs::read_dir(root.join("records"))? {
+            let path = entry?.path();
+            if path.extension() == Some("rec".as_ref()) {
+                let target = path.with_extension("record");
+                changes.push(format!("{:?} -> {:?}", path, target));
+                if !dry_run {
+                    fs::rename(path, target)?;
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+/// A step between two patch levels, which never changes the format
+struct PatchLevel;
+
+impl Migration for PatchLevel {
+    fn source_version(&self) -> Version {
+        Version::parse("0.0.3").unwrap()
+    }
+
+    fn target_version(&self) -> Version {
+        Version::parse("0.0.4").unwrap()
+    }
+
+    fn describe(&self) -> String {
+        "Nothing to do".into()
+    }
+
+    fn apply(&self, _: &Path, _: bool) -> io::Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// Create a vault with a single record, named `wifi`
+fn vault(dir: &TempDir) -> FileVault<DataBody> {
+    let mut vault: FileVault<DataBody> = Generator::new()
+        .path("old", dir.path().to_str().unwrap())
+        .user_type(VaultType::Administrated { secret: vec![0; 4] })
+        .finalise()
+        .unwrap();
+    vault.add_record("wifi", "home", vec![]).unwrap();
+    vault
+        .add_data("wifi", "psk", Payload::Text("12345678".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    vault
+}
+
+/// Turn a vault into a `0.1.0` vault, with records stored by name
+fn downgrade(dir: &TempDir, vault: FileVault<DataBody>, ending: &str, version: &str) {
+    let id = vault.get_record("wifi").unwrap().header.id.to_string();
+    drop(vault);
+
+    let root = dir.path().join("old.vault");
+    let record = fs::read_to_string(root.join(format!("records/{}.record", id))).unwrap();
+    fs::write(
+        root.join(format!("records/wifi.{}", ending)),
+        record.replace(&format!("\"id\":\"{}\",", id), ""),
+    )
+    .unwrap();
+    fs::remove_file(root.join(format!("records/{}.record", id))).unwrap();
+    fs::remove_file(root.join(format!("checksums/{}.record.sum", id))).unwrap();
+
+    let cfg = fs::read_to_string(root.join("vault.cfg")).unwrap();
+    fs::write(root.join("vault.cfg"), cfg.replace("0.3.0", version)).unwrap();
+}
+
+/// Create a vault and turn it into a `0.0.3` vault
+fn old_vault(dir: &TempDir) {
+    let vault = vault(dir);
+    downgrade(dir, vault, "rec", "0.0.3");
+}
+
+#[test]
+fn offer_upgrade() {
+    match Migrator::new().check("0.0.3") {
+        Err(VaultError::IncompatibleVersion { .. }) => {}
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    match Migrator::new().register(RenameRecords).check("0.0.3") {
+        Err(VaultError::MigrationRequired { found, supported }) => {
+            assert_eq!(found, "0.0.3");
+            assert_eq!(supported, "0.3.0");
+        }
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    /* Without the built-in steps there's no way past 0.1 */
+    match Migrator::empty().register(RenameRecords).check("0.0.3") {
+        Err(VaultError::IncompatibleVersion { .. }) => {}
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    /* Vaults from the future can't be downgraded */
+    match Migrator::new().register(RenameRecords).check("0.4.0") {
+        Err(VaultError::IncompatibleVersion { .. }) => {}
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    match Migrator::new().check("0.1.4") {
+        Err(VaultError::MigrationRequired { .. }) => {}
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    match Migrator::new().check("0.2.4") {
+        Err(VaultError::MigrationRequired { .. }) => {}
+        e => panic!("Unexpected result: {:?}", e),
+    }
+
+    assert!(Migrator::new().check("0.3.4").is_ok());
+}
+
+#[test]
+fn dry_run() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    old_vault(&dir);
+
+    let report = Migrator::new()
+        .register(RenameRecords)
+        .migrate(path, "old", true)
+        .unwrap();
+    assert_eq!(report.backup, None);
+    assert_eq!(report.steps.len(), 3);
+    assert_eq!(report.steps[0].1.len(), 1);
+
+    /* Nothing was touched */
+    assert!(dir.path().join("old.vault/records/wifi.rec").exists());
+    match FileVault::<DataBody>::load("old", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::IncompatibleVersion { .. })) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Opened an outdated vault"),
+    }
+}
+
+#[test]
+fn migrate_with_backup() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    old_vault(&dir);
+
+    let report = Migrator::new()
+        .register(RenameRecords)
+        .migrate(path, "old", false)
+        .unwrap();
+    assert_eq!(report.from, Version::parse("0.0.3").unwrap());
+    assert_eq!(report.to, Version::parse("0.3.0").unwrap());
+
+    let backup = report.backup.unwrap();
+    assert!(backup.join("records/wifi.rec").exists());
+    assert!(backup.join("vault.cfg").exists());
+
+    let mut vault: FileVault<DataBody> = *Vault::load("old", path).unwrap();
+    vault.pull("wifi").unwrap();
+    assert_eq!(
+        vault.get_data("wifi", "psk"),
+        Some(&Payload::Text("12345678".into()))
+    );
+}
+
+#[test]
+fn no_upgrade_path() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    old_vault(&dir);
+
+    match Migrator::new().migrate(path, "old", false) {
+        Err(MigrationError::NoUpgradePath(from, to)) => {
+            assert_eq!(from, "0.0.3");
+            assert_eq!(to, "0.3.0");
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn skip_patch_steps() {
+    let (from, to) = (
+        Version::parse("0.0.3").unwrap(),
+        Version::parse("0.2.0").unwrap(),
+    );
+
+    let migrator = Migrator::new().register(PatchLevel).register(RenameRecords);
+    let plan = migrator.plan(&from, &to).unwrap();
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan[0].describe(), RenameRecords.describe());
+
+    match Migrator::empty().register(PatchLevel).plan(&from, &to) {
+        Err(MigrationError::NoUpgradePath(..)) => {}
+        r => panic!("Unexpected result: {:?}", r.map(|p| p.len())),
+    }
+}
+
+#[test]
+fn record_ids() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let vault = vault(&dir);
+    downgrade(&dir, vault, "record", "0.1.0");
+
+    match FileVault::<DataBody>::load("old", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::MigrationRequired { .. })) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Opened an outdated vault"),
+    }
+
+    let report = Migrator::new().migrate(path, "old", false).unwrap();
+    assert_eq!(report.steps.len(), 2);
+    assert_eq!(report.steps[0].1.len(), 1);
+
+    let mut vault: FileVault<DataBody> = *Vault::load("old", path).unwrap();
+    vault.pull("wifi").unwrap();
+    assert_eq!(
+        vault.get_data("wifi", "psk"),
+        Some(&Payload::Text("12345678".into()))
+    );
+
+    let id = vault.get_record("wifi").unwrap().header.id;
+    let root = dir.path().join("old.vault");
+    assert!(root.join(format!("records/{}.record", id)).exists());
+    assert!(!root.join("records/wifi.record").exists());
+    assert!(vault.self_test(false).unwrap().is_clean());
+
+    /* Running the step again doesn't change anything */
+    drop(vault);
+    let cfg = fs::read_to_string(root.join("vault.cfg")).unwrap();
+    fs::write(root.join("vault.cfg"), cfg.replace("0.3.0", "0.1.0")).unwrap();
+    let report = Migrator::new().migrate(path, "old", false).unwrap();
+    assert!(report.steps[0].1.is_empty());
+}
+
+#[test]
+fn sums_and_index() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let vault = vault(&dir);
+    let id = vault.get_record("wifi").unwrap().header.id;
+    drop(vault);
+
+    /* A `0.2` vault has neither an index nor checksums for every file */
+    let root = dir.path().join("old.vault");
+    fs::remove_file(root.join("headers.index")).unwrap();
+    fs::remove_file(root.join(format!("checksums/{}.record.sum", id))).unwrap();
+    let cfg = fs::read_to_string(root.join("vault.cfg")).unwrap();
+    fs::write(root.join("vault.cfg"), cfg.replace("0.3.0", "0.2.0")).unwrap();
+
+    match FileVault::<DataBody>::load("old", path)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::MigrationRequired { .. })) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Opened an outdated vault"),
+    }
+
+    let report = Migrator::new().migrate(path, "old", false).unwrap();
+    assert_eq!(report.steps.len(), 1);
+    assert_eq!(
+        report.steps[0].1,
+        vec![
+            format!("Added checksum for '{}.record'", id),
+            "Indexed 1 records".to_owned()
+        ]
+    );
+    assert!(root.join("headers.index").exists());
+    assert!(root.join(format!("checksums/{}.record.sum", id)).exists());
+
+    let mut vault: FileVault<DataBody> = *Vault::load("old", path).unwrap();
+    vault.pull("wifi").unwrap();
+    assert_eq!(
+        vault.get_data("wifi", "psk"),
+        Some(&Payload::Text("12345678".into()))
+    );
+    assert!(vault.self_test(false).unwrap().is_clean());
+}
@@ -0,0 +1,193 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::files::FileVault;
+use crate::lcc::errors::{DataError, Error, VaultError};
+use crate::lcc::traits::{Body, Vault};
+use crate::lcc::{EncryptedBody, MetaDomain, Payload};
+use tempfile::TempDir;
+
+#[test]
+fn databody_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault
+        .add_record("github", "web", vec!["dev", "work"])
+        .unwrap();
+    vault
+        .add_data("github", "password", Payload::Text("hunter2".into()))
+        .unwrap();
+    match vault
+        .add_data("missing", "password", Payload::Boolean(true))
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::UnknownRecord)) => {}
+        _ => panic!("Adding data to a missing record must fail"),
+    }
+    vault.sync().unwrap();
+
+    /* Record files are named after the record id */
+    let id = vault.get_record("github").unwrap().header.id;
+    let record_file = dir.path().join(format!("test.vault/records/{}.record", id));
+    assert!(record_file.is_file());
+
+    /* Fetching drops all bodies but keeps the header index,
+     * records that weren't synced are lost */
+    vault.add_record("gitlab", "web", vec![]).unwrap();
+    vault.fetch().unwrap();
+    assert!(vault.contains("github"));
+    assert!(!vault.contains("gitlab"));
+    assert_eq!(vault.get_record("github"), None);
+    assert_eq!(vault.metadata().size, 1);
+
+    vault.pull("github").unwrap();
+    let record = vault.get_record("github").unwrap();
+    assert_eq!(record.header.category, "web");
+    assert_eq!(record.header.tags, vec!["dev", "work"]);
+    assert_eq!(
+        vault.get_data("github", "password"),
+        Some(&Payload::Text("hunter2".into()))
+    );
+
+    /* Deleted records are removed from disk on sync */
+    assert!(vault.delete_record("github").is_ok());
+    vault.sync().unwrap();
+    assert!(!record_file.exists());
+
+    vault.fetch().unwrap();
+    assert!(!vault.contains("github"));
+}
+
+#[test]
+fn encrypted_body_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let mut vault: FileVault<EncryptedBody> = common::generator(&dir).finalise().unwrap();
+
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    match vault
+        .add_data("mail", "password", Payload::Number(42))
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Data(DataError::FailedWrite)) => {}
+        _ => panic!("Encrypted bodies can't be written to directly"),
+    }
+
+    vault.meta_add_domain("registry").unwrap();
+    vault
+        .meta_set("registry", "invites", Payload::Number(3))
+        .unwrap();
+    assert!(vault.meta_add_domain("registry").is_err());
+    match vault
+        .meta_add_domain("userstore")
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::InvalidName)) => {}
+        _ => panic!("The userstore domain is reserved"),
+    }
+    match vault
+        .add_record("", "misc", vec![])
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::InvalidName)) => {}
+        _ => panic!("Records need a name"),
+    }
+
+    /* Names never end up in file paths */
+    for name in &[".hidden", "../escape", "a/b"] {
+        vault.add_record(name, "misc", vec![]).unwrap();
+    }
+    vault.sync().unwrap();
+
+    vault.fetch().unwrap();
+    assert!(vault.contains("mail"));
+    assert!(vault.contains("../escape"));
+    assert_eq!(
+        vault.meta_get("registry", "invites"),
+        Some(Payload::Number(3))
+    );
+
+    vault.pull("mail").unwrap();
+    let record = vault.get_record("mail").unwrap();
+    assert_eq!(record.header.category, "personal");
+    assert_eq!(record.body, Some(EncryptedBody::default()));
+
+    let mut domain = MetaDomain::new("stats");
+    domain.set_field("opened", Payload::Boolean(true));
+    vault.meta_push_domain(domain.clone()).unwrap();
+    vault.sync().unwrap();
+
+    vault.fetch().unwrap();
+    assert_eq!(vault.meta_pull_domain("stats"), Some(&domain));
+    assert!(vault.meta_exists("registry"));
+}
+
+#[test]
+fn shorter_rewrite() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault.add_record("notes", "misc", vec![]).unwrap();
+    vault
+        .add_data("notes", "text", Payload::Text("x".repeat(4096)))
+        .unwrap();
+    vault.sync().unwrap();
+
+    vault
+        .add_data("notes", "text", Payload::Text("short".into()))
+        .unwrap();
+    vault.sync().unwrap();
+
+    vault.fetch().unwrap();
+    vault.pull("notes").unwrap();
+    assert_eq!(
+        vault.get_data("notes", "text"),
+        Some(&Payload::Text("short".into()))
+    );
+
+    /* No temporary files are left behind */
+    let leftovers = std::fs::read_dir(dir.path().join("test.vault/records"))
+        .unwrap()
+        .count();
+    assert_eq!(leftovers, 1);
+}
+
+#[test]
+fn rename_in_place() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::create(&dir);
+
+    vault.add_record("notes", "misc", vec![]).unwrap();
+    vault
+        .add_data("notes", "text", Payload::Text("hello".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    let id = vault.get_record("notes").unwrap().header.id;
+
+    /* Records are pulled before they are renamed */
+    vault.fetch().unwrap();
+    vault.rename_record("notes", "journal").unwrap();
+    vault.sync().unwrap();
+
+    let records = std::fs::read_dir(dir.path().join("test.vault/records"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records, vec![format!("{}.record", id)]);
+
+    vault.fetch().unwrap();
+    assert!(!vault.contains("notes"));
+    vault.pull(&id.to_string()).unwrap();
+    assert_eq!(
+        vault.get_data("journal", "text"),
+        Some(&Payload::Text("hello".into()))
+    );
+}
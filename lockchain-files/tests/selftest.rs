@@ -0,0 +1,121 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::files::fs::FileType;
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn clean_vault() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    let root = common::root(&dir);
+
+    let github = common::id(&vault, "github").to_string();
+    assert!(root
+        .join(format!("checksums/{}.record.sum", github))
+        .exists());
+    assert!(root.join("checksums/settings.meta.sum").exists());
+    assert!(root.join("checksums/userstore.meta.sum").exists());
+
+    let report = vault.self_test(false).unwrap();
+    assert!(report.is_clean());
+}
+
+#[test]
+fn quarantine_damaged_record() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    let root = common::root(&dir);
+    let github = common::id(&vault, "github").to_string();
+    let path = root.join(format!("records/{}.record", github));
+
+    let mut content = fs::read_to_string(&path).unwrap();
+    content = content.replace("hunter2", "hunter3");
+    fs::write(&path, content).unwrap();
+
+    /* Damaged data is never read */
+    vault.fetch().unwrap();
+    assert!(vault.pull("github").is_err());
+    assert_eq!(vault.get_record("github"), None);
+
+    let report = vault.self_test(false).unwrap();
+    assert_eq!(report.mismatched, vec![(FileType::Record, github.clone())]);
+    assert!(path.exists());
+
+    let report = vault.self_test(true).unwrap();
+    assert_eq!(
+        report.quarantined,
+        vec![root.join(format!("quarantine/{}.record", github))]
+    );
+    assert!(!path.exists());
+    assert!(!vault.contains("github"));
+    assert!(vault.self_test(false).unwrap().is_clean());
+}
+
+#[test]
+fn restore_from_memory() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    let root = common::root(&dir);
+    let github = common::id(&vault, "github").to_string();
+    let path = root.join(format!("records/{}.record", github));
+
+    fs::remove_file(&path).unwrap();
+    let report = vault.self_test(false).unwrap();
+    assert_eq!(report.missing, vec![(FileType::Record, github.clone())]);
+
+    /* The record is still loaded and can be written back */
+    assert!(vault.self_test(true).unwrap().quarantined.is_empty());
+    assert!(path.exists());
+
+    vault.fetch().unwrap();
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "password"),
+        Some(&Payload::Text("hunter2".into()))
+    );
+}
+
+#[test]
+fn adopt_and_quarantine_orphans() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    let root = common::root(&dir);
+
+    let github = common::id(&vault, "github").to_string();
+    let mail = common::id(&vault, "mail").to_string();
+
+    /* A file from before checksums existed */
+    fs::remove_file(root.join(format!("checksums/{}.record.sum", mail))).unwrap();
+
+    /* A conflict copy left behind by a sync tool */
+    let conflict = format!("{}.sync-conflict-20180101", github);
+    fs::copy(
+        root.join(format!("records/{}.record", github)),
+        root.join(format!("records/{}.record", conflict)),
+    )
+    .unwrap();
+
+    let report = vault.self_test(false).unwrap();
+    let mut orphaned = vec![
+        (FileType::Record, conflict.clone()),
+        (FileType::Record, mail.clone()),
+    ];
+    orphaned.sort();
+    assert_eq!(report.orphaned, orphaned);
+
+    let report = vault.self_test(true).unwrap();
+    assert_eq!(
+        report.quarantined,
+        vec![root.join(format!("quarantine/{}.record", conflict))]
+    );
+    assert!(root.join(format!("checksums/{}.record.sum", mail)).exists());
+    assert!(vault.self_test(false).unwrap().is_clean());
+}
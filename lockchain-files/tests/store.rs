@@ -0,0 +1,45 @@
+#[macro_use]
+extern crate lockchain_core as lcc;
+extern crate lockchain_files as files;
+
+use crate::files::{FileStore, FileVault};
+use crate::lcc::store::StoreVault;
+use crate::lcc::testing::{Fixture, TestBody};
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+
+vault_conformance!(file_store, StoreVault<FileStore, TestBody>);
+
+#[test]
+fn share_a_folder_with_file_vaults() {
+    let fix = Fixture::new();
+    {
+        let mut vault: FileVault<TestBody> = fix.create();
+        vault.add_record("github", "web", vec!["dev"]).unwrap();
+        vault
+            .add_data("github", "user", Payload::Text("alice".into()))
+            .unwrap();
+        vault.meta_add_domain("settings").unwrap();
+        vault.sync().unwrap();
+    }
+
+    {
+        let mut vault: StoreVault<FileStore, TestBody> = fix.reopen();
+        assert!(vault.meta_exists("settings"));
+        vault.pull("github").unwrap();
+        assert_eq!(
+            vault.get_data("github", "user"),
+            Some(&Payload::Text("alice".into()))
+        );
+
+        vault.add_record("gitlab", "web", vec![]).unwrap();
+        vault.delete_record("github").unwrap();
+        vault.sync().unwrap();
+    }
+
+    let mut vault: FileVault<TestBody> = fix.reopen();
+    assert!(!vault.contains("github"));
+    assert!(vault.contains("gitlab"));
+    vault.pull("gitlab").unwrap();
+    assert!(vault.get_record("gitlab").is_some());
+}
@@ -0,0 +1,131 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::files::CachePolicy;
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn only_write_changes() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    let root = common::root(&dir);
+    let github = common::record_file(&dir, &vault, "github");
+    let mail = common::record_file(&dir, &vault, "mail");
+    let settings = root.join("metadata/settings.meta");
+    let users = root.join("metadata/userstore.meta");
+
+    /* Mark files that should be left alone */
+    for path in &[&mail, &settings, &users] {
+        fs::write(path, "untouched").unwrap();
+    }
+
+    vault
+        .add_data("github", "password", Payload::Text("hunter2".into()))
+        .unwrap();
+    vault.sync().unwrap();
+
+    assert!(fs::read_to_string(&github).unwrap().contains("hunter2"));
+    for path in &[&mail, &settings, &users] {
+        assert_eq!(fs::read_to_string(path).unwrap(), "untouched");
+    }
+
+    /* Deleted records are removed, changed domains written */
+    vault.delete_record("github").unwrap();
+    vault
+        .meta_set("settings", "theme", Payload::Text("dark".into()))
+        .unwrap();
+    vault.sync().unwrap();
+
+    assert!(!github.exists());
+    assert!(fs::read_to_string(&settings).unwrap().contains("dark"));
+    assert_eq!(fs::read_to_string(&mail).unwrap(), "untouched");
+}
+
+#[test]
+fn debounce_background_writes() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    vault
+        .set_background_sync(Some(Duration::from_millis(300)))
+        .unwrap();
+
+    /* Syncing only queues changes */
+    vault.add_record("ssh", "server", vec![]).unwrap();
+    vault.sync().unwrap();
+    let ssh = common::record_file(&dir, &vault, "ssh");
+    assert!(!ssh.exists());
+
+    vault.flush().unwrap();
+    assert!(ssh.exists());
+
+    /* Queued changes are written after the debounce interval */
+    vault.add_record("db", "server", vec![]).unwrap();
+    vault.sync().unwrap();
+    let db = common::record_file(&dir, &vault, "db");
+    thread::sleep(Duration::from_millis(1000));
+    assert!(db.exists());
+
+    /* Closing the vault waits for queued writes */
+    vault.delete_record("mail").unwrap();
+    vault.sync().unwrap();
+    drop(vault);
+
+    let vault = common::reopen(&dir);
+    assert!(vault.contains("ssh"));
+    assert!(vault.contains("db"));
+    assert!(!vault.contains("mail"));
+}
+
+#[test]
+fn pull_queued_changes() {
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    vault.set_cache_policy(CachePolicy::default().max_entries(1));
+    vault
+        .set_background_sync(Some(Duration::from_secs(60)))
+        .unwrap();
+
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.sync().unwrap();
+
+    /* Evict the changed record before it was written */
+    vault.pull("mail").unwrap();
+    assert_eq!(vault.get_record("github"), None);
+
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+}
+
+#[test]
+fn keep_unreadable_records() {
+    let dir = TempDir::new().unwrap();
+    let vault = common::sample(&dir);
+    let github = common::record_file(&dir, &vault, "github");
+    drop(vault);
+
+    /* Records that can't be loaded are never deleted by a sync */
+    fs::write(&github, "garbage").unwrap();
+    let mut vault = common::reopen(&dir);
+    assert!(vault.pull("github").is_err());
+    vault.add_record("ssh", "server", vec![]).unwrap();
+    vault.sync().unwrap();
+    vault.fetch().unwrap();
+    vault.sync().unwrap();
+
+    assert_eq!(fs::read_to_string(&github).unwrap(), "garbage");
+    assert!(vault.contains("github"));
+}
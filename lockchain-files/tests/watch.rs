@@ -0,0 +1,208 @@
+#![cfg(feature = "watch")]
+
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_files as files;
+extern crate tempfile;
+
+mod common;
+
+use crate::crypto::DataBody;
+use crate::files::{is_conflict_copy, FileVault, VaultEvent};
+use crate::lcc::traits::Vault;
+use crate::lcc::Payload;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Copy the vault files from one folder to another, like a sync tool
+fn mirror(from: &TempDir, to: &TempDir) {
+    for sub in &["records", "metadata", "checksums"] {
+        fs::create_dir_all(to.path().join("test.vault").join(sub)).unwrap();
+    }
+    for file in &["vault.cfg", "headers.index"] {
+        fs::copy(
+            from.path().join("test.vault").join(file),
+            to.path().join("test.vault").join(file),
+        )
+        .unwrap();
+    }
+    for sub in &["records", "metadata", "checksums"] {
+        let from = from.path().join("test.vault").join(sub);
+        let to = to.path().join("test.vault").join(sub);
+
+        for entry in fs::read_dir(&to).unwrap() {
+            let entry = entry.unwrap();
+            if !from.join(entry.file_name()).exists() {
+                fs::remove_file(entry.path()).unwrap();
+            }
+        }
+        for entry in fs::read_dir(&from).unwrap() {
+            let entry = entry.unwrap();
+            let target = to.join(entry.file_name());
+            if fs::read(&target).ok() != fs::read(entry.path()).ok() {
+                fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+}
+
+/// Poll a vault until it reported some number of events
+fn wait_for(vault: &mut FileVault<DataBody>, count: usize) -> Vec<VaultEvent> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut events = Vec::new();
+    while events.len() < count && Instant::now() < deadline {
+        events.extend(vault.poll_changes().unwrap());
+        thread::sleep(Duration::from_millis(50));
+    }
+    events.sort();
+    events
+}
+
+#[test]
+fn apply_external_changes() {
+    let (ours, theirs) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let mut vault = common::sample(&ours);
+    vault.watch().unwrap();
+
+    /* Our own writes are never reported */
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.sync().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(vault.poll_changes().unwrap(), vec![]);
+
+    /* Make changes to a copy of the vault, then sync them back */
+    mirror(&ours, &theirs);
+    let mut other = common::reopen(&theirs);
+    other.add_record("ssh", "server", vec![]).unwrap();
+    other
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    other.delete_record("mail").unwrap();
+    other
+        .meta_set("settings", "theme", Payload::Text("dark".into()))
+        .unwrap();
+    other.sync().unwrap();
+    let ssh = common::id(&other, "ssh");
+    drop(other);
+    mirror(&theirs, &ours);
+
+    let (github, mail) = (common::id(&vault, "github"), common::id(&vault, "mail"));
+    assert_eq!(
+        wait_for(&mut vault, 4),
+        vec![
+            VaultEvent::RecordAdded(ssh),
+            VaultEvent::RecordChanged(github),
+            VaultEvent::RecordRemoved(mail),
+            VaultEvent::DomainChanged("settings".into()),
+        ]
+    );
+
+    assert!(vault.contains("ssh"));
+    assert!(!vault.contains("mail"));
+    assert_eq!(vault.get_record("github"), None);
+    vault.pull("github").unwrap();
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("bob".into()))
+    );
+    assert_eq!(
+        vault.meta_get("settings", "theme"),
+        Some(Payload::Text("dark".into()))
+    );
+
+    /* The header index is brought up to date on the next sync */
+    vault.sync().unwrap();
+    drop(vault);
+    let vault = common::reopen(&ours);
+    assert!(vault.contains("ssh"));
+    assert!(!vault.contains("mail"));
+    assert_eq!(vault.metadata().size, 2);
+}
+
+#[test]
+fn report_conflicts() {
+    let (ours, theirs) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let mut vault = common::sample(&ours);
+    mirror(&ours, &theirs);
+    vault.watch().unwrap();
+
+    /* Both sides change the same record and domain */
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault
+        .meta_set("settings", "theme", Payload::Text("light".into()))
+        .unwrap();
+
+    let mut other = common::reopen(&theirs);
+    other
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    other
+        .meta_set("settings", "theme", Payload::Text("dark".into()))
+        .unwrap();
+    other.sync().unwrap();
+    drop(other);
+    mirror(&theirs, &ours);
+
+    let github = common::id(&vault, "github");
+    assert_eq!(
+        wait_for(&mut vault, 2),
+        vec![
+            VaultEvent::RecordConflict(github),
+            VaultEvent::DomainConflict("settings".into()),
+        ]
+    );
+
+    /* The in-memory version is kept */
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    assert_eq!(
+        vault.meta_get("settings", "theme"),
+        Some(Payload::Text("light".into()))
+    );
+}
+
+#[test]
+fn detect_conflict_copies() {
+    assert!(is_conflict_copy(
+        "github.sync-conflict-20190301-120000-ABCDEFG"
+    ));
+    assert!(is_conflict_copy(
+        "github (alice's conflicted copy 2019-03-01)"
+    ));
+    assert!(is_conflict_copy(
+        "github (Conflicted Copy 2019-03-01 120000)"
+    ));
+    assert!(is_conflict_copy("github_conflict-20190301-120000"));
+    assert!(!is_conflict_copy("conflicts"));
+
+    let dir = TempDir::new().unwrap();
+    let mut vault = common::sample(&dir);
+    vault.watch().unwrap();
+
+    let github = common::id(&vault, "github");
+    let records = dir.path().join("test.vault/records");
+    let copy = format!("{}.sync-conflict-20190301-120000-ABCDEFG.record", github);
+    fs::copy(
+        records.join(format!("{}.record", github)),
+        records.join(&copy),
+    )
+    .unwrap();
+
+    let events = wait_for(&mut vault, 1);
+    match events.as_slice() {
+        [VaultEvent::ConflictCopy(path)] => {
+            assert_eq!(path.file_name(), Path::new(&copy).file_name())
+        }
+        other => panic!("Unexpected events {:?}", other),
+    }
+    assert_eq!(vault.metadata().size, 2);
+}
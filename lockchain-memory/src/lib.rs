@@ -0,0 +1,156 @@
+//! An in-memory storage backend for lockchain vaults
+//!
+//! `MemoryVault` implements the entire `Vault` API, without any
+//! persistence. Everything is lost once the vault is dropped, which
+//! makes it a good fit for unit tests of code that is generic over
+//! vaults, or for ephemeral vaults that only live as long as a
+//! single process.
+//!
+//! If some state should survive after all, a vault can be turned into
+//! a byte blob via `snapshot` and brought back with `restore`.
+//! Active user sessions are never part of a snapshot.
+//!
+//! `MemoryStore` is an in-memory `BlobStore`, for use with the
+//! generic `StoreVault` from `lockchain-core`.
+
+extern crate lockchain_core as lcc;
+
+use crate::lcc::state::VaultState;
+use crate::lcc::traits::{Body, LoadRecord, Vault};
+use crate::lcc::{
+    errors::{DataError, Error as LockError, VaultError},
+    users::UserStore,
+    Generator, MetaDomain, Record, Uuid, VaultMetadata,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod store;
+
+pub use crate::store::MemoryStore;
+
+/// A vault that only exists in memory
+pub struct MemoryVault<T: Body> {
+    name: String,
+    location: String,
+    state: VaultState<T>,
+}
+
+/// A vault, as it is written by `snapshot`
+#[derive(Serialize)]
+struct SnapshotRef<'a, T: Body> {
+    name: &'a str,
+    location: &'a str,
+    users: &'a UserStore,
+    records: HashMap<Uuid, &'a Record<T>>,
+    metadata: &'a HashMap<String, MetaDomain>,
+}
+
+/// A vault, as it is read by `restore`
+#[derive(Deserialize)]
+struct Snapshot<T: Body> {
+    name: String,
+    location: String,
+    users: UserStore,
+    #[serde(bound(deserialize = "T: Body"))]
+    records: HashMap<Uuid, Record<T>>,
+    metadata: HashMap<String, MetaDomain>,
+}
+
+impl<T: Body> MemoryVault<T> {
+    /// Serialise the entire vault into a byte blob
+    ///
+    /// **Note** Just like other backends, this contains all data
+    /// exactly as it was handed to the vault. Secrets are only
+    /// protected if the bodies are encrypted!
+    ///
+    /// Fails with `DataError::FailedEncode` if a body can't be
+    /// serialised as JSON.
+    pub fn snapshot(&self) -> Result<Vec<u8>, LockError> {
+        let snapshot = SnapshotRef {
+            name: &self.name,
+            location: &self.location,
+            users: &self.state.users,
+            records: self.state.records().map(|r| (r.header.id, r)).collect(),
+            metadata: &self.state.metadata,
+        };
+        serde_json::to_vec(&snapshot)
+            .map_err(|e| LockError::from(DataError::FailedEncode).caused_by(e))
+    }
+
+    /// Restore a vault from a blob created by `snapshot`
+    pub fn restore(blob: &[u8]) -> Result<Self, LockError> {
+        let snapshot: Snapshot<T> = serde_json::from_slice(blob)
+            .map_err(|e| LockError::from(DataError::FailedDecode).caused_by(e))?;
+
+        let mut state = VaultState::new(snapshot.users);
+        snapshot
+            .records
+            .into_iter()
+            .for_each(|(_, r)| state.insert(r));
+        state.metadata = snapshot.metadata;
+        Ok(Self {
+            name: snapshot.name,
+            location: snapshot.location,
+            state,
+        })
+    }
+}
+
+impl<T: Body> LoadRecord<T> for MemoryVault<T> {}
+
+impl<T: Body> Vault<T> for MemoryVault<T> {
+    fn new(gen: Generator) -> Result<Box<Self>, LockError> {
+        let (name, location) = match gen {
+            Generator {
+                name: Some(ref n),
+                location: Some(ref l),
+                ..
+            } => (n.clone(), l.clone()),
+            _ => return Err(VaultError::IncompleteGenerator.into()),
+        };
+        let vault_type = gen.user_type.ok_or(VaultError::IncompleteGenerator)?;
+
+        Ok(Box::new(Self {
+            name,
+            location,
+            state: VaultState::create(&vault_type),
+        }))
+    }
+
+    /// A memory vault has nothing to load from, this always fails
+    fn load(_: &str, _: &str) -> Result<Box<Self>, LockError> {
+        Err(VaultError::FailedLoading.into())
+    }
+
+    lcc::forward_to_state! {
+        state: authenticate, deauthenticate, create_user, delete_user, get_record, contains,
+            query, add_record, delete_record, get_data, meta_add_domain, meta_pull_domain,
+            meta_push_domain, meta_set, meta_get, meta_remove, meta_exists, rename_record,
+            add_data, remove_data
+    }
+
+    fn metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            name: self.name.clone(),
+            location: self.location.clone(),
+            size: self.state.len(),
+        }
+    }
+
+    /// Everything is always in memory, this does nothing
+    fn fetch(&mut self) -> Result<(), LockError> {
+        Ok(())
+    }
+
+    /// Everything is always in memory, this only checks that the record exists
+    fn pull(&mut self, name: &str) -> Result<(), LockError> {
+        self.state.find(name).map(|_| ())
+    }
+
+    /// There is no backing storage, this only forgets what changed
+    fn sync(&mut self) -> Result<(), LockError> {
+        self.state.dirty = Default::default();
+        Ok(())
+    }
+}
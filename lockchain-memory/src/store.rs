@@ -0,0 +1,138 @@
+//! A `BlobStore` that lives in memory
+
+use crate::lcc::errors::{Error as LockError, VaultError};
+use crate::lcc::store::{Blob, BlobStore, Change, Condition, Locate, Partial, Revision, Space};
+use crate::lcc::Generator;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Stored blobs, with a counter to hand out revisions
+#[derive(Default)]
+struct Blobs {
+    data: HashMap<(Space, String), Blob>,
+    counter: u64,
+}
+
+impl Blobs {
+    fn check(&self, change: &Change) -> Result<(), LockError> {
+        let current = self
+            .data
+            .get(&(change.space, change.key.clone()))
+            .map(|b| &b.revision);
+        if change.condition.holds(current) {
+            Ok(())
+        } else {
+            Err(Condition::failed(change.space, &change.key))
+        }
+    }
+
+    fn apply(&mut self, change: Change) -> Option<Revision> {
+        let key = (change.space, change.key);
+        match change.data {
+            Some(data) => {
+                self.counter += 1;
+                let revision = self.counter.to_string();
+                self.data.insert(
+                    key,
+                    Blob {
+                        data,
+                        revision: revision.clone(),
+                    },
+                );
+                Some(revision)
+            }
+            None => {
+                self.data.remove(&key);
+                None
+            }
+        }
+    }
+}
+
+/// A blob store that only exists in memory
+///
+/// Clones share the same blobs, which allows several `StoreVault`s
+/// to work on the same data, i.e. to test conflict handling.
+/// Commits are applied either in full or not at all.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    blobs: Arc<Mutex<Blobs>>,
+}
+
+impl MemoryStore {
+    /// Create a new, empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlobStore for MemoryStore {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        let blobs = self.blobs.lock().unwrap();
+        Ok(blobs.data.get(&(space, key.to_owned())).cloned())
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        let change = Change {
+            space,
+            key: key.to_owned(),
+            data: Some(data),
+            condition: condition.clone(),
+        };
+        let mut blobs = self.blobs.lock().unwrap();
+        blobs.check(&change)?;
+        Ok(blobs.apply(change).unwrap())
+    }
+
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        let change = Change {
+            space,
+            key: key.to_owned(),
+            data: None,
+            condition: condition.clone(),
+        };
+        let mut blobs = self.blobs.lock().unwrap();
+        blobs.check(&change)?;
+        blobs.apply(change);
+        Ok(())
+    }
+
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        let blobs = self.blobs.lock().unwrap();
+        Ok(blobs
+            .data
+            .iter()
+            .filter(|((s, _), _)| *s == space)
+            .map(|((_, key), blob)| (key.clone(), blob.revision.clone()))
+            .collect())
+    }
+
+    fn commit(&mut self, changes: Vec<Change>) -> Result<Vec<Option<Revision>>, Partial> {
+        let mut blobs = self.blobs.lock().unwrap();
+        changes
+            .iter()
+            .try_for_each(|c| blobs.check(c))
+            .map_err(|error| Partial {
+                applied: vec![],
+                error,
+            })?;
+        Ok(changes.into_iter().map(|c| blobs.apply(c)).collect())
+    }
+}
+
+impl Locate for MemoryStore {
+    fn create(_: &Generator) -> Result<Self, LockError> {
+        Ok(Self::new())
+    }
+
+    /// Always fails, there is nothing to open
+    fn open(name: &str, _: &str) -> Result<Self, LockError> {
+        Err(LockError::from(VaultError::FailedLoading).context(format!("vault '{}'", name)))
+    }
+}
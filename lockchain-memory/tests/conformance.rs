@@ -0,0 +1,6 @@
+use lockchain_core::store::StoreVault;
+use lockchain_core::{testing::TestBody, vault_conformance};
+use lockchain_memory::{MemoryStore, MemoryVault};
+
+vault_conformance!(memory_vault, MemoryVault<TestBody>, transient);
+vault_conformance!(memory_store, StoreVault<MemoryStore, TestBody>, transient);
@@ -0,0 +1,195 @@
+use lockchain_core::errors::{Error, VaultError};
+use lockchain_core::store::{
+    Blob, BlobStore, Change, Condition, Locate, Revision, Space, StoreVault,
+};
+use lockchain_core::testing::{Fixture, TestBody};
+use lockchain_core::traits::Vault;
+use lockchain_core::{Generator, Payload};
+use lockchain_memory::MemoryStore;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn create(store: &MemoryStore) -> StoreVault<MemoryStore, TestBody> {
+    StoreVault::create(store.clone(), Fixture::new().generator()).unwrap()
+}
+
+fn is_conflict(e: &Error) -> bool {
+    matches!(e.kind(), Error::Vault(VaultError::Conflict))
+}
+
+#[test]
+fn share_a_store() {
+    let store = MemoryStore::new();
+    let mut vault = create(&store);
+    vault.add_record("github", "web", vec!["dev"]).unwrap();
+    vault
+        .add_data("github", "password", Payload::Text("hunter2".into()))
+        .unwrap();
+    vault.meta_add_domain("settings").unwrap();
+    vault.sync().unwrap();
+
+    /* Only headers are read when opening a vault */
+    let mut other: StoreVault<_, TestBody> =
+        StoreVault::open(store.clone(), "conformance", "memory").unwrap();
+    assert!(other.contains("github"));
+    assert!(other.get_record("github").is_none());
+    assert!(other.meta_exists("settings"));
+
+    other.pull("github").unwrap();
+    assert_eq!(
+        other.get_data("github", "password"),
+        Some(&Payload::Text("hunter2".into()))
+    );
+}
+
+#[test]
+fn detect_conflicts() {
+    let store = MemoryStore::new();
+    let mut first = create(&store);
+    first.add_record("github", "web", vec![]).unwrap();
+    first.sync().unwrap();
+
+    let mut second: StoreVault<_, TestBody> =
+        StoreVault::open(store.clone(), "conformance", "memory").unwrap();
+    second.pull("github").unwrap();
+    first
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    first.sync().unwrap();
+
+    /* The second vault is out of date */
+    second
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    assert!(is_conflict(&second.sync().unwrap_err()));
+
+    second.fetch().unwrap();
+    second.pull("github").unwrap();
+    assert_eq!(
+        second.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    second
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    second.sync().unwrap();
+}
+
+#[test]
+fn commits_are_atomic() {
+    let mut store = MemoryStore::new();
+    store
+        .put(Space::Records, "a", vec![1], &Condition::Absent)
+        .unwrap();
+
+    let changes = vec![
+        Change {
+            space: Space::Records,
+            key: "b".into(),
+            data: Some(vec![2]),
+            condition: Condition::Absent,
+        },
+        Change {
+            space: Space::Records,
+            key: "a".into(),
+            data: Some(vec![3]),
+            condition: Condition::Absent,
+        },
+    ];
+    let partial = store.commit(changes).unwrap_err();
+    assert!(is_conflict(&partial.error));
+    assert!(partial.applied.is_empty());
+    assert!(store.get(Space::Records, "b").unwrap().is_none());
+    assert_eq!(
+        store.get(Space::Records, "a").unwrap().unwrap().data,
+        vec![1]
+    );
+}
+
+/// A store that fails writes once a number of them went through
+#[derive(Clone)]
+struct Flaky {
+    inner: MemoryStore,
+    puts_left: Arc<AtomicUsize>,
+}
+
+impl BlobStore for Flaky {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, Error> {
+        self.inner.get(space, key)
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, Error> {
+        match self.puts_left.load(Ordering::SeqCst) {
+            0 => Err(VaultError::FailedSync.into()),
+            n => {
+                self.puts_left.store(n - 1, Ordering::SeqCst);
+                self.inner.put(space, key, data, condition)
+            }
+        }
+    }
+
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), Error> {
+        self.inner.delete(space, key, condition)
+    }
+
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, Error> {
+        self.inner.list(space)
+    }
+}
+
+impl Flaky {
+    fn new(inner: MemoryStore) -> Self {
+        Self {
+            inner,
+            puts_left: Arc::new(AtomicUsize::new(usize::MAX)),
+        }
+    }
+}
+
+impl Locate for Flaky {
+    fn create(gen: &Generator) -> Result<Self, Error> {
+        MemoryStore::create(gen).map(Self::new)
+    }
+
+    fn open(name: &str, location: &str) -> Result<Self, Error> {
+        MemoryStore::open(name, location).map(Self::new)
+    }
+}
+
+#[test]
+fn retry_a_partial_sync() {
+    let store = Flaky::new(MemoryStore::new());
+    let puts_left = store.puts_left.clone();
+    let mut vault: StoreVault<_, TestBody> =
+        StoreVault::create(store, Fixture::new().generator()).unwrap();
+    vault.add_record("github", "web", vec![]).unwrap();
+    vault.add_record("mail", "personal", vec![]).unwrap();
+    vault.sync().unwrap();
+
+    /* Only one of the two records is written */
+    for name in &["github", "mail"] {
+        vault
+            .add_data(name, "user", Payload::Text("alice".into()))
+            .unwrap();
+    }
+    puts_left.store(1, Ordering::SeqCst);
+    let e = vault.sync().unwrap_err();
+    assert!(matches!(e.kind(), Error::Vault(VaultError::FailedSync)));
+
+    puts_left.store(usize::MAX, Ordering::SeqCst);
+    vault.sync().unwrap();
+    vault.fetch().unwrap();
+    for name in &["github", "mail"] {
+        vault.pull(name).unwrap();
+        assert_eq!(
+            vault.get_data(name, "user"),
+            Some(&Payload::Text("alice".into()))
+        );
+    }
+}
@@ -0,0 +1,146 @@
+extern crate lockchain_core as lcc;
+extern crate lockchain_crypto as crypto;
+extern crate lockchain_memory as memory;
+
+use crate::crypto::DataBody;
+use crate::lcc::errors::{Error, VaultError};
+use crate::lcc::traits::{Body, Vault};
+use crate::lcc::users::{Access, Token};
+use crate::lcc::{Generator, MetaDomain, Payload, VaultType};
+use crate::memory::MemoryVault;
+
+fn create() -> MemoryVault<DataBody> {
+    Generator::new()
+        .path("test", "memory")
+        .user_type(VaultType::SoloUser {
+            username: "alice".into(),
+            secret: b"secret".to_vec(),
+        })
+        .finalise()
+        .unwrap()
+}
+
+#[test]
+fn records() {
+    let mut vault = create();
+
+    vault.add_record("github", "web", vec!["dev"]).unwrap();
+    assert!(vault.contains("github"));
+    vault
+        .add_data("github", "password", Payload::Text("hunter2".into()))
+        .unwrap();
+    match vault
+        .add_data("missing", "password", Payload::Number(1))
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::UnknownRecord)) => {}
+        _ => panic!("Adding data to a missing record must fail"),
+    }
+
+    /* There is no storage, so syncing and fetching change nothing */
+    vault.sync().unwrap();
+    vault.fetch().unwrap();
+    assert_eq!(
+        vault.get_data("github", "password"),
+        Some(&Payload::Text("hunter2".into()))
+    );
+    assert_eq!(vault.metadata().size, 1);
+
+    let record = vault.delete_record("github").unwrap();
+    assert_eq!(record.header.category, "web");
+    assert!(!vault.contains("github"));
+    assert_eq!(vault.get_record("github"), None);
+}
+
+#[test]
+fn metadata() {
+    let mut vault = create();
+
+    vault.meta_add_domain("registry").unwrap();
+    assert!(vault.meta_add_domain("registry").is_err());
+    vault
+        .meta_set("registry", "invites", Payload::Number(3))
+        .unwrap();
+    assert_eq!(
+        vault.meta_get("registry", "invites"),
+        Some(Payload::Number(3))
+    );
+    assert!(vault.meta_set("missing", "x", Payload::Number(1)).is_err());
+
+    let mut domain = MetaDomain::new("stats");
+    domain.set_field("opened", Payload::Boolean(true));
+    vault.meta_push_domain(domain.clone()).unwrap();
+    assert_eq!(vault.meta_pull_domain("stats"), Some(&domain));
+    assert!(vault.meta_exists("registry"));
+}
+
+#[test]
+fn users() {
+    let mut vault = create();
+
+    /* Failed logins don't hand out tokens */
+    assert!(vault.authenticate("alice", "wrong").is_err());
+    assert!(vault.authenticate("mallory", "secret").is_err());
+
+    let token = vault.authenticate("alice", "secret").unwrap();
+    vault
+        .create_user(token.clone(), "bob", b"pw".to_vec(), vec![Access::Root])
+        .unwrap();
+    assert!(vault
+        .create_user(token.clone(), "bob", b"pw".to_vec(), vec![])
+        .is_err());
+
+    let bob = vault.authenticate("bob", "pw").unwrap();
+    vault.delete_user(token.clone(), "bob").unwrap();
+    assert!(vault
+        .create_user(bob, "carol", b"pw".to_vec(), vec![])
+        .is_err());
+
+    /* Tokens are invalid after logging out */
+    vault.deauthenticate("alice", token.clone()).unwrap();
+    assert!(vault
+        .create_user(token, "carol", b"pw".to_vec(), vec![])
+        .is_err());
+    assert!(vault
+        .create_user(Token::new(), "carol", b"pw".to_vec(), vec![])
+        .is_err());
+}
+
+#[test]
+fn snapshots() {
+    let mut vault = create();
+    vault.add_record("github", "web", vec![]).unwrap();
+    vault
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    vault.meta_add_domain("registry").unwrap();
+    let token = vault.authenticate("alice", "secret").unwrap();
+
+    let blob = vault.snapshot().unwrap();
+    let mut restored: MemoryVault<DataBody> = MemoryVault::restore(&blob).unwrap();
+    assert_eq!(
+        restored.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    assert!(restored.meta_exists("registry"));
+    assert_eq!(restored.metadata().name, "test");
+
+    /* Users survive a snapshot, their sessions don't */
+    assert!(restored.create_user(token, "bob", vec![1], vec![]).is_err());
+    let token = restored.authenticate("alice", "secret").unwrap();
+    restored.create_user(token, "bob", vec![1], vec![]).unwrap();
+
+    assert!(MemoryVault::<DataBody>::restore(b"garbage").is_err());
+}
+
+#[test]
+fn no_persistence() {
+    match MemoryVault::<DataBody>::load("test", "memory")
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::Vault(VaultError::FailedLoading)) => {}
+        _ => panic!("Memory vaults can't be loaded"),
+    }
+}
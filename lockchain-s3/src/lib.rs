@@ -0,0 +1,99 @@
+//! An S3-compatible object storage backend for lockchain vaults
+//!
+//! `S3Store` keeps the records, metadata domains and userstore of
+//! a vault as objects in a bucket, so that several servers can share
+//! a vault. `S3Vault` puts the generic `StoreVault` from
+//! `lockchain-core` on top of it, which implements the `Vault` API.
+//!
+//! Writes are conditional on the ETag an object had when it was
+//! read. If another server changed an object in the meantime, `sync`
+//! fails with `VaultError::Conflict`, and `fetch` brings the vault
+//! up to date again. This needs a service that supports `If-Match`
+//! and `If-None-Match` on `PUT` (S3, MinIO, Ceph and others do).
+//!
+//! Requests are signed with AWS Signature Version 4 and use
+//! path-style URLs (`<endpoint>/<bucket>/<key>`).
+
+extern crate lockchain_core as lcc;
+
+use crate::lcc::errors::{Error as LockError, VaultError};
+use crate::lcc::store::StoreVault;
+use std::env;
+
+pub mod sign;
+mod store;
+
+pub use crate::sign::Credentials;
+pub use crate::store::S3Store;
+
+/// A vault in an S3 bucket
+pub type S3Vault<T> = StoreVault<S3Store, T>;
+
+/// Where to find a bucket, and how to access it
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// The URL of the service, i.e. `https://s3.eu-central-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub credentials: Credentials,
+}
+
+impl S3Config {
+    pub fn new(endpoint: &str, bucket: &str, credentials: Credentials) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            bucket: bucket.to_owned(),
+            credentials,
+        }
+    }
+
+    /// Configure a bucket from it's URL and the environment
+    ///
+    /// The location is the URL of the bucket, like
+    /// `http://localhost:9000/vaults`. Credentials are read from
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, the region
+    /// from `AWS_REGION` (`us-east-1` if it isn't set).
+    ///
+    /// This is what `S3Vault::new` and `S3Vault::load` use.
+    pub fn from_env(location: &str) -> Result<Self, LockError> {
+        let invalid =
+            || LockError::from(VaultError::InvalidPath).context(format!("location '{}'", location));
+        let (scheme, rest) = location.split_once("://").ok_or_else(invalid)?;
+        let (host, bucket) = rest
+            .trim_end_matches('/')
+            .split_once('/')
+            .ok_or_else(invalid)?;
+        if host.is_empty() || bucket.is_empty() || bucket.contains('/') {
+            return Err(invalid());
+        }
+
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                LockError::from(VaultError::FailedLoading).context(format!("variable '{}'", name))
+            })
+        };
+        let credentials = Credentials {
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            region: var("AWS_REGION").unwrap_or_else(|_| "us-east-1".into()),
+        };
+        Ok(Self::new(
+            &format!("{}://{}", scheme, host),
+            bucket,
+            credentials,
+        ))
+    }
+
+    /// The value of the `Host` header, without default ports
+    pub fn host(&self) -> String {
+        let (scheme, host) = self
+            .endpoint
+            .split_once("://")
+            .unwrap_or(("https", &self.endpoint));
+        let host = host.split('/').next().unwrap_or(host);
+        match (scheme, host.rsplit_once(':')) {
+            ("http", Some((name, "80"))) | ("https", Some((name, "443"))) => name.to_owned(),
+            _ => host.to_owned(),
+        }
+    }
+}
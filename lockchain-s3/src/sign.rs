@@ -0,0 +1,122 @@
+//! AWS Signature Version 4, as far as S3 needs it
+//!
+//! Every request is signed with the secret key of a set of
+//! `Credentials`. The signature covers the method, path, query,
+//! a set of headers and the hash of the payload.
+
+use crate::lcc::store::http::RESERVED;
+use hmac::{Hmac, Mac};
+use percent_encoding::utf8_percent_encode;
+use sha2::{Digest, Sha256};
+
+/// The keys to sign requests with
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    /// The region of the bucket, `us-east-1` for most S3-compatible services
+    pub region: String,
+}
+
+/// A request, as it is signed
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// The path, not yet URI encoded
+    pub path: &'a str,
+    pub query: &'a [(&'a str, &'a str)],
+    /// All headers to sign, including `host` and `x-amz-date`
+    pub headers: &'a [(&'a str, &'a str)],
+    /// The hex encoded SHA256 hash of the payload
+    pub payload: &'a str,
+}
+
+impl Credentials {
+    /// Compute the `Authorization` header of a request
+    ///
+    /// `time` is the value of the `x-amz-date` header.
+    pub fn authorization(&self, req: &Request, time: &str) -> String {
+        let date = &time[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+
+        let mut headers: Vec<_> = req
+            .headers
+            .iter()
+            .map(|(k, v)| (k.to_lowercase(), v.trim()))
+            .collect();
+        headers.sort();
+        let signed = headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method,
+            encode_path(req.path),
+            encode_query(req.query),
+            headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}\n", k, v))
+                .collect::<String>(),
+            signed,
+            req.payload
+        );
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time,
+            scope,
+            hash(canonical.as_bytes())
+        );
+
+        let key = [date, &self.region, "s3", "aws4_request"].iter().fold(
+            format!("AWS4{}", self.secret_key).into_bytes(),
+            |key, part| hmac(&key, part.as_bytes()),
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed,
+            hex::encode(hmac(&key, to_sign.as_bytes()))
+        )
+    }
+}
+
+/// The hex encoded SHA256 hash of some data
+pub fn hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// URI encode every segment of a path
+pub fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|s| utf8_percent_encode(s, RESERVED).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// URI encode a query, sorted by key
+pub fn encode_query(query: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<_> = query
+        .iter()
+        .map(|(k, v)| {
+            (
+                utf8_percent_encode(k, RESERVED).to_string(),
+                utf8_percent_encode(v, RESERVED).to_string(),
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
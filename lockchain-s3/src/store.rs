@@ -0,0 +1,305 @@
+//! A `BlobStore` on top of the S3 REST API
+
+use crate::lcc::errors::{DataError, Error as LockError, VaultError};
+use crate::lcc::store::http::Reply;
+use crate::lcc::store::{valid_key, Blob, BlobStore, Condition, Locate, Revision, Space};
+use crate::lcc::Generator;
+use std::io::Read;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Response};
+
+use crate::sign::{self, Request};
+use crate::S3Config;
+
+/// Stores blobs as objects in an S3 bucket, for use with a `StoreVault`
+///
+/// All objects of a vault live under a common prefix (the name of
+/// the vault), sorted by space:
+///
+/// - `<name>/records/<id>`
+/// - `<name>/metadata/<domain>`, including `<name>/metadata/userstore`
+/// - `<name>/index/headers`
+///
+/// Object ETags are used as revisions. Writes send them along as
+/// `If-Match` (or `If-None-Match: *` for new objects), so that the
+/// service rejects them if another writer got there first.
+///
+/// S3 has no transactions: a failed `sync` can leave some
+/// objects written. The header index is written last, so
+/// other handles notice that something changed. Conditional
+/// deletes are checked with a separate request, see `delete`.
+pub struct S3Store {
+    config: S3Config,
+    prefix: String,
+    agent: Agent,
+}
+
+impl S3Store {
+    /// Open the objects of a vault in a bucket
+    pub fn new(config: S3Config, name: &str) -> Self {
+        Self {
+            config,
+            prefix: name.to_owned(),
+            agent: AgentBuilder::new().timeout(Duration::from_secs(60)).build(),
+        }
+    }
+
+    /// The configuration this store talks to
+    pub fn config(&self) -> &S3Config {
+        &self.config
+    }
+
+    /// The key of an object, relative to the bucket
+    ///
+    /// Fails with `VaultError::InvalidName` for keys that `list`
+    /// wouldn't find again, i.e. keys containing a `/`.
+    fn key(&self, space: Space, key: &str) -> Result<String, LockError> {
+        if !valid_key(key) {
+            return Err(LockError::from(VaultError::InvalidName).context(format!("key '{}'", key)));
+        }
+        Ok(format!("{}/{}", self.space_prefix(space), key))
+    }
+
+    fn space_prefix(&self, space: Space) -> String {
+        let dir = match space {
+            Space::Records => "records",
+            Space::Metadata => "metadata",
+            Space::Index => "index",
+        };
+        format!("{}/{}", self.prefix, dir)
+    }
+
+    /// Send a signed request for an object (or the bucket if `key` is `None`)
+    fn send(
+        &self,
+        method: &str,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        extra: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Reply, LockError> {
+        let path = match key {
+            Some(key) => format!("/{}/{}", self.config.bucket, key),
+            None => format!("/{}", self.config.bucket),
+        };
+        let time = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload = sign::hash(body);
+        let host = self.config.host();
+
+        let mut headers = vec![
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload.as_str()),
+            ("x-amz-date", time.as_str()),
+        ];
+        headers.extend_from_slice(extra);
+        let auth = self.config.credentials.authorization(
+            &Request {
+                method,
+                path: &path,
+                query,
+                headers: &headers,
+                payload: &payload,
+            },
+            &time,
+        );
+
+        let mut url = format!("{}{}", self.config.endpoint, sign::encode_path(&path));
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&sign::encode_query(query));
+        }
+
+        let mut req = self.agent.request(method, &url).set("Authorization", &auth);
+        for (k, v) in headers.iter().filter(|(k, _)| *k != "host") {
+            req = req.set(k, v);
+        }
+        let res = if body.is_empty() && method != "PUT" {
+            req.call()
+        } else {
+            req.set("Content-Type", "application/octet-stream")
+                .send_bytes(body)
+        };
+
+        Reply::new(res, method, &url)
+    }
+
+    /// The current ETag of an object, if it exists
+    fn head(&self, key: &str) -> Result<Option<Revision>, LockError> {
+        match self.send("HEAD", Some(key), &[], &[], &[])? {
+            Reply::Ok(res) => Ok(etag(&res)),
+            Reply::Status(404) => Ok(None),
+            Reply::Status(code) => Err(status(DataError::FailedRead, code, key)),
+        }
+    }
+}
+
+impl BlobStore for S3Store {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        let key = self.key(space, key)?;
+        let res = match self.send("GET", Some(&key), &[], &[], &[])? {
+            Reply::Ok(res) => res,
+            Reply::Status(404) => return Ok(None),
+            Reply::Status(code) => return Err(status(DataError::FailedRead, code, &key)),
+        };
+
+        let revision = etag(&res)
+            .ok_or_else(|| status(DataError::FailedRead, 200, &key).context("missing ETag"))?;
+        let mut data = Vec::new();
+        res.into_reader()
+            .read_to_end(&mut data)
+            .map_err(|e| status(DataError::FailedRead, 200, &key).caused_by(e))?;
+        Ok(Some(Blob { data, revision }))
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        let object = self.key(space, key)?;
+        let extra: Vec<(&str, &str)> = match condition {
+            Condition::Any => vec![],
+            Condition::Absent => vec![("if-none-match", "*")],
+            Condition::Matches(rev) => vec![("if-match", rev.as_str())],
+        };
+
+        match self.send("PUT", Some(&object), &[], &extra, &data)? {
+            Reply::Ok(res) => etag(&res).ok_or_else(|| {
+                status(VaultError::FailedSync, 200, &object).context("missing ETag")
+            }),
+            Reply::Status(409) | Reply::Status(412) => Err(Condition::failed(space, key)),
+            Reply::Status(code) => Err(status(VaultError::FailedSync, code, &object)),
+        }
+    }
+
+    /// Conditions are checked with a `HEAD` request first
+    ///
+    /// Many S3-compatible services ignore `If-Match` on `DELETE`,
+    /// so it is only sent along as an extra safeguard. On those,
+    /// an object that changes between the check and the delete
+    /// is still removed: deletes are not atomic.
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        let object = self.key(space, key)?;
+        let extra = match condition {
+            Condition::Any => vec![],
+            Condition::Absent => {
+                return match self.head(&object)? {
+                    Some(_) => Err(Condition::failed(space, key)),
+                    None => Ok(()),
+                };
+            }
+            Condition::Matches(rev) => {
+                if self.head(&object)?.as_ref() != Some(rev) {
+                    return Err(Condition::failed(space, key));
+                }
+                vec![("if-match", rev.as_str())]
+            }
+        };
+
+        match self.send("DELETE", Some(&object), &[], &extra, &[])? {
+            Reply::Ok(_) => Ok(()),
+            Reply::Status(404) if *condition == Condition::Any => Ok(()),
+            Reply::Status(404) | Reply::Status(409) | Reply::Status(412) => {
+                Err(Condition::failed(space, key))
+            }
+            Reply::Status(code) => Err(status(VaultError::FailedSync, code, &object)),
+        }
+    }
+
+    /// Lists objects directly under the prefix of a space
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        let prefix = format!("{}/", self.space_prefix(space));
+        let mut found = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+            if let Some(ref token) = token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let res = match self.send("GET", None, &query, &[], &[])? {
+                Reply::Ok(res) => res,
+                Reply::Status(code) => return Err(status(DataError::FailedRead, code, &prefix)),
+            };
+            let body = res
+                .into_string()
+                .map_err(|e| status(DataError::FailedRead, 200, &prefix).caused_by(e))?;
+            let page = parse_listing(&body)
+                .map_err(|e| LockError::from(DataError::FailedDecode).context(e))?;
+
+            found.extend(page.objects.into_iter().filter_map(|(key, etag)| {
+                let name = key.strip_prefix(&prefix)?;
+                if valid_key(name) {
+                    Some((name.to_owned(), etag))
+                } else {
+                    None
+                }
+            }));
+            match page.next {
+                Some(next) => token = Some(next),
+                None => return Ok(found),
+            }
+        }
+    }
+}
+
+impl Locate for S3Store {
+    /// The location is the URL of the bucket, see `S3Config::from_env`
+    fn create(gen: &Generator) -> Result<Self, LockError> {
+        match gen {
+            Generator {
+                name: Some(ref name),
+                location: Some(ref location),
+                ..
+            } => Ok(Self::new(S3Config::from_env(location)?, name)),
+            _ => Err(VaultError::IncompleteGenerator.into()),
+        }
+    }
+
+    /// The location is the URL of the bucket, see `S3Config::from_env`
+    fn open(name: &str, location: &str) -> Result<Self, LockError> {
+        Ok(Self::new(S3Config::from_env(location)?, name))
+    }
+}
+
+/// One page of a `ListObjectsV2` response
+struct Listing {
+    objects: Vec<(String, Revision)>,
+    /// The continuation token, if the listing was truncated
+    next: Option<String>,
+}
+
+fn parse_listing(xml: &str) -> Result<Listing, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+    let text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.tag_name().name() == name)
+            .and_then(|c| c.text())
+            .map(|t| t.to_owned())
+    };
+
+    let root = doc.root_element();
+    let objects = root
+        .children()
+        .filter(|c| c.tag_name().name() == "Contents")
+        .filter_map(|c| Some((text(c, "Key")?, text(c, "ETag").unwrap_or_default())))
+        .collect();
+    let next = match text(root, "IsTruncated").as_deref() {
+        Some("true") => text(root, "NextContinuationToken"),
+        _ => None,
+    };
+    Ok(Listing { objects, next })
+}
+
+fn etag(res: &Response) -> Option<Revision> {
+    res.header("ETag").map(|e| e.to_owned())
+}
+
+fn status<E: Into<LockError>>(error: E, code: u16, key: &str) -> LockError {
+    error
+        .into()
+        .context(format!("HTTP {} for object '{}'", code, key))
+}
@@ -0,0 +1,60 @@
+mod mock;
+
+use lockchain_core::errors::Error as LockError;
+use lockchain_core::store::{Blob, BlobStore, Condition, Locate, Revision, Space, StoreVault};
+use lockchain_core::{testing::TestBody, vault_conformance, Generator};
+use lockchain_s3::S3Store;
+use std::path::Path;
+
+/// An `S3Store` that finds it's vault on the mock server
+struct Mocked(S3Store);
+
+impl Mocked {
+    /// Every fixture gets it's own prefix in a shared bucket
+    fn open(name: &str, location: &str) -> Self {
+        let dir = Path::new(location).file_name().unwrap().to_str().unwrap();
+        Mocked(S3Store::new(
+            mock::config("conformance"),
+            &format!("{}/{}", dir, name),
+        ))
+    }
+}
+
+impl BlobStore for Mocked {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        self.0.get(space, key)
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        self.0.put(space, key, data, condition)
+    }
+
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        self.0.delete(space, key, condition)
+    }
+
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        self.0.list(space)
+    }
+}
+
+impl Locate for Mocked {
+    fn create(gen: &Generator) -> Result<Self, LockError> {
+        Ok(Self::open(
+            gen.name.as_ref().unwrap(),
+            gen.location.as_ref().unwrap(),
+        ))
+    }
+
+    fn open(name: &str, location: &str) -> Result<Self, LockError> {
+        Ok(Self::open(name, location))
+    }
+}
+
+vault_conformance!(s3_store, StoreVault<Mocked, TestBody>);
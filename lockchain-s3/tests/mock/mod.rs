@@ -0,0 +1,203 @@
+//! An in-process stand-in for the parts of the S3 API that `S3Store` uses
+//!
+//! Keeps objects in memory, honours `If-Match` and `If-None-Match`,
+//! and returns listings in pages of two to exercise continuation.
+//! Every request has to be signed with `credentials()`.
+
+use lockchain_s3::sign::{self, Request as Signed};
+use lockchain_s3::{Credentials, S3Config};
+use percent_encoding::percent_decode_str;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const PAGE: usize = 2;
+
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, (Vec<u8>, String)>,
+    counter: u64,
+}
+
+/// The configuration of a bucket on the (shared) mock server
+pub fn config(bucket: &str) -> S3Config {
+    static ENDPOINT: OnceLock<String> = OnceLock::new();
+    let endpoint = ENDPOINT.get_or_init(|| {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            let buckets = Mutex::new(BTreeMap::new());
+            for req in server.incoming_requests() {
+                handle(&buckets, req);
+            }
+        });
+        endpoint
+    });
+
+    S3Config::new(endpoint, bucket, credentials())
+}
+
+/// The only credentials the mock accepts
+pub fn credentials() -> Credentials {
+    Credentials {
+        access_key: "AKIDEXAMPLE".into(),
+        secret_key: "secret".into(),
+        region: "us-east-1".into(),
+    }
+}
+
+fn handle(buckets: &Mutex<BTreeMap<String, Bucket>>, mut req: Request) {
+    let url = req.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let path = percent_decode_str(path).decode_utf8_lossy().into_owned();
+    let query: BTreeMap<_, _> = query
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| {
+            (
+                percent_decode_str(k).decode_utf8_lossy().into_owned(),
+                percent_decode_str(v).decode_utf8_lossy().into_owned(),
+            )
+        })
+        .collect();
+
+    let mut body = Vec::new();
+    req.as_reader().read_to_end(&mut body).unwrap();
+    if !signed(&req, &path, &query, &body) {
+        return reply(req, 403, vec![], None);
+    }
+
+    let (bucket, key) = path[1..].split_once('/').unwrap_or((&path[1..], ""));
+    let if_match = header(&req, "If-Match");
+    let if_none_match = header(&req, "If-None-Match");
+
+    let mut buckets = buckets.lock().unwrap();
+    let bucket = buckets.entry(bucket.to_owned()).or_default();
+    let current = bucket.objects.get(key).map(|(_, etag)| etag.clone());
+    let failed = match (&if_match, &if_none_match) {
+        (Some(etag), _) => current.as_ref() != Some(etag),
+        (_, Some(_)) => current.is_some(),
+        _ => false,
+    };
+
+    match (req.method(), key) {
+        (Method::Get, "") => {
+            let listing = list(bucket, &query);
+            reply(req, 200, listing.into_bytes(), None)
+        }
+        (Method::Get, _) | (Method::Head, _) => match bucket.objects.get(key) {
+            Some((data, etag)) => {
+                let data = if *req.method() == Method::Get {
+                    data.clone()
+                } else {
+                    vec![]
+                };
+                let etag = etag.clone();
+                reply(req, 200, data, Some(etag))
+            }
+            None => reply(req, 404, vec![], None),
+        },
+        (Method::Put, _) if failed => reply(req, 412, vec![], None),
+        (Method::Put, _) => {
+            bucket.counter += 1;
+            let etag = format!("\"{:08x}\"", bucket.counter);
+            bucket.objects.insert(key.to_owned(), (body, etag.clone()));
+            reply(req, 200, vec![], Some(etag))
+        }
+        (Method::Delete, _) if failed => reply(req, 412, vec![], None),
+        (Method::Delete, _) => {
+            bucket.objects.remove(key);
+            reply(req, 204, vec![], None)
+        }
+        _ => reply(req, 405, vec![], None),
+    }
+}
+
+/// Check the signature of a request, by signing it again
+fn signed(req: &Request, path: &str, query: &BTreeMap<String, String>, body: &[u8]) -> bool {
+    let auth = header(req, "Authorization").unwrap_or_default();
+    let names = match auth
+        .split(", ")
+        .find_map(|p| p.strip_prefix("SignedHeaders="))
+    {
+        Some(names) => names,
+        None => return false,
+    };
+    let values: Option<Vec<_>> = names
+        .split(';')
+        .map(|n| header(req, n).map(|v| (n, v)))
+        .collect();
+    let (values, time) = match (values, header(req, "x-amz-date")) {
+        (Some(values), Some(time)) if time.len() > 8 => (values, time),
+        _ => return false,
+    };
+    let payload = sign::hash(body);
+    if header(req, "x-amz-content-sha256").as_ref() != Some(&payload) {
+        return false;
+    }
+
+    let headers: Vec<_> = values.iter().map(|(n, v)| (*n, v.as_str())).collect();
+    let query: Vec<_> = query
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let expected = credentials().authorization(
+        &Signed {
+            method: req.method().as_str(),
+            path,
+            query: &query,
+            headers: &headers,
+            payload: &payload,
+        },
+        &time,
+    );
+    auth == expected
+}
+
+fn header(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str().to_owned())
+}
+
+/// A page of a `ListObjectsV2` response
+fn list(bucket: &Bucket, query: &BTreeMap<String, String>) -> String {
+    let prefix = query.get("prefix").map(|p| p.as_str()).unwrap_or("");
+    let after = query
+        .get("continuation-token")
+        .map(|t| t.as_str())
+        .unwrap_or("");
+    let keys: Vec<_> = bucket
+        .objects
+        .iter()
+        .filter(|(k, _)| k.starts_with(prefix) && k.as_str() > after)
+        .collect();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult>");
+    for (key, (_, etag)) in keys.iter().take(PAGE) {
+        xml.push_str(&format!(
+            "<Contents><Key>{}</Key><ETag>{}</ETag></Contents>",
+            key,
+            etag.replace('"', "&quot;")
+        ));
+    }
+    if keys.len() > PAGE {
+        xml.push_str(&format!(
+            "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+            keys[PAGE - 1].0
+        ));
+    } else {
+        xml.push_str("<IsTruncated>false</IsTruncated>");
+    }
+    xml + "</ListBucketResult>"
+}
+
+fn reply(req: Request, code: u16, data: Vec<u8>, etag: Option<String>) {
+    let mut res = Response::from_data(data).with_status_code(code);
+    if let Some(etag) = etag {
+        res = res.with_header(Header::from_bytes("ETag", etag).unwrap());
+    }
+    let _ = req.respond(res);
+}
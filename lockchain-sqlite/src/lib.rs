@@ -0,0 +1,298 @@
+//! An SQLite storage backend for lockchain vaults
+//!
+//! `SqliteVault` keeps an entire vault in a single database file,
+//! which scales to far more records than one file per record and
+//! gets transactions for free: all changes made since the last
+//! `sync` are written in one transaction, so either all of them
+//! end up in the database or none.
+//!
+//! Record headers are kept in indexed columns, which means that
+//! category, tag and date filters of a `Query` run in SQL. Bodies,
+//! metadata domains and users are stored as opaque blobs, encoded
+//! with the vault's `Codec`. With `EncryptedBody` (see
+//! `lockchain-crypto`) the database never contains cleartext secrets.
+//!
+//! Vaults can be copied from and to the `FileVault` layout with
+//! `SqliteVault::import` and `SqliteVault::export`.
+
+extern crate lockchain_core as lcc;
+extern crate lockchain_files as files;
+
+use crate::lcc::codec::{self, Codec};
+use crate::lcc::query::Query;
+use crate::lcc::state::{VaultState, USERS};
+use crate::lcc::traits::{AutoEncoder, Body, LoadRecord, Vault};
+use crate::lcc::{
+    errors::{about, DataError, Error as LockError, VaultError},
+    users::UserStore,
+    Generator, Header, MetaDomain, Record, VaultMetadata,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+mod query;
+mod schema;
+mod transfer;
+
+pub use crate::schema::SCHEMA_VERSION;
+use crate::schema::{failed_read, failed_write};
+
+/// A vault in an SQLite database
+///
+/// The database lives at `<location>/<name>.sqlite`. Opening a
+/// vault reads all headers and metadata domains, record bodies are
+/// read when a record is pulled. Changes are kept in memory until
+/// they are written by `sync`.
+///
+/// SQLite itself takes care of locking, so a database can be
+/// opened by several handles. There is no conflict detection
+/// between them though: the last `sync` to write a record wins.
+/// Only record names are checked, a `sync` fails with
+/// `VaultError::RecordExists` if another handle already
+/// wrote a different record of the same name.
+pub struct SqliteVault<T: Body> {
+    db: Connection,
+    name: String,
+    location: String,
+    /// The codec that headers, bodies and domains are written with
+    codec: Codec,
+    state: VaultState<T>,
+}
+
+impl<T: Body> SqliteVault<T> {
+    /// The path of the database for a vault
+    pub fn path(name: &str, location: &str) -> PathBuf {
+        PathBuf::from(location).join(format!("{}.sqlite", name))
+    }
+
+    /// The codec that new data is written with
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Write data with a different codec from now on
+    ///
+    /// Rows that were written before are converted
+    /// the next time they change.
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+
+    /// Make sure a record is loaded
+    fn pulled(&mut self, record: &str) -> Result<(), LockError> {
+        let id = self.state.find(record)?;
+        if !self.state.is_pulled(&id) {
+            self.pull(record)?;
+        }
+        Ok(())
+    }
+
+    /// Take over a record, replacing any other record of the same name
+    fn insert(&mut self, record: Record<T>) {
+        let id = record.header.id;
+        if let Some(old) = self
+            .state
+            .named(&record.header.name)
+            .filter(|old| *old != id)
+        {
+            self.state.forget(&old);
+            self.state.dirty.remove(old);
+        }
+
+        self.state.insert(record);
+        self.state.dirty.record(id);
+    }
+}
+
+impl<T: Body> LoadRecord<T> for SqliteVault<T> {}
+
+impl<T: Body> Vault<T> for SqliteVault<T> {
+    /// Fails with `VaultError::AlreadyExists` if the database exists
+    fn new(gen: Generator) -> Result<Box<Self>, LockError> {
+        let (name, location) = match gen {
+            Generator {
+                name: Some(ref n),
+                location: Some(ref l),
+                ..
+            } => (n.clone(), l.clone()),
+            _ => return Err(VaultError::IncompleteGenerator.into()),
+        };
+
+        let path = Self::path(&name, &location);
+        if path.exists() {
+            return Err(about(VaultError::AlreadyExists, "path", path.display()));
+        }
+
+        let vault_type = gen.user_type.ok_or(VaultError::IncompleteGenerator)?;
+
+        let mut db = Connection::open(&path)
+            .map_err(|e| about(VaultError::FailedCreation, "path", path.display()).caused_by(e))?;
+        schema::create(&db)?;
+        let tx = db.transaction().map_err(failed_write)?;
+        schema::set_setting(&tx, "version", schema::SCHEMA_VERSION.as_bytes())?;
+        tx.commit().map_err(failed_write)?;
+
+        let mut me = Self {
+            db,
+            name,
+            location,
+            codec: gen.codec.unwrap_or_default(),
+            state: VaultState::create(&vault_type),
+        };
+        me.sync()?;
+        Ok(Box::new(me))
+    }
+
+    fn load(name: &str, location: &str) -> Result<Box<Self>, LockError> {
+        let path = Self::path(name, location);
+        if !path.is_file() {
+            return Err(about(VaultError::InvalidPath, "path", path.display()));
+        }
+
+        let db = Connection::open(&path)
+            .map_err(|e| about(VaultError::FailedLoading, "path", path.display()).caused_by(e))?;
+        schema::check(&db)?;
+        let codec = match schema::setting(&db, "codec")? {
+            Some(c) => String::from_utf8_lossy(&c).parse()?,
+            None => Codec::default(),
+        };
+        let users = schema::setting(&db, USERS)?
+            .ok_or_else(|| about(VaultError::FailedLoading, "vault", name))?;
+        let users = UserStore::from_bytes(&users)?;
+
+        let mut me = Self {
+            db,
+            name: name.into(),
+            location: location.into(),
+            codec,
+            state: VaultState::new(users),
+        };
+        me.fetch()?;
+        Ok(Box::new(me))
+    }
+
+    lcc::forward_to_state! {
+        state: authenticate, deauthenticate, create_user, delete_user, get_record, contains,
+            add_record, delete_record, get_data, meta_add_domain, meta_pull_domain,
+            meta_push_domain, meta_set, meta_get, meta_remove, meta_exists;
+        pulled: rename_record, add_data, remove_data
+    }
+
+    fn metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            name: self.name.clone(),
+            location: self.location.clone(),
+            size: self.state.len(),
+        }
+    }
+
+    /// Reads all record headers and metadata domains from the database
+    ///
+    /// Loaded record bodies are dropped, unsynced changes to
+    /// records and domains are lost.
+    fn fetch(&mut self) -> Result<(), LockError> {
+        let mut headers = Vec::new();
+        for data in schema::column(&self.db, "SELECT header FROM records")? {
+            headers.push(codec::decode::<Header>(&data)?);
+        }
+
+        let mut metadata = HashMap::new();
+        for data in schema::column(&self.db, "SELECT data FROM domains")? {
+            let domain = MetaDomain::from_bytes(&data)?;
+            metadata.insert(domain.name().to_owned(), domain);
+        }
+
+        self.state.clear();
+        headers
+            .into_iter()
+            .for_each(|h| self.state.insert_header(h));
+        self.state.metadata = metadata;
+        self.state.reset();
+        Ok(())
+    }
+
+    fn pull(&mut self, name: &str) -> Result<(), LockError> {
+        let id = self.state.find(name)?;
+        let (header, body): (Vec<u8>, Option<Vec<u8>>) = self
+            .db
+            .query_row(
+                "SELECT header, body FROM records WHERE id = ?1",
+                params![id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(failed_read)?
+            .ok_or_else(|| about(DataError::FailedRead, "record", name))?;
+
+        let decoded = codec::decode(&header).and_then(|header: Header| {
+            Ok(Record {
+                header,
+                body: match body {
+                    Some(body) => Some(codec::decode(&body)?),
+                    None => Some(T::default()),
+                },
+            })
+        });
+        let rec = decoded.map_err(|e| e.context(format!("record '{}'", name)))?;
+
+        self.state.insert(rec);
+        Ok(())
+    }
+
+    /// Writes everything that changed since the last sync, in a single transaction
+    fn sync(&mut self) -> Result<(), LockError> {
+        let tx = self.db.transaction().map_err(failed_write)?;
+        let dirty = &self.state.dirty;
+
+        for id in &dirty.removed {
+            schema::delete_record(&tx, &id.to_string())?;
+        }
+        for id in &dirty.records {
+            schema::release_name(&tx, &id.to_string())?;
+        }
+        for id in &dirty.records {
+            if let Some(rec) = self.state.record(id) {
+                let header = self.codec.encode(&rec.header)?;
+                let body = match rec.body {
+                    Some(ref body) => Some(self.codec.encode(body)?),
+                    None => None,
+                };
+                schema::put_record(&tx, &rec.header, &header, body.as_deref())?;
+            }
+        }
+        for name in &dirty.domains {
+            if let Some(domain) = self.state.metadata.get(name) {
+                tx.execute(
+                    "INSERT OR REPLACE INTO domains (name, data) VALUES (?1, ?2)",
+                    params![name, domain.to_bytes(self.codec)?],
+                )
+                .map_err(failed_write)?;
+            }
+        }
+        if dirty.users {
+            schema::set_setting(&tx, USERS, &self.state.users.to_bytes(self.codec)?)?;
+        }
+        schema::set_setting(&tx, "codec", self.codec.to_string().as_bytes())?;
+
+        tx.commit().map_err(failed_write)?;
+        self.state.dirty = Default::default();
+        Ok(())
+    }
+
+    /// Filters by category, tags and dates run in SQL
+    ///
+    /// Records with unsynced changes are checked in memory instead.
+    fn query(&self, query: &Query) -> Vec<&Header> {
+        let found = match query::candidates(&self.db, query) {
+            Ok(found) => found,
+            Err(_) => return self.state.query(query),
+        };
+        let dirty = &self.state.dirty.records;
+        query.run(
+            self.state
+                .headers()
+                .filter(|h| found.contains(&h.id) || dirty.contains(&h.id)),
+        )
+    }
+}
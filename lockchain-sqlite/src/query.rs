@@ -0,0 +1,85 @@
+//! Running queries in SQL
+//!
+//! Category, tag and date filters are translated into a `WHERE`
+//! clause over the indexed columns. Filters on custom fields
+//! can't be expressed in SQL, they are applied afterwards along
+//! with sorting and pagination.
+
+use crate::lcc::errors::Error as LockError;
+use crate::lcc::query::{Filter, Limit, Query, Range};
+use crate::lcc::Uuid;
+use chrono::{DateTime, Local};
+use rusqlite::{params_from_iter, types::Value, Connection};
+use std::collections::HashSet;
+
+use crate::schema::{failed_read, timestamp};
+
+/// The ids of all stored records that pass the SQL filters of a query
+pub(crate) fn candidates(db: &Connection, query: &Query) -> Result<HashSet<Uuid>, LockError> {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+
+    for filter in &query.filters {
+        match filter {
+            Filter::Category(category) => {
+                clauses.push("category = ?".to_owned());
+                values.push(Value::from(category.clone()));
+            }
+            Filter::AnyTag(tags) => {
+                let marks = vec!["?"; tags.len()].join(", ");
+                clauses.push(format!(
+                    "id IN (SELECT record FROM tags WHERE tag IN ({}))",
+                    marks
+                ));
+                values.extend(tags.iter().cloned().map(Value::from));
+            }
+            Filter::AllTags(tags) => {
+                for tag in tags {
+                    clauses.push("id IN (SELECT record FROM tags WHERE tag = ?)".to_owned());
+                    values.push(Value::from(tag.clone()));
+                }
+            }
+            Filter::Created(range) => within("created", range, &mut clauses, &mut values),
+            Filter::Updated(range) => within("updated", range, &mut clauses, &mut values),
+            Filter::FieldEquals(..) | Filter::FieldRange(..) => {}
+        }
+    }
+
+    let mut sql = "SELECT id FROM records".to_owned();
+    if !clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&clauses.join(" AND "));
+    }
+
+    let mut stmt = db.prepare(&sql).map_err(failed_read)?;
+    let ids = stmt
+        .query_map(params_from_iter(values), |row| row.get::<_, String>(0))
+        .map_err(failed_read)?;
+
+    let mut found = HashSet::new();
+    for id in ids {
+        if let Ok(id) = Uuid::parse_str(&id.map_err(failed_read)?) {
+            found.insert(id);
+        }
+    }
+    Ok(found)
+}
+
+/// Limit a date column to a range
+fn within(
+    column: &str,
+    range: &Range<DateTime<Local>>,
+    clauses: &mut Vec<String>,
+    values: &mut Vec<Value>,
+) {
+    let bounds = [(&range.lower, ">=", ">"), (&range.upper, "<=", "<")];
+    for (limit, included, excluded) in bounds.iter() {
+        let (op, date) = match limit {
+            Limit::Unbounded => continue,
+            Limit::Included(date) => (included, date),
+            Limit::Excluded(date) => (excluded, date),
+        };
+        clauses.push(format!("{} {} ?", column, op));
+        values.push(Value::from(timestamp(date)));
+    }
+}
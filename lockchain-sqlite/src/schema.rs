@@ -0,0 +1,180 @@
+//! The database layout of a vault
+//!
+//! Headers are stored twice: in full as an encoded blob, and split
+//! into indexed columns (plus a `tags` table) so that queries can
+//! run in SQL. Bodies and metadata domains are opaque blobs,
+//! encoded with the codec of the vault.
+
+use crate::lcc::errors::{DataError, Error as LockError, VaultError};
+use crate::lcc::Header;
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Transaction};
+
+/// The layout version written by this library
+pub const SCHEMA_VERSION: &str = "1";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS settings (
+        key     TEXT PRIMARY KEY,
+        value   BLOB NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS records (
+        id          TEXT PRIMARY KEY,
+        name        TEXT NOT NULL UNIQUE,
+        category    TEXT NOT NULL,
+        created     INTEGER NOT NULL,
+        updated     INTEGER NOT NULL,
+        header      BLOB NOT NULL,
+        body        BLOB
+    );
+    CREATE INDEX IF NOT EXISTS records_category ON records (category);
+    CREATE INDEX IF NOT EXISTS records_created ON records (created);
+    CREATE INDEX IF NOT EXISTS records_updated ON records (updated);
+
+    CREATE TABLE IF NOT EXISTS tags (
+        record  TEXT NOT NULL,
+        tag     TEXT NOT NULL,
+        PRIMARY KEY (record, tag)
+    );
+    CREATE INDEX IF NOT EXISTS tags_tag ON tags (tag);
+
+    CREATE TABLE IF NOT EXISTS domains (
+        name    TEXT PRIMARY KEY,
+        data    BLOB NOT NULL
+    );
+";
+
+/// Create all tables of an empty database
+pub(crate) fn create(db: &Connection) -> Result<(), LockError> {
+    db.execute_batch(SCHEMA)
+        .map_err(|e| LockError::from(VaultError::FailedCreation).caused_by(e))
+}
+
+/// Check that a database was written with a compatible layout
+pub(crate) fn check(db: &Connection) -> Result<(), LockError> {
+    match setting(db, "version")? {
+        Some(ref v) if v.as_slice() == SCHEMA_VERSION.as_bytes() => Ok(()),
+        Some(v) => Err(VaultError::IncompatibleVersion {
+            found: String::from_utf8_lossy(&v).into_owned(),
+            supported: SCHEMA_VERSION.into(),
+        }
+        .into()),
+        None => Err(VaultError::CorruptedConfig.into()),
+    }
+}
+
+/// Read a value from the `settings` table
+pub(crate) fn setting(db: &Connection, key: &str) -> Result<Option<Vec<u8>>, LockError> {
+    db.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(failed_read)
+}
+
+/// Read the first column of all rows a statement returns
+pub(crate) fn column(db: &Connection, sql: &str) -> Result<Vec<Vec<u8>>, LockError> {
+    let mut stmt = db.prepare(sql).map_err(failed_read)?;
+    let rows = stmt
+        .query_map(params![], |row| row.get(0))
+        .map_err(failed_read)?;
+    rows.collect::<Result<_, _>>().map_err(failed_read)
+}
+
+/// Write a value to the `settings` table
+pub(crate) fn set_setting(tx: &Transaction, key: &str, value: &[u8]) -> Result<(), LockError> {
+    tx.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )
+    .map(|_| ())
+    .map_err(failed_write)
+}
+
+/// Insert or replace a record, along with it's tags
+///
+/// The body is only replaced if one is given. Fails with
+/// `VaultError::RecordExists` if another record has the same name.
+pub(crate) fn put_record(
+    tx: &Transaction,
+    header: &Header,
+    encoded: &[u8],
+    body: Option<&[u8]>,
+) -> Result<(), LockError> {
+    let id = header.id.to_string();
+    tx.execute(
+        "INSERT INTO records (id, name, category, created, updated, header, body)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name, category = excluded.category,
+            created = excluded.created, updated = excluded.updated,
+            header = excluded.header, body = coalesce(excluded.body, body)",
+        params![
+            id,
+            header.name,
+            header.category,
+            timestamp(&header.date_created),
+            timestamp(&header.date_updated),
+            encoded,
+            body
+        ],
+    )
+    .map_err(|e| match e.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => LockError::from(VaultError::RecordExists)
+            .context(format!("record '{}'", header.name))
+            .caused_by(e),
+        _ => failed_write(e),
+    })?;
+
+    tx.execute("DELETE FROM tags WHERE record = ?1", params![id])
+        .map_err(failed_write)?;
+    let mut insert = tx
+        .prepare_cached("INSERT OR IGNORE INTO tags (record, tag) VALUES (?1, ?2)")
+        .map_err(failed_write)?;
+    for tag in &header.tags {
+        insert.execute(params![id, tag]).map_err(failed_write)?;
+    }
+    Ok(())
+}
+
+/// Give records a placeholder name, their id
+///
+/// Records that swap names in one sync would otherwise run
+/// into the `UNIQUE` constraint on names, depending on the
+/// order they are written in.
+pub(crate) fn release_name(tx: &Transaction, id: &str) -> Result<(), LockError> {
+    tx.execute("UPDATE records SET name = id WHERE id = ?1", params![id])
+        .map(|_| ())
+        .map_err(failed_write)
+}
+
+/// Remove a record and it's tags
+pub(crate) fn delete_record(tx: &Transaction, id: &str) -> Result<(), LockError> {
+    tx.execute("DELETE FROM tags WHERE record = ?1", params![id])
+        .and_then(|_| tx.execute("DELETE FROM records WHERE id = ?1", params![id]))
+        .map(|_| ())
+        .map_err(failed_write)
+}
+
+/// Dates are stored as nanoseconds since the epoch, which sort correctly
+///
+/// That only covers the years 1677 to 2262, dates outside of it
+/// are clamped. Imported and restored headers, as well as query
+/// bounds, can carry any date.
+pub(crate) fn timestamp(date: &DateTime<Local>) -> i64 {
+    let secs = date.timestamp();
+    secs.checked_mul(1_000_000_000)
+        .and_then(|nanos| nanos.checked_add(i64::from(date.timestamp_subsec_nanos())))
+        .unwrap_or(if secs < 0 { i64::MIN } else { i64::MAX })
+}
+
+pub(crate) fn failed_read(e: rusqlite::Error) -> LockError {
+    LockError::from(DataError::FailedRead).caused_by(e)
+}
+
+pub(crate) fn failed_write(e: rusqlite::Error) -> LockError {
+    LockError::from(VaultError::FailedSync).caused_by(e)
+}
@@ -0,0 +1,142 @@
+//! Copying vaults from and to the `FileVault` layout
+//!
+//! Both directions go through a `FileStore` from `lockchain-files`,
+//! which reads and writes the files of a vault folder without
+//! interpreting them. Records keep their ids, so a vault can be
+//! moved back and forth.
+
+use crate::files::FileStore;
+use crate::lcc::errors::Error as LockError;
+use crate::lcc::state::{HeaderIndex, IndexEntry, INDEX, USERS};
+use crate::lcc::store::{BlobStore, Condition, Space};
+use crate::lcc::traits::{AutoEncoder, Body, Vault};
+use crate::lcc::users::UserStore;
+use crate::lcc::{codec, Header, MetaDomain, Record, Uuid};
+use rusqlite::params;
+use std::collections::HashSet;
+
+use crate::schema::failed_read;
+use crate::SqliteVault;
+
+impl<T: Body> SqliteVault<T> {
+    /// Copy all records, metadata domains and users out of a vault folder
+    ///
+    /// Records replace those with the same id or name, the users of
+    /// the folder replace the users of this vault. Everything is
+    /// written in a single `sync`. Returns the number of imported
+    /// records.
+    pub fn import(&mut self, store: &FileStore) -> Result<usize, LockError> {
+        let mut count = 0;
+        for (key, _) in store.list(Space::Records)? {
+            /* Only files named after their record are part of the vault */
+            let id = match Uuid::parse_str(&key) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let blob = match store.get(Space::Records, &key)? {
+                Some(blob) => blob,
+                None => continue,
+            };
+            let record = Record::<T>::from_bytes(&blob.data)
+                .map_err(|e| e.context(format!("record '{}'", key)))?;
+            if record.header.id == id {
+                self.insert(record);
+                count += 1;
+            }
+        }
+
+        for (key, _) in store.list(Space::Metadata)? {
+            let blob = match store.get(Space::Metadata, &key)? {
+                Some(blob) => blob,
+                None => continue,
+            };
+            if key == USERS {
+                self.state.users = UserStore::from_bytes(&blob.data)?;
+                self.state.dirty.users = true;
+            } else {
+                let domain = MetaDomain::from_bytes(&blob.data)
+                    .map_err(|e| e.context(format!("domain '{}'", key)))?;
+                self.state.dirty.domain(&key);
+                self.state.metadata.insert(domain.name().into(), domain);
+            }
+        }
+
+        self.sync()?;
+        Ok(count)
+    }
+
+    /// Write all records, metadata domains and users into a vault folder
+    ///
+    /// Syncs first, so that only stored data is exported. Files
+    /// already in the folder are overwritten, and record files of
+    /// records that aren't in this vault are removed. The header
+    /// index is written last, so that a `FileVault` sees the
+    /// exported headers. Returns the number of exported records.
+    pub fn export(&mut self, store: &mut FileStore) -> Result<usize, LockError> {
+        self.sync()?;
+
+        let mut stmt = self
+            .db
+            .prepare("SELECT header, body FROM records")
+            .map_err(failed_read)?;
+        let rows = stmt
+            .query_map(params![], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?))
+            })
+            .map_err(failed_read)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (header, body) = row.map_err(failed_read)?;
+            let header: Header = codec::decode(&header)?;
+            let body: Option<T> = match body {
+                Some(body) => Some(codec::decode(&body)?),
+                None => None,
+            };
+
+            let key = header.id.to_string();
+            let mut record = Record { header, body };
+            let data = record.to_bytes(self.codec);
+            if let Some(ref mut body) = record.body {
+                body.zeroize();
+            }
+            let sum = store.put(Space::Records, &key, data?, &Condition::Any)?;
+            entries.push(IndexEntry {
+                sum: Some(sum),
+                header: record.header,
+            });
+        }
+
+        let exported: HashSet<String> = entries.iter().map(|e| e.header.id.to_string()).collect();
+        for (key, _) in store.list(Space::Records)? {
+            if !exported.contains(&key) {
+                store.delete(Space::Records, &key, &Condition::Any)?;
+            }
+        }
+
+        for (name, domain) in &self.state.metadata {
+            store.put(
+                Space::Metadata,
+                name,
+                domain.to_bytes(self.codec)?,
+                &Condition::Any,
+            )?;
+        }
+        store.put(
+            Space::Metadata,
+            USERS,
+            self.state.users.to_bytes(self.codec)?,
+            &Condition::Any,
+        )?;
+
+        let count = entries.len();
+        entries.sort_by_key(|e| e.header.id);
+        store.put(
+            Space::Index,
+            INDEX,
+            HeaderIndex { entries }.to_bytes(self.codec)?,
+            &Condition::Any,
+        )?;
+        Ok(count)
+    }
+}
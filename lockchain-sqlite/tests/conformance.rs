@@ -0,0 +1,4 @@
+use lockchain_core::{testing::TestBody, vault_conformance};
+use lockchain_sqlite::SqliteVault;
+
+vault_conformance!(sqlite_vault, SqliteVault<TestBody>);
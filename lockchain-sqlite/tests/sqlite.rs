@@ -0,0 +1,210 @@
+use chrono::{Duration, Local};
+use lockchain_core::errors::{Error, VaultError};
+use lockchain_core::query::Query;
+use lockchain_core::store::Locate;
+use lockchain_core::testing::{Fixture, TestBody};
+use lockchain_core::traits::Vault;
+use lockchain_core::{Generator, Payload, VaultType};
+use lockchain_files::{FileStore, FileVault};
+use lockchain_sqlite::SqliteVault;
+use rusqlite::Connection;
+
+fn names(vault: &SqliteVault<TestBody>, query: &Query) -> Vec<String> {
+    vault
+        .query(query)
+        .into_iter()
+        .map(|h| h.name.clone())
+        .collect()
+}
+
+#[test]
+fn queries_run_in_sql() {
+    let fix = Fixture::new();
+    let mut vault: SqliteVault<TestBody> = fix.create();
+    vault.add_record("ssh", "server", vec!["work"]).unwrap();
+    vault
+        .add_record("mail", "web", vec!["personal", "work"])
+        .unwrap();
+    vault.add_record("bank", "web", vec!["personal"]).unwrap();
+    vault.sync().unwrap();
+
+    let vault: SqliteVault<TestBody> = fix.reopen();
+    assert_eq!(
+        names(&vault, &Query::new().category("web")),
+        vec!["bank", "mail"]
+    );
+    assert_eq!(
+        names(&vault, &Query::new().all_tags(&["personal", "work"])),
+        vec!["mail"]
+    );
+    assert_eq!(
+        names(&vault, &Query::new().any_tag(&[])),
+        Vec::<String>::new()
+    );
+
+    let now = Local::now();
+    assert_eq!(names(&vault, &Query::new().created(..now)).len(), 3);
+    assert!(names(&vault, &Query::new().updated(now + Duration::hours(1)..)).is_empty());
+
+    /* Bounds beyond what nanosecond timestamps can hold */
+    let far = Duration::days(500 * 365);
+    assert_eq!(names(&vault, &Query::new().created(..now + far)).len(), 3);
+    assert_eq!(names(&vault, &Query::new().created(now - far..)).len(), 3);
+}
+
+#[test]
+fn queries_see_unsynced_changes() {
+    let fix = Fixture::new();
+    let mut vault: SqliteVault<TestBody> = fix.create();
+    vault.add_record("ssh", "server", vec!["work"]).unwrap();
+    vault.add_record("mail", "web", vec![]).unwrap();
+    vault.sync().unwrap();
+
+    vault.add_record("bank", "web", vec![]).unwrap();
+    vault.delete_record("mail").unwrap();
+    assert_eq!(names(&vault, &Query::new().category("web")), vec!["bank"]);
+
+    vault.add_data("ssh", "port", Payload::Number(22)).unwrap();
+    vault.rename_record("ssh", "openssh").unwrap();
+    assert_eq!(
+        names(&vault, &Query::new().any_tag(&["work"])),
+        vec!["openssh"]
+    );
+}
+
+#[test]
+fn sync_is_transactional() {
+    let fix = Fixture::new();
+    let mut vault: SqliteVault<TestBody> = fix.create();
+    vault.add_record("ssh", "server", vec![]).unwrap();
+    vault.sync().unwrap();
+
+    /* Break the database behind the back of the vault */
+    let path = SqliteVault::<TestBody>::path(&fix.name, &fix.location);
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch("DROP TABLE tags")
+        .unwrap();
+
+    vault.add_record("mail", "web", vec!["personal"]).unwrap();
+    vault.add_record("bank", "web", vec![]).unwrap();
+    vault.delete_record("ssh").unwrap();
+    match vault.sync().as_ref().map_err(Error::kind) {
+        Err(Error::Vault(VaultError::FailedSync)) => {}
+        _ => panic!("Syncing into a broken database must fail"),
+    }
+
+    /* None of the changes were written */
+    let vault: SqliteVault<TestBody> = fix.reopen();
+    assert!(vault.contains("ssh"));
+    assert!(!vault.contains("mail"));
+    assert!(!vault.contains("bank"));
+}
+
+#[test]
+fn names_are_unique() {
+    let fix = Fixture::new();
+    let mut vault: SqliteVault<TestBody> = fix.create();
+    vault.add_record("ssh", "server", vec![]).unwrap();
+    vault.add_record("mail", "web", vec![]).unwrap();
+    vault.sync().unwrap();
+
+    /* Swapping names within one sync is fine */
+    vault.rename_record("ssh", "tmp").unwrap();
+    vault.rename_record("mail", "ssh").unwrap();
+    vault.rename_record("tmp", "mail").unwrap();
+    vault.sync().unwrap();
+
+    let mut other: SqliteVault<TestBody> = fix.reopen();
+    assert_eq!(names(&other, &Query::new().category("web")), vec!["ssh"]);
+
+    /* Two handles can't both add a record of the same name */
+    vault.add_record("bank", "web", vec![]).unwrap();
+    other.add_record("bank", "finance", vec![]).unwrap();
+    vault.sync().unwrap();
+    match other.sync().as_ref().map_err(Error::kind) {
+        Err(Error::Vault(VaultError::RecordExists)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn import_and_export_file_vaults() {
+    let fix = Fixture::new();
+    let id = {
+        let mut vault: FileVault<TestBody> = Generator::new()
+            .path("files", fix.location.as_str())
+            .user_type(VaultType::SoloUser {
+                username: "alice".into(),
+                secret: b"secret".to_vec(),
+            })
+            .finalise()
+            .unwrap();
+        vault.add_record("github", "web", vec!["dev"]).unwrap();
+        vault
+            .add_data("github", "user", Payload::Text("alice".into()))
+            .unwrap();
+        vault.meta_add_domain("settings").unwrap();
+        vault.sync().unwrap();
+        vault.get_record("github").unwrap().header.id
+    };
+
+    let mut vault: SqliteVault<TestBody> = fix.create();
+    {
+        let store = FileStore::open("files", &fix.location).unwrap();
+        assert_eq!(vault.import(&store).unwrap(), 1);
+    }
+    vault.pull("github").unwrap();
+    assert_eq!(vault.get_record("github").unwrap().header.id, id);
+    assert_eq!(
+        vault.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    assert!(vault.meta_exists("settings"));
+    vault.authenticate("alice", "secret").unwrap();
+
+    vault.add_record("gitlab", "web", vec![]).unwrap();
+    {
+        let gen = Generator::new()
+            .path("exported", fix.location.as_str())
+            .user_type(VaultType::SoloUser {
+                username: "alice".into(),
+                secret: vec![],
+            });
+        let mut store = FileStore::create(&gen).unwrap();
+        assert_eq!(vault.export(&mut store).unwrap(), 2);
+    }
+
+    {
+        let mut files: FileVault<TestBody> = *FileVault::load("exported", &fix.location).unwrap();
+        assert!(files.contains("gitlab"));
+        assert!(files.meta_exists("settings"));
+        files.pull("github").unwrap();
+        assert_eq!(
+            files.get_data("github", "user"),
+            Some(&Payload::Text("alice".into()))
+        );
+        files.authenticate("alice", "secret").unwrap();
+    }
+
+    /* Exporting again replaces the headers of the same records */
+    vault.rename_record("github", "hub").unwrap();
+    {
+        let mut store = FileStore::open("exported", &fix.location).unwrap();
+        assert_eq!(vault.export(&mut store).unwrap(), 2);
+    }
+    let files: FileVault<TestBody> = *FileVault::load("exported", &fix.location).unwrap();
+    assert!(files.contains("hub"));
+    assert!(!files.contains("github"));
+    drop(files);
+
+    /* ...and removes deleted records */
+    vault.delete_record("gitlab").unwrap();
+    {
+        let mut store = FileStore::open("exported", &fix.location).unwrap();
+        assert_eq!(vault.export(&mut store).unwrap(), 1);
+    }
+    let files: FileVault<TestBody> = *FileVault::load("exported", &fix.location).unwrap();
+    assert!(files.contains("hub"));
+    assert!(!files.contains("gitlab"));
+}
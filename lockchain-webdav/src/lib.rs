@@ -0,0 +1,86 @@
+//! A WebDAV storage backend for lockchain vaults
+//!
+//! `WebDavStore` keeps a vault on a WebDAV share, in the same layout
+//! that `lockchain-files` uses on disk: a `<name>.vault` collection
+//! with a `vault.cfg`, `records`, `metadata` and `checksums`. A share
+//! that is also synced to a local folder (i.e. by a Nextcloud client)
+//! can be opened as a `FileVault` there. `WebDavVault` puts the generic
+//! `StoreVault` from `lockchain-core` on top of it, which implements
+//! the `Vault` API.
+//!
+//! Writes are conditional on the ETag a file had when it was read.
+//! If another client changed a file in the meantime, `sync` fails
+//! with `VaultError::Conflict`, and `fetch` brings the vault up to
+//! date again. The server has to send ETags and honour `If-Match`
+//! and `If-None-Match`, which all common servers do.
+
+extern crate lockchain_core as lcc;
+extern crate lockchain_files as files;
+
+use crate::lcc::errors::{Error as LockError, VaultError};
+use crate::lcc::store::StoreVault;
+use std::env;
+
+mod store;
+
+pub use crate::store::WebDavStore;
+
+/// A vault on a WebDAV share
+pub type WebDavVault<T> = StoreVault<WebDavStore, T>;
+
+/// Where to find a share, and how to log into it
+#[derive(Debug, Clone)]
+pub struct WebDavConfig {
+    /// The URL of the collection vaults are stored in, i.e.
+    /// `https://cloud.example.com/remote.php/dav/files/alice/vaults`
+    pub url: String,
+    /// Username and password for HTTP basic authentication
+    pub login: Option<(String, String)>,
+}
+
+impl WebDavConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            login: None,
+        }
+    }
+
+    /// Log in with a username and password
+    pub fn login(self, username: &str, password: &str) -> Self {
+        Self {
+            login: Some((username.into(), password.into())),
+            ..self
+        }
+    }
+
+    /// Configure a share from it's URL and the environment
+    ///
+    /// The location is the URL of the collection that holds vaults.
+    /// A login is read from `LOCKCHAIN_WEBDAV_USER` and
+    /// `LOCKCHAIN_WEBDAV_PASSWORD`, if the former is set.
+    ///
+    /// This is what `WebDavVault::new` and `WebDavVault::load` use.
+    pub fn from_env(location: &str) -> Result<Self, LockError> {
+        let host = location
+            .strip_prefix("https://")
+            .or_else(|| location.strip_prefix("http://"))
+            .and_then(|rest| rest.split('/').next());
+        if host.map(str::is_empty).unwrap_or(true) {
+            return Err(LockError::from(VaultError::InvalidPath)
+                .context(format!("location '{}'", location)));
+        }
+
+        let config = Self::new(location);
+        match env::var("LOCKCHAIN_WEBDAV_USER") {
+            Ok(user) => {
+                let password = env::var("LOCKCHAIN_WEBDAV_PASSWORD").map_err(|_| {
+                    LockError::from(VaultError::FailedLoading)
+                        .context("variable 'LOCKCHAIN_WEBDAV_PASSWORD'")
+                })?;
+                Ok(config.login(&user, &password))
+            }
+            Err(_) => Ok(config),
+        }
+    }
+}
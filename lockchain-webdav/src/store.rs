@@ -0,0 +1,379 @@
+//! A `BlobStore` on top of a WebDAV share
+
+use crate::files::fs::{self, FileType};
+use crate::files::VaultConfig;
+use crate::lcc::errors::{DataError, Error as LockError, VaultError};
+use crate::lcc::store::http::{Reply, RESERVED};
+use crate::lcc::store::{Blob, BlobStore, Condition, Locate, Revision, Space};
+use crate::lcc::Generator;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use roxmltree::Node;
+use std::io::Read;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Response};
+
+use crate::WebDavConfig;
+
+const CONFIG: &str = "vault.cfg";
+
+const PROPFIND: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+    <d:propfind xmlns:d=\"DAV:\"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>";
+
+/// Stores blobs as files on a WebDAV share, for use with a `StoreVault`
+///
+/// Files are laid out like a `FileVault` folder:
+///
+/// - `<name>.vault/records/<id>.record`
+/// - `<name>.vault/metadata/<domain>.meta`, including `userstore.meta`
+/// - `<name>.vault/headers.index`
+///
+/// Every file gets a checksum in `checksums/`, like `lockchain-files`
+/// writes them, so that a `FileVault` doesn't reject the files as
+/// corrupted. ETags are used as revisions, and sent along with
+/// writes as `If-Match` (or `If-None-Match: *` for new files).
+///
+/// WebDAV has no transactions: a failed `sync` can leave some files
+/// written. The header index is written last, so other handles
+/// notice that something changed. Keys must be valid file names.
+pub struct WebDavStore {
+    config: WebDavConfig,
+    /// The URL of the vault collection, with a trailing `/`
+    root: String,
+    agent: Agent,
+}
+
+impl WebDavStore {
+    /// Set up a new vault collection on a share
+    ///
+    /// Creates the collections of the vault and writes it's
+    /// `vault.cfg`. Fails if the vault collection already exists.
+    pub fn scaffold(config: WebDavConfig, gen: &Generator) -> Result<Self, LockError> {
+        let name = gen.name.as_ref().ok_or(VaultError::IncompleteGenerator)?;
+        let vault_type = gen
+            .user_type
+            .as_ref()
+            .ok_or(VaultError::IncompleteGenerator)?;
+        let store = Self::new(config, name);
+
+        for dir in &["", "records/", "metadata/", "checksums/"] {
+            let url = format!("{}{}", store.root, dir);
+            match store.send("MKCOL", &url, &[], None)? {
+                Reply::Ok(_) => {}
+                Reply::Status(405) => {
+                    return Err(status(VaultError::FailedCreation, 405, &url)
+                        .context("the collection already exists"))
+                }
+                Reply::Status(code) => return Err(status(VaultError::FailedCreation, code, &url)),
+            }
+        }
+
+        let cfg = VaultConfig::new(vault_type, gen.codec.unwrap_or_default())?
+            .to_bytes()
+            .map_err(|e| LockError::from(VaultError::FailedCreation).caused_by(e))?;
+        let url = format!("{}{}", store.root, CONFIG);
+        match store.send("PUT", &url, &[("If-None-Match", "*")], Some(&cfg))? {
+            Reply::Ok(_) => Ok(store),
+            Reply::Status(code) => Err(status(VaultError::FailedCreation, code, &url)),
+        }
+    }
+
+    /// Connect to an existing vault collection on a share
+    ///
+    /// Checks the `vault.cfg` like `FileVault::load` does.
+    pub fn connect(config: WebDavConfig, name: &str) -> Result<Self, LockError> {
+        let store = Self::new(config, name);
+        let url = format!("{}{}", store.root, CONFIG);
+        let cfg = match store.send("GET", &url, &[], None)? {
+            Reply::Ok(res) => read(*res, &url)?,
+            Reply::Status(404) => return Err(status(VaultError::InvalidPath, 404, &url)),
+            Reply::Status(code) => return Err(status(VaultError::FailedLoading, code, &url)),
+        };
+
+        VaultConfig::from_bytes(&cfg)
+            .map(|_| store)
+            .map_err(|e| VaultError::from(e).into())
+    }
+
+    fn new(config: WebDavConfig, name: &str) -> Self {
+        let root = format!(
+            "{}/{}/",
+            config.url,
+            utf8_percent_encode(&format!("{}.vault", name), RESERVED)
+        );
+        Self {
+            config,
+            root,
+            agent: AgentBuilder::new().timeout(Duration::from_secs(60)).build(),
+        }
+    }
+
+    /// The configuration this store talks to
+    pub fn config(&self) -> &WebDavConfig {
+        &self.config
+    }
+
+    /// The URL of the vault collection
+    pub fn url(&self) -> &str {
+        &self.root
+    }
+
+    /// The URL of the file a blob is stored in
+    fn file(&self, space: Space, key: &str) -> Result<String, LockError> {
+        if !fs::valid_name(key) {
+            return Err(LockError::from(VaultError::InvalidName).context(format!("key '{}'", key)));
+        }
+        Ok(format!(
+            "{}{}{}",
+            self.root,
+            collection(space),
+            utf8_percent_encode(&fs::file_name(space.into(), key), RESERVED)
+        ))
+    }
+
+    /// The URL of the checksum of a blob
+    fn checksum(&self, space: Space, key: &str) -> String {
+        format!(
+            "{}checksums/{}",
+            self.root,
+            utf8_percent_encode(&fs::sum_file(space.into(), key), RESERVED)
+        )
+    }
+
+    /// Send a request, with a login if one is configured
+    fn send(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<Reply, LockError> {
+        let mut req = self.agent.request(method, url);
+        if body.is_some() {
+            req = req.set("Content-Type", "application/octet-stream");
+        }
+        if let Some((ref user, ref password)) = self.config.login {
+            let login = BASE64.encode(format!("{}:{}", user, password));
+            req = req.set("Authorization", &format!("Basic {}", login));
+        }
+        for (k, v) in headers {
+            req = req.set(k, v);
+        }
+
+        let res = match body {
+            Some(body) => req.send_bytes(body),
+            None => req.call(),
+        };
+        Reply::new(res, method, url)
+    }
+
+    /// The current ETag of a file, if it exists
+    fn head(&self, url: &str) -> Result<Option<Revision>, LockError> {
+        match self.send("HEAD", url, &[], None)? {
+            Reply::Ok(res) => etag(&res, url).map(Some),
+            Reply::Status(404) => Ok(None),
+            Reply::Status(code) => Err(status(DataError::FailedRead, code, url)),
+        }
+    }
+}
+
+impl BlobStore for WebDavStore {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        let url = self.file(space, key)?;
+        match self.send("GET", &url, &[], None)? {
+            Reply::Ok(res) => {
+                let revision = etag(&res, &url)?;
+                let data = read(*res, &url)?;
+                Ok(Some(Blob { data, revision }))
+            }
+            Reply::Status(404) => Ok(None),
+            Reply::Status(code) => Err(status(DataError::FailedRead, code, &url)),
+        }
+    }
+
+    /// Writes the file, then it's checksum
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        let url = self.file(space, key)?;
+        let headers: Vec<(&str, &str)> = match condition {
+            Condition::Any => vec![],
+            Condition::Absent => vec![("If-None-Match", "*")],
+            Condition::Matches(rev) => vec![("If-Match", rev.as_str())],
+        };
+
+        let revision = match self.send("PUT", &url, &headers, Some(&data))? {
+            /* Not every server returns the new ETag of a file */
+            Reply::Ok(res) => match etag(&res, &url) {
+                Ok(etag) => etag,
+                Err(_) => self
+                    .head(&url)?
+                    .ok_or_else(|| status(VaultError::FailedSync, 404, &url))?,
+            },
+            Reply::Status(412) => return Err(Condition::failed(space, key)),
+            Reply::Status(code) => return Err(status(VaultError::FailedSync, code, &url)),
+        };
+
+        let sum = self.checksum(space, key);
+        match self.send("PUT", &sum, &[], Some(fs::checksum(&data).as_bytes()))? {
+            Reply::Ok(_) => Ok(revision),
+            Reply::Status(code) => Err(status(VaultError::FailedSync, code, &sum)),
+        }
+    }
+
+    /// Removes the file, then it's checksum
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        let url = self.file(space, key)?;
+        let headers = match condition {
+            Condition::Any => vec![],
+            Condition::Absent => {
+                return match self.head(&url)? {
+                    Some(_) => Err(Condition::failed(space, key)),
+                    None => Ok(()),
+                };
+            }
+            Condition::Matches(rev) => vec![("If-Match", rev.as_str())],
+        };
+
+        match self.send("DELETE", &url, &headers, None)? {
+            Reply::Ok(_) => {}
+            Reply::Status(404) if *condition == Condition::Any => {}
+            Reply::Status(404) | Reply::Status(412) => return Err(Condition::failed(space, key)),
+            Reply::Status(code) => return Err(status(VaultError::FailedSync, code, &url)),
+        }
+
+        let sum = self.checksum(space, key);
+        match self.send("DELETE", &sum, &[], None)? {
+            Reply::Ok(_) | Reply::Status(404) => Ok(()),
+            Reply::Status(code) => Err(status(VaultError::FailedSync, code, &sum)),
+        }
+    }
+
+    /// Lists the files of a space with a single `PROPFIND`
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        let url = format!("{}{}", self.root, collection(space));
+        let res = match self.send(
+            "PROPFIND",
+            &url,
+            &[("Depth", "1"), ("Content-Type", "application/xml")],
+            Some(PROPFIND.as_bytes()),
+        )? {
+            Reply::Ok(res) => res,
+            Reply::Status(404) => return Ok(vec![]),
+            Reply::Status(code) => return Err(status(DataError::FailedRead, code, &url)),
+        };
+
+        let body = read(*res, &url)?;
+        let ending = format!(".{}", FileType::from(space).ending());
+        let files = parse_multistatus(&String::from_utf8_lossy(&body)).map_err(|e| {
+            LockError::from(DataError::FailedDecode).context(format!("PROPFIND {}: {}", url, e))
+        })?;
+
+        Ok(files
+            .into_iter()
+            .filter_map(|(name, etag)| {
+                let key = name.strip_suffix(&ending)?;
+                if fs::valid_name(key) {
+                    Some((key.to_owned(), etag))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+impl Locate for WebDavStore {
+    /// The location is the URL of a collection, see `WebDavConfig::from_env`
+    fn create(gen: &Generator) -> Result<Self, LockError> {
+        let location = gen
+            .location
+            .as_ref()
+            .ok_or(VaultError::IncompleteGenerator)?;
+        Self::scaffold(WebDavConfig::from_env(location)?, gen)
+    }
+
+    /// The location is the URL of a collection, see `WebDavConfig::from_env`
+    fn open(name: &str, location: &str) -> Result<Self, LockError> {
+        Self::connect(WebDavConfig::from_env(location)?, name)
+    }
+}
+
+/// The names and ETags of all files in a `207 Multi-Status` response
+///
+/// Collections, and files without an ETag, are skipped.
+fn parse_multistatus(xml: &str) -> Result<Vec<(String, Revision)>, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+    let mut files = Vec::new();
+    for response in doc.root_element().children().filter(|c| dav(c, "response")) {
+        let href = match child(response, "href").and_then(|h| h.text()) {
+            Some(href) if !href.ends_with('/') => href,
+            _ => continue,
+        };
+        let name = href.rsplit('/').next().unwrap_or(href);
+        let name = percent_decode_str(name).decode_utf8_lossy().into_owned();
+
+        let props = response
+            .children()
+            .filter(|c| dav(c, "propstat"))
+            .filter(|p| {
+                child(*p, "status")
+                    .and_then(|s| s.text())
+                    .map(|s| s.contains(" 200 "))
+                    .unwrap_or(false)
+            })
+            .filter_map(|p| child(p, "prop"));
+        for prop in props {
+            let collection = child(prop, "resourcetype")
+                .map(|t| child(t, "collection").is_some())
+                .unwrap_or(false);
+            match child(prop, "getetag").and_then(|e| e.text()) {
+                Some(etag) if !collection => files.push((name.clone(), etag.trim().to_owned())),
+                _ => {}
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether an element is `<name>` from the `DAV:` namespace
+fn dav(node: &Node, name: &str) -> bool {
+    node.tag_name().namespace() == Some("DAV:") && node.tag_name().name() == name
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|c| dav(c, name))
+}
+
+/// The collection of a space, relative to the vault collection
+fn collection(space: Space) -> &'static str {
+    match space {
+        Space::Records => "records/",
+        Space::Metadata => "metadata/",
+        Space::Index => "",
+    }
+}
+
+fn etag(res: &Response, url: &str) -> Result<Revision, LockError> {
+    res.header("ETag")
+        .map(|e| e.to_owned())
+        .ok_or_else(|| status(DataError::FailedRead, res.status(), url).context("missing ETag"))
+}
+
+fn read(res: Response, url: &str) -> Result<Vec<u8>, LockError> {
+    let code = res.status();
+    let mut data = Vec::new();
+    res.into_reader()
+        .read_to_end(&mut data)
+        .map(|_| data)
+        .map_err(|e| status(DataError::FailedRead, code, url).caused_by(e))
+}
+
+fn status<E: Into<LockError>>(error: E, code: u16, url: &str) -> LockError {
+    error.into().context(format!("HTTP {} for '{}'", code, url))
+}
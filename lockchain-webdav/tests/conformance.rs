@@ -0,0 +1,47 @@
+mod mock;
+
+use lockchain_core::errors::Error as LockError;
+use lockchain_core::store::{Blob, BlobStore, Condition, Locate, Revision, Space, StoreVault};
+use lockchain_core::{testing::TestBody, vault_conformance, Generator};
+use lockchain_webdav::WebDavStore;
+
+/// A `WebDavStore` that finds it's vault on a local server
+struct Mocked(WebDavStore);
+
+impl BlobStore for Mocked {
+    fn get(&self, space: Space, key: &str) -> Result<Option<Blob>, LockError> {
+        self.0.get(space, key)
+    }
+
+    fn put(
+        &mut self,
+        space: Space,
+        key: &str,
+        data: Vec<u8>,
+        condition: &Condition,
+    ) -> Result<Revision, LockError> {
+        self.0.put(space, key, data, condition)
+    }
+
+    fn delete(&mut self, space: Space, key: &str, condition: &Condition) -> Result<(), LockError> {
+        self.0.delete(space, key, condition)
+    }
+
+    fn list(&self, space: Space) -> Result<Vec<(String, Revision)>, LockError> {
+        self.0.list(space)
+    }
+}
+
+impl Locate for Mocked {
+    /// The fixture directory is served over WebDAV
+    fn create(gen: &Generator) -> Result<Self, LockError> {
+        let config = mock::config(gen.location.as_ref().unwrap());
+        WebDavStore::scaffold(config, gen).map(Mocked)
+    }
+
+    fn open(name: &str, location: &str) -> Result<Self, LockError> {
+        WebDavStore::connect(mock::config(location), name).map(Mocked)
+    }
+}
+
+vault_conformance!(webdav_store, StoreVault<Mocked, TestBody>);
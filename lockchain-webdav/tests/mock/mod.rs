@@ -0,0 +1,174 @@
+//! A small WebDAV server that serves a directory
+//!
+//! Supports what `WebDavStore` uses: `GET`, `HEAD`, `PUT`, `DELETE`,
+//! `MKCOL` and `PROPFIND` with a depth of one. ETags are checksums
+//! of file contents, so changes made directly on disk (i.e. by a
+//! `FileVault`) are noticed. Requests have to log in as `USER`.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use lockchain_core::crypto::hashing;
+use lockchain_webdav::WebDavConfig;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use tiny_http::{Header, Request, Response, Server};
+
+pub const USER: &str = "alice";
+pub const PASSWORD: &str = "hunter2";
+
+const PATH: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'%').add(b'<').add(b'>');
+
+/// The URL of a server for a directory, starting one if needed
+pub fn serve(root: &str) -> String {
+    static SERVERS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    let mut servers = SERVERS.get_or_init(Default::default).lock().unwrap();
+    servers
+        .entry(root.to_owned())
+        .or_insert_with(|| {
+            let server = Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/dav", server.server_addr().to_ip().unwrap());
+            let root = PathBuf::from(root);
+            thread::spawn(move || {
+                for req in server.incoming_requests() {
+                    handle(&root, req);
+                }
+            });
+            url
+        })
+        .clone()
+}
+
+/// A configuration for the server of a directory, with a login
+pub fn config(root: &str) -> WebDavConfig {
+    WebDavConfig::new(&serve(root)).login(USER, PASSWORD)
+}
+
+fn handle(root: &Path, mut req: Request) {
+    let login = format!("Basic {}", BASE64.encode(format!("{}:{}", USER, PASSWORD)));
+    if header(&req, "Authorization").as_deref() != Some(login.as_str()) {
+        return reply(req, 401, vec![], None);
+    }
+
+    let url = req.url().to_owned();
+    let rel = match url.strip_prefix("/dav") {
+        Some(rel) => percent_decode_str(rel).decode_utf8_lossy().into_owned(),
+        None => return reply(req, 404, vec![], None),
+    };
+    if rel.split('/').any(|s| s == "..") {
+        return reply(req, 403, vec![], None);
+    }
+    let path = root.join(rel.trim_matches('/'));
+
+    let current = fs::read(&path).ok().map(|data| etag(&data));
+    let failed = match (header(&req, "If-Match"), header(&req, "If-None-Match")) {
+        (Some(etag), _) => current.as_ref() != Some(&etag),
+        (_, Some(_)) => current.is_some(),
+        _ => false,
+    };
+    let mut body = Vec::new();
+    req.as_reader().read_to_end(&mut body).unwrap();
+
+    match req.method().as_str() {
+        "GET" | "HEAD" => match fs::read(&path) {
+            Ok(data) if path.is_file() => {
+                let etag = etag(&data);
+                let data = if req.method().as_str() == "GET" {
+                    data
+                } else {
+                    vec![]
+                };
+                reply(req, 200, data, Some(etag))
+            }
+            _ => reply(req, 404, vec![], None),
+        },
+        "PUT" | "DELETE" if failed => reply(req, 412, vec![], None),
+        "PUT" if !path.parent().map(Path::is_dir).unwrap_or(false) => reply(req, 409, vec![], None),
+        "PUT" => {
+            let etag = etag(&body);
+            fs::write(&path, body).unwrap();
+            let code = if current.is_some() { 204 } else { 201 };
+            reply(req, code, vec![], Some(etag))
+        }
+        "DELETE" if path.is_dir() => {
+            fs::remove_dir_all(&path).unwrap();
+            reply(req, 204, vec![], None)
+        }
+        "DELETE" => match fs::remove_file(&path) {
+            Ok(()) => reply(req, 204, vec![], None),
+            Err(_) => reply(req, 404, vec![], None),
+        },
+        "MKCOL" if path.exists() => reply(req, 405, vec![], None),
+        "MKCOL" => match fs::create_dir(&path) {
+            Ok(()) => reply(req, 201, vec![], None),
+            Err(_) => reply(req, 409, vec![], None),
+        },
+        "PROPFIND" if !path.exists() => reply(req, 404, vec![], None),
+        "PROPFIND" => {
+            let listing = propfind(&path, &url);
+            reply(req, 207, listing.into_bytes(), None)
+        }
+        _ => reply(req, 405, vec![], None),
+    }
+}
+
+/// A `Multi-Status` response for a resource and it's children
+fn propfind(path: &Path, url: &str) -> String {
+    let mut xml =
+        String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?><d:multistatus xmlns:d=\"DAV:\">");
+    let mut entry = |path: &Path, href: &str| {
+        let props = if path.is_dir() {
+            "<d:resourcetype><d:collection/></d:resourcetype>".to_owned()
+        } else {
+            let etag = etag(&fs::read(path).unwrap());
+            format!(
+                "<d:resourcetype/><d:getetag>{}</d:getetag>",
+                etag.replace('"', "&quot;")
+            )
+        };
+        xml.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>{}</d:prop>\
+             <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            href, props
+        ));
+    };
+
+    entry(path, url);
+    if path.is_dir() {
+        let base = url.trim_end_matches('/');
+        for child in fs::read_dir(path).unwrap().filter_map(|e| e.ok()) {
+            let name = child.file_name().into_string().unwrap();
+            let slash = if child.path().is_dir() { "/" } else { "" };
+            let href = format!("{}/{}{}", base, utf8_percent_encode(&name, PATH), slash);
+            entry(&child.path(), &href);
+        }
+    }
+    xml + "</d:multistatus>"
+}
+
+/// Strong ETags, from the contents of a file
+fn etag(data: &[u8]) -> String {
+    let sum: String = hashing::checksum(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("\"{}\"", sum)
+}
+
+fn header(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str().to_owned())
+}
+
+fn reply(req: Request, code: u16, data: Vec<u8>, etag: Option<String>) {
+    let mut res = Response::from_data(data).with_status_code(code);
+    if let Some(etag) = etag {
+        res = res.with_header(Header::from_bytes("ETag", etag).unwrap());
+    }
+    let _ = req.respond(res);
+}
@@ -0,0 +1,136 @@
+mod mock;
+
+use lockchain_core::errors::{Error, VaultError};
+use lockchain_core::testing::{Fixture, TestBody};
+use lockchain_core::traits::Vault;
+use lockchain_core::Payload;
+use lockchain_files::{fs as files, FileVault};
+use lockchain_webdav::{WebDavConfig, WebDavStore, WebDavVault};
+use std::fs;
+use std::path::Path;
+
+fn is_conflict(e: &Error) -> bool {
+    matches!(e.kind(), Error::Vault(VaultError::Conflict))
+}
+
+fn create(fix: &Fixture) -> WebDavVault<TestBody> {
+    let store = WebDavStore::scaffold(mock::config(&fix.location), &fix.generator()).unwrap();
+    WebDavVault::create(store, fix.generator()).unwrap()
+}
+
+fn connect(fix: &Fixture) -> WebDavVault<TestBody> {
+    let store = WebDavStore::connect(mock::config(&fix.location), &fix.name).unwrap();
+    WebDavVault::open(store, &fix.name, &fix.location).unwrap()
+}
+
+#[test]
+fn share_a_folder_with_file_vaults() {
+    let fix = Fixture::new();
+    let mut dav = create(&fix);
+    dav.add_record("github", "web", vec!["dev"]).unwrap();
+    dav.add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    dav.meta_add_domain("settings").unwrap();
+    dav.sync().unwrap();
+
+    let vault_dir = Path::new(&fix.location).join("conformance.vault");
+    assert!(vault_dir.join("vault.cfg").is_file());
+    assert!(vault_dir.join("headers.index").is_file());
+    assert!(vault_dir.join("metadata/settings.meta").is_file());
+    assert_eq!(
+        fs::read_to_string(vault_dir.join("checksums/settings.meta.sum")).unwrap(),
+        files::checksum(&fs::read(vault_dir.join("metadata/settings.meta")).unwrap())
+    );
+
+    {
+        let mut vault: FileVault<TestBody> = fix.reopen();
+        assert!(vault.meta_exists("settings"));
+        vault.pull("github").unwrap();
+        assert_eq!(
+            vault.get_data("github", "user"),
+            Some(&Payload::Text("alice".into()))
+        );
+        vault.add_record("gitlab", "web", vec![]).unwrap();
+        vault.sync().unwrap();
+    }
+
+    dav.fetch().unwrap();
+    assert!(dav.contains("gitlab"));
+    dav.delete_record("github").unwrap();
+    dav.sync().unwrap();
+
+    let vault: FileVault<TestBody> = fix.reopen();
+    assert!(!vault.contains("github"));
+    assert!(vault.contains("gitlab"));
+}
+
+#[test]
+fn detect_concurrent_writers() {
+    let fix = Fixture::new();
+    let mut first = create(&fix);
+    first.add_record("github", "web", vec![]).unwrap();
+    first.sync().unwrap();
+
+    let mut second = connect(&fix);
+    second.pull("github").unwrap();
+    first
+        .add_data("github", "user", Payload::Text("alice".into()))
+        .unwrap();
+    first.sync().unwrap();
+
+    second
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    assert!(is_conflict(&second.sync().unwrap_err()));
+
+    /* After a fetch, the change of the first writer is visible */
+    second.fetch().unwrap();
+    second.pull("github").unwrap();
+    assert_eq!(
+        second.get_data("github", "user"),
+        Some(&Payload::Text("alice".into()))
+    );
+    second
+        .add_data("github", "user", Payload::Text("bob".into()))
+        .unwrap();
+    second.sync().unwrap();
+}
+
+#[test]
+fn refuse_existing_vaults() {
+    let fix = Fixture::new();
+    let _vault = create(&fix);
+
+    match WebDavStore::scaffold(mock::config(&fix.location), &fix.generator()) {
+        Err(ref e) if matches!(e.kind(), Error::Vault(VaultError::FailedCreation)) => {}
+        _ => panic!("The vault collection already exists"),
+    }
+}
+
+#[test]
+fn require_a_login() {
+    let fix = Fixture::new();
+    let _vault = create(&fix);
+
+    let config = WebDavConfig::new(&mock::serve(&fix.location));
+    assert!(WebDavStore::connect(config.clone(), &fix.name).is_err());
+    let config = config.login(mock::USER, "wrong");
+    assert!(WebDavStore::connect(config, &fix.name).is_err());
+}
+
+#[test]
+fn locations() {
+    assert_eq!(
+        WebDavConfig::new("https://cloud.example.com/dav/").url,
+        "https://cloud.example.com/dav"
+    );
+    for location in &["/tmp/vaults", "ftp://example.com/vaults", "https:///vaults"] {
+        match WebDavConfig::from_env(location)
+            .as_ref()
+            .map_err(Error::kind)
+        {
+            Err(Error::Vault(VaultError::InvalidPath)) => {}
+            _ => panic!("'{}' is not the URL of a collection", location),
+        }
+    }
+}